@@ -1,6 +1,6 @@
-use crate::base::RouteableComponent;
-
-use crate::route::longpull::LongPollRoute;
+use crate::base::{RouteableComponent, UpdaterComponent};
+use crate::config::registry::{RouteConfig, UpdateConfig};
+use crate::route::filter::{FilterEdit, FilterLists};
 
 use std::sync::Arc;
 
@@ -9,15 +9,43 @@ use tokio::sync::oneshot::Sender;
 use serde_json::Value;
 
 
-pub enum AddRouteType {
-    Longpull(Arc<LongPollRoute>),
-    Webhook(Arc<dyn RouteableComponent>),
+pub enum UpdaterAction {
+    Pause,
+    Resume,
+    Remove,
 }
 
 pub enum ApiMessage {
     AddRoute {
-        route: AddRouteType,
-        sublevel: i8
+        route: Arc<dyn RouteableComponent>,
+        /// What `route` was built from, for `GET /api/config`.
+        config: RouteConfig,
+        response: Sender<Result<(), String>>
+    },
+    GetRoutes(Sender<Value>),
+    GetConfig(Sender<Result<String, String>>),
+    GetUpdates(Sender<Value>),
+    AddUpdate {
+        updater: Arc<dyn UpdaterComponent>,
+        /// What `updater` was built from, for `GET /api/config`.
+        config: UpdateConfig,
+        response: Sender<Result<usize, String>>
+    },
+    ManageUpdate {
+        id: usize,
+        action: UpdaterAction,
+        response: Sender<Result<(), String>>
     },
-    GetRoutes(Sender<Value>)
-}
\ No newline at end of file
+    GetFlood(Sender<Option<Value>>),
+    GetOrdering(Sender<Option<Value>>),
+    SetShares {
+        name: String,
+        shares: Vec<f64>,
+        response: Sender<Result<Vec<f64>, String>>
+    },
+    EditFilter {
+        name: String,
+        edit: FilterEdit,
+        response: Sender<Result<FilterLists, String>>
+    },
+}
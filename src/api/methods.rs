@@ -1,53 +1,251 @@
 
-use axum::{http, extract::State, Json, response::IntoResponse};
-use serde_json::Value;
+use axum::{http, extract::{Path, State}, Json, response::{IntoResponse, Response}};
+use serde_json::{json, Map, Value};
 use tokio::sync::mpsc::Sender;
 use tokio::sync::oneshot;
-use tokio;
+
+use crate::api::schemas::{AddRoute, AddUpdate, EditFilter, SetShares};
+use crate::api::message::{ApiMessage, UpdaterAction};
+use crate::base::UpdaterComponent;
+
+use crate::config::registry::{RouteConfig, UpdateConfig};
+use crate::config::setup::{build_route, has_env_vars, substitute_env_vars_in};
+
 use std::sync::Arc;
 
-use crate::api::schemas::{AddRoute, RouteType};
-use crate::api::message::{ApiMessage, AddRouteType};
 
-use crate::route::webhook::WebhookRoute;
-use crate::route::longpull::LongPollRoute;
+fn api_error(status: http::StatusCode, description: impl Into<String>) -> Response {
+    (
+        status,
+        Json(json!({
+            "ok": false,
+            "error_code": status.as_u16(),
+            "description": description.into()
+        }))
+    ).into_response()
+}
+
+
 
 
 
 
 
+/// Options that hold secrets. Components added through the API have to take
+/// them from `${VAR}` placeholders, so they stay out of the config file and
+/// `GET /config`.
+const SECRET_OPTIONS: &[&str] = &["token", "secret_token", "signing_secret", "proxy_password", "headers"];
+
+/// Options of a component added through the API: one copy to build it from
+/// with the placeholders substituted, one to keep for `GET /config` with the
+/// placeholders in place.
+fn component_options(options: Map<String, Value>) -> Result<(Value, Value), String> {
+    let kept = Value::Object(options);
+    check_secrets(&kept)?;
+    let mut options = kept.clone();
+    substitute_env_vars_in(&mut options)?;
+    Ok((options, kept))
+}
+
+fn check_secrets(options: &Value) -> Result<(), String> {
+    match options {
+        Value::Object(map) => map.iter().try_for_each(|(name, value)| {
+            if SECRET_OPTIONS.contains(&name.as_str()) { check_secret(name, value) } else { check_secrets(value) }
+        }),
+        Value::Array(values) => values.iter().try_for_each(check_secrets),
+        _ => Ok(()),
+    }
+}
+
+fn check_secret(name: &str, value: &Value) -> Result<(), String> {
+    match value {
+        Value::Null => Ok(()),
+        Value::String(secret) if has_env_vars(secret) => Ok(()),
+        // `headers`, every value counts
+        Value::Object(values) => values.values().try_for_each(|value| check_secret(name, value)),
+        _ => Err(format!("{} has to come from an environment variable, e.g. \"${{{}}}\"", name, name.to_uppercase())),
+    }
+}
 
+pub async fn add_route(State(tx): State<Sender<ApiMessage>>, Json(data): Json<AddRoute>) -> Response {
+    let kind = data.registered_kind().to_string();
+    let (config, kept) = match component_options(data.options)
+        .and_then(|(options, kept)| Ok((RouteConfig::from_options(&kind, options)?, RouteConfig::from_options(&kind, kept)?))) {
+        Ok(configs) => configs,
+        Err(description) => return api_error(http::StatusCode::BAD_REQUEST, description),
+    };
 
-pub async fn add_route(State(tx): State<Sender<ApiMessage>>, Json(data): Json<AddRoute>)  {
-    let route = match data.typee {
-        RouteType::Longpull(route) => {
-            let update = LongPollRoute::new(route.path);
-            AddRouteType::Longpull(Arc::new(update))
-        },
-        RouteType::Webhook(route) => {
-            let update = WebhookRoute::new(route.url);
-            AddRouteType::Webhook(Arc::new(update))
-        }
+    let route = match build_route(config) {
+        Ok(route) => route,
+        Err(description) => return api_error(http::StatusCode::BAD_REQUEST, description),
     };
 
+    let (tx_response, rx_response) = oneshot::channel();
+
     let _ = tx.send(ApiMessage::AddRoute{
-        sublevel: data.sublevel,
-        route
+        route,
+        config: kept,
+        response: tx_response,
         }).await;
 
+    match rx_response.await {
+        Ok(Ok(())) => Json(json!({ "ok": true })).into_response(),
+        Ok(Err(description)) => api_error(http::StatusCode::BAD_REQUEST, description),
+        Err(_) => api_error(http::StatusCode::INTERNAL_SERVER_ERROR, "api channel closed"),
+    }
 }
 
 
 
-pub async fn get_routes(State(tx): State<Sender<ApiMessage>>) -> Result<Json<Value>, impl IntoResponse> {
+pub async fn set_shares(State(tx): State<Sender<ApiMessage>>, Json(data): Json<SetShares>) -> Response {
+    let (tx_response, rx_response) = oneshot::channel();
+
+    let _ = tx.send(ApiMessage::SetShares {
+        name: data.name,
+        shares: data.shares,
+        response: tx_response,
+    }).await;
+
+    match rx_response.await {
+        Ok(Ok(shares)) => Json(json!({ "ok": true, "result": shares })).into_response(),
+        Ok(Err(description)) => api_error(http::StatusCode::BAD_REQUEST, description),
+        Err(_) => api_error(http::StatusCode::INTERNAL_SERVER_ERROR, "api channel closed"),
+    }
+}
+
+
+
+pub async fn get_routes(State(tx): State<Sender<ApiMessage>>) -> Response {
     let (tx_response, rx_response) = oneshot::channel();
 
     let _ = tx.send(ApiMessage::GetRoutes(tx_response)).await;
 
     match rx_response.await {
-        Ok(json) => Ok(Json::from(json)),
-        Err(_) => Err(
-            http::StatusCode::INTERNAL_SERVER_ERROR
-        ),
+        Ok(json) => Json::from(json).into_response(),
+        Err(_) => api_error(http::StatusCode::INTERNAL_SERVER_ERROR, "api channel closed"),
+    }
+}
+
+
+pub async fn get_config(State(tx): State<Sender<ApiMessage>>) -> Response {
+    let (tx_response, rx_response) = oneshot::channel();
+
+    let _ = tx.send(ApiMessage::GetConfig(tx_response)).await;
+
+    match rx_response.await {
+        Ok(Ok(config)) => ([(http::header::CONTENT_TYPE, "application/ron; charset=utf-8")], config).into_response(),
+        Ok(Err(description)) => api_error(http::StatusCode::BAD_REQUEST, description),
+        Err(_) => api_error(http::StatusCode::INTERNAL_SERVER_ERROR, "api channel closed"),
+    }
+}
+
+
+pub async fn edit_filter(State(tx): State<Sender<ApiMessage>>, Json(data): Json<EditFilter>) -> Response {
+    let (tx_response, rx_response) = oneshot::channel();
+
+    let _ = tx.send(ApiMessage::EditFilter {
+        name: data.name,
+        edit: data.edit,
+        response: tx_response,
+    }).await;
+
+    match rx_response.await {
+        Ok(Ok(lists)) => Json(json!({ "ok": true, "result": lists })).into_response(),
+        Ok(Err(description)) => api_error(http::StatusCode::BAD_REQUEST, description),
+        Err(_) => api_error(http::StatusCode::INTERNAL_SERVER_ERROR, "api channel closed"),
+    }
+}
+
+
+pub async fn get_flood(State(tx): State<Sender<ApiMessage>>) -> Response {
+    let (tx_response, rx_response) = oneshot::channel();
+
+    let _ = tx.send(ApiMessage::GetFlood(tx_response)).await;
+
+    match rx_response.await {
+        Ok(Some(report)) => Json(json!({ "ok": true, "result": report })).into_response(),
+        Ok(None) => api_error(http::StatusCode::NOT_FOUND, "flood control is not enabled"),
+        Err(_) => api_error(http::StatusCode::INTERNAL_SERVER_ERROR, "api channel closed"),
+    }
+}
+
+
+pub async fn get_ordering(State(tx): State<Sender<ApiMessage>>) -> Response {
+    let (tx_response, rx_response) = oneshot::channel();
+
+    let _ = tx.send(ApiMessage::GetOrdering(tx_response)).await;
+
+    match rx_response.await {
+        Ok(Some(report)) => Json(json!({ "ok": true, "result": report })).into_response(),
+        Ok(None) => api_error(http::StatusCode::NOT_FOUND, "ordering is not enabled"),
+        Err(_) => api_error(http::StatusCode::INTERNAL_SERVER_ERROR, "api channel closed"),
+    }
+}
+
+
+pub async fn get_updates(State(tx): State<Sender<ApiMessage>>) -> Response {
+    let (tx_response, rx_response) = oneshot::channel();
+
+    let _ = tx.send(ApiMessage::GetUpdates(tx_response)).await;
+
+    match rx_response.await {
+        Ok(updates) => Json(json!({ "ok": true, "result": updates })).into_response(),
+        Err(_) => api_error(http::StatusCode::INTERNAL_SERVER_ERROR, "api channel closed"),
     }
-}
\ No newline at end of file
+}
+
+
+pub async fn add_update(State(tx): State<Sender<ApiMessage>>, Json(data): Json<AddUpdate>) -> Response {
+    let (config, kept) = match component_options(data.options)
+        .and_then(|(options, kept)| Ok((UpdateConfig::from_options(&data.kind, options)?, UpdateConfig::from_options(&data.kind, kept)?))) {
+        Ok(configs) => configs,
+        Err(description) => return api_error(http::StatusCode::BAD_REQUEST, description),
+    };
+    let updater: Arc<dyn UpdaterComponent> = match config.spec.build() {
+        Ok(updater) => Arc::from(updater),
+        Err(description) => return api_error(http::StatusCode::BAD_REQUEST, description),
+    };
+
+    let (tx_response, rx_response) = oneshot::channel();
+
+    let _ = tx.send(ApiMessage::AddUpdate {
+        updater,
+        config: kept,
+        response: tx_response,
+    }).await;
+
+    match rx_response.await {
+        Ok(Ok(id)) => Json(json!({ "ok": true, "result": { "id": id } })).into_response(),
+        Ok(Err(description)) => api_error(http::StatusCode::BAD_REQUEST, description),
+        Err(_) => api_error(http::StatusCode::INTERNAL_SERVER_ERROR, "api channel closed"),
+    }
+}
+
+
+async fn manage_update(tx: Sender<ApiMessage>, id: usize, action: UpdaterAction) -> Response {
+    let (tx_response, rx_response) = oneshot::channel();
+
+    let _ = tx.send(ApiMessage::ManageUpdate {
+        id,
+        action,
+        response: tx_response,
+    }).await;
+
+    match rx_response.await {
+        Ok(Ok(())) => Json(json!({ "ok": true })).into_response(),
+        Ok(Err(description)) => api_error(http::StatusCode::BAD_REQUEST, description),
+        Err(_) => api_error(http::StatusCode::INTERNAL_SERVER_ERROR, "api channel closed"),
+    }
+}
+
+pub async fn pause_update(State(tx): State<Sender<ApiMessage>>, Path(id): Path<usize>) -> Response {
+    manage_update(tx, id, UpdaterAction::Pause).await
+}
+
+pub async fn resume_update(State(tx): State<Sender<ApiMessage>>, Path(id): Path<usize>) -> Response {
+    manage_update(tx, id, UpdaterAction::Resume).await
+}
+
+pub async fn remove_update(State(tx): State<Sender<ApiMessage>>, Path(id): Path<usize>) -> Response {
+    manage_update(tx, id, UpdaterAction::Remove).await
+}
@@ -1,4 +1,4 @@
-use axum::{Router, routing::{post, get}};
+use axum::{Router, routing::{post, get, put, patch, delete}};
 use serde_json::{Value};
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::{Sender, Receiver};
@@ -13,6 +13,13 @@ use async_trait::async_trait;
 
 
 
+/// Everything `set_server` serves under the base path.
+const ROUTES: &[&str] = &[
+    "/routes", "/config", "/route", "/updates", "/update", "/update/:id",
+    "/update/:id/pause", "/update/:id/resume", "/split", "/flood", "/ordering", "/filter",
+];
+
+
 pub struct Api {
     base_path: String,
     tx: Sender<ApiMessage>,
@@ -42,13 +49,27 @@ impl Serverable for Api {
     async fn set_server(&self, main_router: Router<Sender<Value>>) -> Router<Sender<Value>> {
         let router = Router::new()
             .route("/routes", get(methods::get_routes))
+            .route("/config", get(methods::get_config))
             .route("/route", post(methods::add_route))
+            .route("/updates", get(methods::get_updates))
+            .route("/update", post(methods::add_update))
+            .route("/update/:id", delete(methods::remove_update))
+            .route("/update/:id/pause", post(methods::pause_update))
+            .route("/update/:id/resume", post(methods::resume_update))
+            .route("/split", put(methods::set_shares))
+            .route("/flood", get(methods::get_flood))
+            .route("/ordering", get(methods::get_ordering))
+            .route("/filter", patch(methods::edit_filter))
             .with_state(self.tx.clone());
 
 
         main_router.nest(&self.base_path, router)
 
     }
+
+    async fn paths(&self) -> Vec<String> {
+        ROUTES.iter().map(|route| format!("{}{}", self.base_path, route)).collect()
+    }
 }
 
 
@@ -1,34 +1,45 @@
 use serde::Deserialize;
+use serde_json::{Map, Value};
 
-fn default_sublevel() -> i8 {
-    0
-}
-
+use crate::route::filter::FilterEdit;
 
 #[derive(Deserialize, Debug)]
-pub struct AddWebhookRoute {
-    #[serde(default)]
-    pub url: String,
+pub struct AddRoute {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(flatten)]
+    pub options: Map<String, Value>,
 }
 
-
 #[derive(Deserialize, Debug)]
-pub struct AddLongpullRoute {
-    #[serde(default)]
-    pub path: String,
+pub struct AddUpdate {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(flatten)]
+    pub options: Map<String, Value>,
 }
 
 #[derive(Deserialize, Debug)]
-#[serde(tag = "type")] 
-pub enum RouteType {
-    Webhook(AddWebhookRoute),
-    Longpull(AddLongpullRoute),
+pub struct SetShares {
+    pub name: String,
+    pub shares: Vec<f64>,
 }
 
 #[derive(Deserialize, Debug)]
-pub struct AddRoute {
+pub struct EditFilter {
+    pub name: String,
     #[serde(flatten)]
-    pub typee: RouteType,
-    #[serde(default = "default_sublevel")]
-    pub sublevel: i8
-}
\ No newline at end of file
+    pub edit: FilterEdit,
+}
+
+impl AddRoute {
+    // `Webhook` and `Longpull` were accepted before routes were resolved
+    // through the component registry, keep them working for existing clients.
+    pub fn registered_kind(&self) -> &str {
+        match self.kind.as_str() {
+            "Webhook" => "WebhookRoute",
+            "Longpull" => "LongPollRoute",
+            kind => kind,
+        }
+    }
+}
@@ -2,30 +2,108 @@
 use async_trait::async_trait;
 use serde_json::{Value, json};
 
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::{Sender, UnboundedSender};
+use tokio::sync::RwLock;
 
 use axum::Router;
 
 use crate::update::base::Updater;
 
-use crate::api::message::AddRouteType;
 
+/// How often one update may be handed on to a sibling. Without a cap two
+/// routes nobody polls would pass their updates back and forth forever.
+pub const MAX_REDELIVERIES: u32 = 3;
+
+/// An update handed back to a balancer: the index of the child it came from,
+/// the update and how often it was redelivered, this time included.
+pub type Redelivered = (usize, Value, u32);
+
+/// Handle a balancer gives its children so they can return updates they could
+/// not get rid of, e.g. a `LongPollRoute` whose consumer stopped polling.
+#[derive(Clone)]
+pub struct Redelivery {
+    tx: UnboundedSender<Redelivered>,
+    index: usize,
+    siblings: Weak<RwLock<Vec<Arc<dyn RouteableComponent>>>>,
+}
+
+impl Redelivery {
+    pub fn new(tx: UnboundedSender<Redelivered>, index: usize, siblings: Weak<RwLock<Vec<Arc<dyn RouteableComponent>>>>) -> Self {
+        Self { tx, index, siblings }
+    }
+
+    /// Whether a sibling could take updates right now. Updates stay with the
+    /// child while there is none.
+    pub async fn has_healthy_sibling(&self) -> bool {
+        let Some(siblings) = self.siblings.upgrade() else {
+            return false;
+        };
+        let siblings = siblings.read().await;
+        for (index, sibling) in siblings.iter().enumerate() {
+            if index != self.index && sibling.is_healthy().await {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Hands the update back to the balancer, or returns it if the balancer is gone.
+    /// `hops` counts this redelivery.
+    pub fn send(&self, update: Value, hops: u32) -> Result<(), Value> {
+        self.tx.send((self.index, update, hops)).map_err(|e| e.0 .1)
+    }
+}
 
 #[async_trait]
 pub trait Routeable: Send + Sync {
     async fn process(&self, update: Value);
 
-    async fn add_route(&self, route: AddRouteType) -> Result<(), ()>{
+    /// Like `process`, but hands the update back when it could not be delivered
+    /// so the caller can try another route.
+    async fn try_process(&self, update: Value) -> Result<(), Value> {
+        self.process(update).await;
+        Ok(())
+    }
+
+    /// Updates that belong together, e.g. the parts of an album. Balancers send
+    /// the whole batch to one child.
+    async fn process_batch(&self, updates: Vec<Value>) {
+        for update in updates {
+            self.process(update).await;
+        }
+    }
+
+    /// Whether the route can take updates right now.
+    async fn is_healthy(&self) -> bool {
+        true
+    }
+
+    async fn add_route(&self, _route: Arc<dyn RouteableComponent>) -> Result<(), ()>{
         Err(())
     }
+
+    /// Called by a parent balancer, see `Redelivery`.
+    fn set_redelivery(&self, _redelivery: Redelivery) {}
+
+    /// Takes an update a sibling gave back, `hops` times redelivered so far.
+    /// Routes that give updates back keep the count so they stop at `MAX_REDELIVERIES`.
+    async fn process_redelivered(&self, update: Value, _hops: u32) {
+        self.process(update).await;
+    }
 }
 #[async_trait]
 pub trait Serverable {
     async fn set_server(&self, server: Router<Sender<Value>>) -> Router<Sender<Value>> {
         server
     }
+
+    /// Paths `set_server` serves, checked against the ones already taken
+    /// before the component is added at runtime.
+    async fn paths(&self) -> Vec<String> {
+        Vec::new()
+    }
 }
 #[async_trait]
 pub trait Printable {
@@ -0,0 +1,440 @@
+use crate::config::registry::{RouteConfig, UpdateConfig};
+use crate::config::schema::TginConfig;
+use crate::config::setup::substitute_env_vars;
+use crate::route::filter::FilterLists;
+
+use ron::ser::PrettyConfig;
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::future::Future;
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+
+/// A change made through the API to a named component.
+#[derive(Debug, Clone)]
+pub enum ConfigEdit {
+    /// New shares of a `SplitLB`.
+    Shares { name: String, shares: Vec<f64> },
+    /// New lists of a `FilterRoute`.
+    Filter { name: String, lists: FilterLists },
+    /// An update provider paused or resumed, by its id in `/api/updates`.
+    UpdatePaused { id: usize, paused: bool },
+    /// An update provider removed, by its id in `/api/updates`.
+    UpdateRemoved { id: usize },
+}
+
+/// The configuration a running instance was started from, kept in sync with
+/// the changes made through the API so it can be written back as RON.
+pub struct ConfigDocument {
+    path: PathBuf,
+    config: TginConfig,
+    /// Values of the file that had `${VAR}` placeholders in them, by where
+    /// they are in the tree: what they were loaded as and how they were written.
+    placeholders: BTreeMap<Vec<String>, (Scalar, String)>,
+    /// Ids of the providers in `updates`, in the same order.
+    update_ids: Vec<usize>,
+    persist: bool,
+    /// Counts `write_back` calls, `written` holds the last one on disk.
+    generation: u64,
+    written: Arc<Mutex<u64>>,
+}
+
+impl ConfigDocument {
+    pub fn load(path: &str) -> Self {
+        let content = fs::read_to_string(path).expect("Failed to read config file");
+        Self::parse(path, &content)
+    }
+
+    /// `content` as if it was read from `path`.
+    pub fn parse(path: impl Into<PathBuf>, content: &str) -> Self {
+        let config = ron::from_str(&substitute_env_vars(content)).expect("Failed to parse RON config");
+        let mut document = Self::new(path, config);
+
+        for (position, range) in values(content) {
+            let token = &content[range];
+            if !token.contains("${") {
+                continue;
+            }
+            if let Some(value) = Scalar::parse(&substitute_env_vars(token)) {
+                document.placeholders.insert(position, (value, token.to_string()));
+            }
+        }
+        document
+    }
+
+    /// The providers of `config` are expected to have the ids `0..`, in order.
+    pub fn new(path: impl Into<PathBuf>, config: TginConfig) -> Self {
+        Self {
+            path: path.into(),
+            update_ids: (0..config.updates.len()).collect(),
+            config,
+            placeholders: BTreeMap::new(),
+            persist: false,
+            generation: 0,
+            written: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    /// Rewrite the file on `write_back`.
+    pub fn set_persist(&mut self, persist: bool) {
+        self.persist = persist;
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Same as `Routeable::add_route` on the root route.
+    pub fn add_route(&mut self, route: RouteConfig) -> Result<(), String> {
+        let kind = self.config.route.kind.clone();
+        self.config.route.spec.add_route(route)
+            .map_err(|route| format!("{} does not accept {}", kind, route.kind))
+    }
+
+    /// A provider added through the API, `id` is the one it was given.
+    pub fn add_update(&mut self, id: usize, update: UpdateConfig) {
+        self.config.updates.push(update);
+        self.update_ids.push(id);
+    }
+
+    /// The configuration as it is now, `rollback` goes back to it.
+    pub fn checkpoint(&self) -> Result<String, String> {
+        self.serialize()
+    }
+
+    pub fn rollback(&mut self, checkpoint: &str) -> Result<(), String> {
+        self.config = ron::from_str(checkpoint).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Applies `edit` to the component it names, in the route tree or in the
+    /// flood control quarantine, or to the provider with its id.
+    pub fn edit(&mut self, edit: &ConfigEdit) -> bool {
+        match edit {
+            ConfigEdit::UpdatePaused { id, paused } => return self.pause_update(*id, *paused),
+            ConfigEdit::UpdateRemoved { id } => return self.remove_update(*id),
+            _ => {}
+        }
+        if edit_route(&mut self.config.route, edit) {
+            return true;
+        }
+        match self.config.flood_control.as_mut().and_then(|flood| flood.quarantine.as_mut()) {
+            Some(quarantine) => edit_route(quarantine, edit),
+            None => false,
+        }
+    }
+
+    fn pause_update(&mut self, id: usize, paused: bool) -> bool {
+        let Some(index) = self.update_ids.iter().position(|update| *update == id) else {
+            return false;
+        };
+        let paused_updates = &mut self.config.paused_updates;
+        paused_updates.retain(|update| *update != index);
+        if paused {
+            paused_updates.push(index);
+            paused_updates.sort_unstable();
+        }
+        true
+    }
+
+    fn remove_update(&mut self, id: usize) -> bool {
+        let Some(index) = self.update_ids.iter().position(|update| *update == id) else {
+            return false;
+        };
+        self.config.updates.remove(index);
+        self.update_ids.remove(index);
+        self.placeholders = std::mem::take(&mut self.placeholders).into_iter()
+            .filter_map(|(mut position, value)| {
+                // `updates.<index>...`, the providers after the removed one move up
+                if position.first().is_some_and(|field| field == "updates") {
+                    match position.get(1).and_then(|update| update.parse::<usize>().ok()) {
+                        Some(update) if update == index => return None,
+                        Some(update) if update > index => position[1] = (update - 1).to_string(),
+                        _ => {}
+                    }
+                }
+                Some((position, value))
+            })
+            .collect();
+        // the providers after it move up by one
+        self.config.paused_updates.retain(|update| *update != index);
+        for update in &mut self.config.paused_updates {
+            if *update > index {
+                *update -= 1;
+            }
+        }
+        true
+    }
+
+    /// The configuration as RON, with the values that came from environment
+    /// variables replaced by their `${VAR}` placeholders again.
+    pub fn to_ron(&self) -> Result<String, String> {
+        Ok(self.restore_placeholders(&self.serialize()?))
+    }
+
+    fn serialize(&self) -> Result<String, String> {
+        ron::ser::to_string_pretty(&self.config, PrettyConfig::new().struct_names(true)).map_err(|e| e.to_string())
+    }
+
+    /// Rewrites the file when persisting is enabled, on a blocking thread once
+    /// the returned future is polled. The new content is written and synced
+    /// next to the file and renamed over it, so a crash never leaves half a
+    /// config. Of writes that overlap, the latest one wins.
+    pub fn write_back(&mut self) -> impl Future<Output = Result<(), String>> + Send + 'static {
+        let content = self.persist.then(|| self.to_ron());
+        self.generation += 1;
+        let generation = self.generation;
+        let written = self.written.clone();
+        let path = self.path.clone();
+
+        async move {
+            let Some(content) = content else {
+                return Ok(());
+            };
+            let content = content?;
+            tokio::task::spawn_blocking(move || {
+                let mut written = written.lock().unwrap();
+                if *written > generation {
+                    return Ok(());
+                }
+                replace_file(&path, &content)?;
+                *written = generation;
+                Ok(())
+            }).await.map_err(|e| e.to_string())?
+        }
+    }
+
+    /// A value is put back only where it was in the file and only while it
+    /// still holds what was substituted, equal values elsewhere stay as they are.
+    fn restore_placeholders(&self, content: &str) -> String {
+        if self.placeholders.is_empty() {
+            return content.to_string();
+        }
+        let mut restored = String::with_capacity(content.len());
+        let mut last = 0;
+        for (position, range) in values(content) {
+            let Some((value, original)) = self.placeholders.get(&position) else {
+                continue;
+            };
+            if Scalar::parse(&content[range.clone()]).as_ref() != Some(value) {
+                continue;
+            }
+            restored.push_str(&content[last..range.start]);
+            restored.push_str(original);
+            last = range.end;
+        }
+        restored.push_str(&content[last..]);
+        restored
+    }
+}
+
+/// A string or a bare value (number, identifier, ...) as it was parsed, so
+/// the same string compares equal however it was escaped.
+#[derive(PartialEq, Eq)]
+enum Scalar {
+    Str(String),
+    Bare(String),
+}
+
+impl Scalar {
+    fn parse(token: &str) -> Option<Self> {
+        if token.starts_with('"') || token.starts_with('r') && token[1..].trim_start_matches('#').starts_with('"') {
+            ron::from_str(token).ok().map(Scalar::Str)
+        } else {
+            Some(Scalar::Bare(token.to_string()))
+        }
+    }
+
+    fn into_string(self) -> String {
+        match self {
+            Scalar::Str(value) | Scalar::Bare(value) => value,
+        }
+    }
+}
+
+enum Token {
+    Punct(u8),
+    Value(Range<usize>),
+}
+
+/// The strings and bare values of RON `content` with their position in the
+/// tree: field names, list indices and map keys from the top, struct and
+/// variant names left out, so `Some(x)` puts `"0"` in front of `x`.
+fn values(content: &str) -> Vec<(Vec<String>, Range<usize>)> {
+    let mut walker = Walker { content, tokens: tokens(content), at: 0, values: Vec::new() };
+    while walker.at < walker.tokens.len() {
+        walker.value(&mut Vec::new());
+    }
+    walker.values
+}
+
+struct Walker<'a> {
+    content: &'a str,
+    tokens: Vec<Token>,
+    at: usize,
+    values: Vec<(Vec<String>, Range<usize>)>,
+}
+
+impl Walker<'_> {
+    fn next_is(&self, punct: u8) -> bool {
+        matches!(self.tokens.get(self.at), Some(Token::Punct(p)) if *p == punct)
+    }
+
+    /// Steps over separators, false once `close` or the end is reached.
+    fn more(&mut self, close: u8) -> bool {
+        while self.next_is(b',') {
+            self.at += 1;
+        }
+        if self.next_is(close) {
+            self.at += 1;
+            return false;
+        }
+        self.at < self.tokens.len()
+    }
+
+    /// Always consumes at least one token.
+    fn value(&mut self, position: &mut Vec<String>) {
+        let at = self.at;
+        self.at += 1;
+        match &self.tokens[at] {
+            Token::Punct(b'(') => self.fields(position),
+            Token::Punct(b'[') => self.list(position),
+            Token::Punct(b'{') => self.map(position),
+            Token::Punct(_) => {}
+            Token::Value(_) if self.next_is(b'(') => {
+                self.at += 1;
+                self.fields(position);
+            }
+            Token::Value(range) => self.values.push((position.clone(), range.clone())),
+        }
+    }
+
+    /// `name: value, ...` of a struct or the values of a tuple, after the `(`.
+    fn fields(&mut self, position: &mut Vec<String>) {
+        let mut index = 0;
+        while self.more(b')') {
+            let field = match (self.tokens.get(self.at), self.tokens.get(self.at + 1)) {
+                (Some(Token::Value(name)), Some(Token::Punct(b':'))) => {
+                    let name = self.content[name.clone()].to_string();
+                    self.at += 2;
+                    name
+                }
+                _ => {
+                    index += 1;
+                    (index - 1).to_string()
+                }
+            };
+            position.push(field);
+            self.value(position);
+            position.pop();
+        }
+    }
+
+    fn list(&mut self, position: &mut Vec<String>) {
+        let mut index = 0;
+        while self.more(b']') {
+            position.push(index.to_string());
+            self.value(position);
+            position.pop();
+            index += 1;
+        }
+    }
+
+    fn map(&mut self, position: &mut Vec<String>) {
+        while self.more(b'}') {
+            let key = match &self.tokens[self.at] {
+                Token::Value(key) => Scalar::parse(&self.content[key.clone()]).map(Scalar::into_string).unwrap_or_default(),
+                Token::Punct(_) => String::new(),
+            };
+            self.at += 1;
+            if self.next_is(b':') {
+                self.at += 1;
+            }
+            position.push(key);
+            self.value(position);
+            position.pop();
+        }
+    }
+}
+
+/// The strings, bare values and punctuation of RON `content`, leaving out
+/// whitespace and comments.
+fn tokens(content: &str) -> Vec<Token> {
+    let bytes = content.as_bytes();
+    let is_delimiter = |b: u8| b.is_ascii_whitespace() || b"()[]{},:\"".contains(&b);
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+        match bytes[i] {
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                i = (i + 1).min(bytes.len());
+                tokens.push(Token::Value(start..i));
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = content[i..].find('\n').map_or(bytes.len(), |end| i + end);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = content[i + 2..].find("*/").map_or(bytes.len(), |end| i + 2 + end + 2);
+            }
+            b if b.is_ascii_whitespace() => i += 1,
+            b if is_delimiter(b) => {
+                tokens.push(Token::Punct(b));
+                i += 1;
+            }
+            b'r' if bytes[i + 1..].iter().find(|b| **b != b'#') == Some(&b'"') => {
+                // raw string, closed by a quote and as many hashes as it opened with
+                let hashes = bytes[i + 1..].iter().take_while(|b| **b == b'#').count();
+                let body = i + 1 + hashes + 1;
+                let closing = format!("\"{}", "#".repeat(hashes));
+                i = content[body..].find(&closing).map_or(bytes.len(), |end| body + end + closing.len());
+                tokens.push(Token::Value(start..i));
+            }
+            _ => {
+                while i < bytes.len() && !is_delimiter(bytes[i]) {
+                    i += 1;
+                    // the braces of a placeholder are part of the value
+                    if bytes[i - 1] == b'$' && bytes.get(i) == Some(&b'{') {
+                        i = content[i..].find('}').map_or(bytes.len(), |end| i + end + 1);
+                    }
+                }
+                tokens.push(Token::Value(start..i));
+            }
+        }
+    }
+    tokens
+}
+
+fn replace_file(path: &Path, content: &str) -> Result<(), String> {
+    let mut tmp = path.to_path_buf().into_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let mut file = File::create(&tmp).map_err(|e| format!("failed to create {}: {}", tmp.display(), e))?;
+    file.write_all(content.as_bytes())
+        .and_then(|()| file.sync_all())
+        .map_err(|e| format!("failed to write {}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("failed to replace {}: {}", path.display(), e))?;
+
+    // the rename only survives a crash once the directory is synced too
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .map_err(|e| format!("failed to sync {}: {}", dir.display(), e))
+}
+
+fn edit_route(route: &mut RouteConfig, edit: &ConfigEdit) -> bool {
+    route.spec.edit(edit) || route.spec.routes_mut().into_iter().any(|route| edit_route(route, edit))
+}
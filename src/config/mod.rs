@@ -1,2 +1,4 @@
 pub mod schema;
-pub mod setup;
\ No newline at end of file
+pub mod setup;
+pub mod registry;
+pub mod document;
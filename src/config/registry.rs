@@ -0,0 +1,425 @@
+use crate::base::{RouteableComponent, UpdaterComponent};
+use crate::config::document::ConfigEdit;
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, MapAccess, VariantAccess, Visitor};
+use serde::de::value::MapAccessDeserializer;
+use serde::ser::{self, Impossible, SerializeStruct, SerializeStructVariant, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use once_cell::sync::Lazy;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::{Arc, RwLock};
+
+
+pub trait RouteSpec: fmt::Debug + Send + Sync {
+    /// Fails on options that only turn out invalid when the component is built,
+    /// e.g. a malformed header or an unreadable file.
+    fn build(self: Box<Self>) -> Result<Arc<dyn RouteableComponent>, String>;
+
+    /// Options written back by `GET /api/config`, usually `Some(self)`.
+    /// A component returning `None` cannot be exported.
+    fn serialized(&self) -> Option<&dyn erased_serde::Serialize> {
+        None
+    }
+
+    /// Nested routes, searched for the component a `ConfigEdit` names.
+    fn routes_mut(&mut self) -> Vec<&mut RouteConfig> {
+        Vec::new()
+    }
+
+    /// Mirrors `Routeable::add_route` of the built component.
+    fn add_route(&mut self, route: RouteConfig) -> Result<(), RouteConfig> {
+        Err(route)
+    }
+
+    /// Applies `edit` when it targets this component.
+    fn edit(&mut self, _edit: &ConfigEdit) -> bool {
+        false
+    }
+}
+
+pub trait UpdateSpec: fmt::Debug + Send + Sync {
+    /// See `RouteSpec::build`.
+    fn build(self: Box<Self>) -> Result<Box<dyn UpdaterComponent>, String>;
+
+    /// See `RouteSpec::serialized`.
+    fn serialized(&self) -> Option<&dyn erased_serde::Serialize> {
+        None
+    }
+}
+
+
+type SpecFactory<S> = fn(&mut dyn erased_serde::Deserializer) -> Result<Box<S>, erased_serde::Error>;
+
+fn route_factory<C>(de: &mut dyn erased_serde::Deserializer) -> Result<Box<dyn RouteSpec>, erased_serde::Error>
+where
+    C: RouteSpec + DeserializeOwned + 'static,
+{
+    Ok(Box::new(erased_serde::deserialize::<C>(de)?))
+}
+
+fn update_factory<C>(de: &mut dyn erased_serde::Deserializer) -> Result<Box<dyn UpdateSpec>, erased_serde::Error>
+where
+    C: UpdateSpec + DeserializeOwned + 'static,
+{
+    Ok(Box::new(erased_serde::deserialize::<C>(de)?))
+}
+
+
+pub struct ComponentRegistry {
+    routes: HashMap<String, SpecFactory<dyn RouteSpec>>,
+    updates: HashMap<String, SpecFactory<dyn UpdateSpec>>,
+}
+
+impl ComponentRegistry {
+    pub fn new() -> Self {
+        Self {
+            routes: HashMap::new(),
+            updates: HashMap::new(),
+        }
+    }
+
+    pub fn register_route<C>(&mut self, name: &str)
+    where
+        C: RouteSpec + DeserializeOwned + 'static,
+    {
+        self.routes.insert(name.to_string(), route_factory::<C>);
+    }
+
+    pub fn register_update<C>(&mut self, name: &str)
+    where
+        C: UpdateSpec + DeserializeOwned + 'static,
+    {
+        self.updates.insert(name.to_string(), update_factory::<C>);
+    }
+
+    pub fn route_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.routes.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn update_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.updates.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+impl Default for ComponentRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+pub static COMPONENT_REGISTRY: Lazy<RwLock<ComponentRegistry>> = Lazy::new(|| {
+    let mut registry = ComponentRegistry::new();
+    crate::config::setup::register_builtins(&mut registry);
+    RwLock::new(registry)
+});
+
+pub fn register_route<C>(name: &str)
+where
+    C: RouteSpec + DeserializeOwned + 'static,
+{
+    COMPONENT_REGISTRY.write().expect("Registry lock poisoned").register_route::<C>(name);
+}
+
+pub fn register_update<C>(name: &str)
+where
+    C: UpdateSpec + DeserializeOwned + 'static,
+{
+    COMPONENT_REGISTRY.write().expect("Registry lock poisoned").register_update::<C>(name);
+}
+
+// The factory is copied out so the lock is not held while nested components
+// (e.g. the routes of a load balancer) resolve through the registry themselves.
+fn route_factory_for(kind: &str) -> Result<SpecFactory<dyn RouteSpec>, String> {
+    let registry = COMPONENT_REGISTRY.read().expect("Registry lock poisoned");
+    registry.routes.get(kind).copied().ok_or_else(|| {
+        format!("unknown route `{}`, expected one of: {}", kind, registry.route_names().join(", "))
+    })
+}
+
+fn update_factory_for(kind: &str) -> Result<SpecFactory<dyn UpdateSpec>, String> {
+    let registry = COMPONENT_REGISTRY.read().expect("Registry lock poisoned");
+    registry.updates.get(kind).copied().ok_or_else(|| {
+        format!("unknown update `{}`, expected one of: {}", kind, registry.update_names().join(", "))
+    })
+}
+
+
+pub struct RouteConfig {
+    pub kind: String,
+    pub spec: Box<dyn RouteSpec>,
+}
+
+impl RouteConfig {
+    pub fn from_options(kind: &str, options: Value) -> Result<Self, String> {
+        let factory = route_factory_for(kind)?;
+        let spec = factory(&mut <dyn erased_serde::Deserializer>::erase(options))
+            .map_err(|e| e.to_string())?;
+        Ok(Self { kind: kind.to_string(), spec })
+    }
+}
+
+impl fmt::Debug for RouteConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:?}", self.kind, self.spec)
+    }
+}
+
+pub struct UpdateConfig {
+    pub kind: String,
+    pub spec: Box<dyn UpdateSpec>,
+}
+
+impl UpdateConfig {
+    pub fn from_options(kind: &str, options: Value) -> Result<Self, String> {
+        let factory = update_factory_for(kind)?;
+        let spec = factory(&mut <dyn erased_serde::Deserializer>::erase(options))
+            .map_err(|e| e.to_string())?;
+        Ok(Self { kind: kind.to_string(), spec })
+    }
+}
+
+impl fmt::Debug for UpdateConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:?}", self.kind, self.spec)
+    }
+}
+
+
+// Components are written as enum variants, `Name(field: value, ...)` in RON and
+// `{"Name": {...}}` in JSON. The variant name selects the registered factory,
+// which then deserializes its own options from the variant body.
+struct ComponentVisitor<T: ?Sized> {
+    expecting: &'static str,
+    resolve: fn(&str) -> Result<SpecFactory<T>, String>,
+    _marker: PhantomData<T>,
+}
+
+impl<'de, T: ?Sized> Visitor<'de> for ComponentVisitor<T> {
+    type Value = (String, Box<T>);
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.expecting)
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let (kind, variant) = data.variant_seed(KindSeed)?;
+        let factory = (self.resolve)(&kind).map_err(de::Error::custom)?;
+
+        let spec = variant.struct_variant(&[], OptionsVisitor { factory })?;
+        Ok((kind, spec))
+    }
+}
+
+// RON only hands out variant names through `deserialize_identifier`.
+struct KindSeed;
+
+impl<'de> DeserializeSeed<'de> for KindSeed {
+    type Value = String;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+impl<'de> Visitor<'de> for KindSeed {
+    type Value = String;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("component name")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        Ok(value.to_string())
+    }
+}
+
+struct OptionsVisitor<T: ?Sized> {
+    factory: SpecFactory<T>,
+}
+
+impl<'de, T: ?Sized> Visitor<'de> for OptionsVisitor<T> {
+    type Value = Box<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("component options")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        let deserializer = MapAccessDeserializer::new(map);
+        (self.factory)(&mut <dyn erased_serde::Deserializer>::erase(deserializer)).map_err(de::Error::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for RouteConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let visitor = ComponentVisitor {
+            expecting: "a registered route",
+            resolve: route_factory_for,
+            _marker: PhantomData,
+        };
+        let (kind, spec) = deserializer.deserialize_enum("RouteConfig", &[], visitor)?;
+        Ok(Self { kind, spec })
+    }
+}
+
+impl<'de> Deserialize<'de> for UpdateConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let visitor = ComponentVisitor {
+            expecting: "a registered update",
+            resolve: update_factory_for,
+            _marker: PhantomData,
+        };
+        let (kind, spec) = deserializer.deserialize_enum("UpdateConfig", &[], visitor)?;
+        Ok(Self { kind, spec })
+    }
+}
+
+
+// Written back the same way, as `Name(field: value, ...)`. The options
+// serialize themselves as a struct, `VariantSerializer` turns that struct into
+// a struct variant named after the component.
+impl Serialize for RouteConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_component("RouteConfig", &self.kind, self.spec.serialized(), serializer)
+    }
+}
+
+impl Serialize for UpdateConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_component("UpdateConfig", &self.kind, self.spec.serialized(), serializer)
+    }
+}
+
+fn serialize_component<S: Serializer>(
+    name: &'static str,
+    kind: &str,
+    options: Option<&dyn erased_serde::Serialize>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let options = options.ok_or_else(|| ser::Error::custom(format!("`{}` cannot be written back", kind)))?;
+    erased_serde::serialize(options, VariantSerializer { name, kind: intern(kind), inner: serializer })
+}
+
+// Variant names have to be `'static`, there is one per registered component.
+fn intern(kind: &str) -> &'static str {
+    static KINDS: Lazy<RwLock<HashSet<&'static str>>> = Lazy::new(|| RwLock::new(HashSet::new()));
+
+    if let Some(kind) = KINDS.read().expect("Registry lock poisoned").get(kind) {
+        return kind;
+    }
+    let mut kinds = KINDS.write().expect("Registry lock poisoned");
+    match kinds.get(kind) {
+        Some(kind) => kind,
+        None => {
+            let kind: &'static str = Box::leak(kind.to_string().into_boxed_str());
+            kinds.insert(kind);
+            kind
+        }
+    }
+}
+
+struct VariantSerializer<S> {
+    name: &'static str,
+    kind: &'static str,
+    inner: S,
+}
+
+struct VariantFields<V>(V);
+
+impl<V: SerializeStructVariant> SerializeStruct for VariantFields<V> {
+    type Ok = V::Ok;
+    type Error = V::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), V::Error> {
+        self.0.serialize_field(key, value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), V::Error> {
+        self.0.skip_field(key)
+    }
+
+    fn end(self) -> Result<V::Ok, V::Error> {
+        self.0.end()
+    }
+}
+
+macro_rules! not_a_struct {
+    ($($method:ident($($arg:ty),*) -> $ret:ty;)*) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<$ret, S::Error> {
+                Err(ser::Error::custom(format!("options of `{}` must be a struct", self.kind)))
+            }
+        )*
+    };
+}
+
+impl<S: Serializer> Serializer for VariantSerializer<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Impossible<S::Ok, S::Error>;
+    type SerializeTuple = Impossible<S::Ok, S::Error>;
+    type SerializeTupleStruct = Impossible<S::Ok, S::Error>;
+    type SerializeTupleVariant = Impossible<S::Ok, S::Error>;
+    type SerializeMap = Impossible<S::Ok, S::Error>;
+    type SerializeStruct = VariantFields<S::SerializeStructVariant>;
+    type SerializeStructVariant = Impossible<S::Ok, S::Error>;
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct, S::Error> {
+        self.inner.serialize_struct_variant(self.name, 0, self.kind, len).map(VariantFields)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _value: &T) -> Result<S::Ok, S::Error> {
+        Err(ser::Error::custom(format!("options of `{}` must be a struct", self.kind)))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, _value: &T) -> Result<S::Ok, S::Error> {
+        Err(ser::Error::custom(format!("options of `{}` must be a struct", self.kind)))
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        Err(ser::Error::custom(format!("options of `{}` must be a struct", self.kind)))
+    }
+
+    not_a_struct! {
+        serialize_bool(bool) -> S::Ok;
+        serialize_i8(i8) -> S::Ok;
+        serialize_i16(i16) -> S::Ok;
+        serialize_i32(i32) -> S::Ok;
+        serialize_i64(i64) -> S::Ok;
+        serialize_u8(u8) -> S::Ok;
+        serialize_u16(u16) -> S::Ok;
+        serialize_u32(u32) -> S::Ok;
+        serialize_u64(u64) -> S::Ok;
+        serialize_f32(f32) -> S::Ok;
+        serialize_f64(f64) -> S::Ok;
+        serialize_char(char) -> S::Ok;
+        serialize_str(&str) -> S::Ok;
+        serialize_bytes(&[u8]) -> S::Ok;
+        serialize_none() -> S::Ok;
+        serialize_unit() -> S::Ok;
+        serialize_unit_struct(&'static str) -> S::Ok;
+        serialize_unit_variant(&'static str, u32, &'static str) -> S::Ok;
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+}
@@ -1,6 +1,19 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug)]
+use std::collections::HashMap;
+
+pub use crate::config::registry::{RouteConfig, UpdateConfig};
+use crate::update::replay::ReplaySpeed;
+use crate::update::webhook::WhenFull;
+use crate::dispatch::ordered::OrderingKey;
+use crate::dispatch::flood::{FloodLimits, RateLimit};
+use crate::lb::split::SplitAssignment;
+use crate::route::filter::FilterList;
+use crate::route::longpull::UpdateAge;
+use crate::route::mediagroup::MediaGroupMode;
+use crate::listener::{ListenerComponent, ALL_COMPONENTS};
+
+#[derive(Deserialize, Serialize, Debug)]
 pub struct TginConfig {
     #[serde(default = "default_workers")]
     pub dark_threads: usize,
@@ -8,66 +21,353 @@ pub struct TginConfig {
     #[serde(default)]
     pub ssl: Option<SslConfig>,
     pub updates: Vec<UpdateConfig>,
+    /// Positions in `updates` of the providers that start paused, kept up to
+    /// date when they are paused and resumed through the API.
+    #[serde(default)]
+    pub paused_updates: Vec<usize>,
     pub route: RouteConfig,
     pub api: Option<ApiConfig>,
+    #[serde(default)]
+    pub ordering: Option<OrderingConfig>,
+    #[serde(default)]
+    pub flood_control: Option<FloodControlConfig>,
+    /// Defaults for every outbound HTTP client, components can override single fields.
+    #[serde(default)]
+    pub http: Option<HttpConfig>,
+    /// Listeners in addition to `server_port`.
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfig>,
+    /// Updates buffered between the providers and dispatch.
+    pub queue_capacity: Option<usize>,
 }
 
 fn default_workers() -> usize {
     4
 }
 
-#[derive(Deserialize, Debug)]
+/// Outbound HTTP client settings, used for Telegram calls and webhook deliveries.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct HttpConfig {
+    /// `http://`, `https://` or `socks5://` proxy URL.
+    pub proxy: Option<String>,
+    pub proxy_username: Option<String>,
+    pub proxy_password: Option<String>,
+    /// PEM file with extra root certificates to trust.
+    pub ca_bundle: Option<String>,
+    /// PEM certificate and key presented to servers that require mTLS.
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+    pub connect_timeout_ms: Option<u64>,
+    /// Whole request timeout. Long polling adds its poll `timeout` to it.
+    pub timeout_ms: Option<u64>,
+    pub pool_max_idle_per_host: Option<usize>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ListenerConfig {
+    /// `0.0.0.0:8443`, `[::]:8443` or `unix:/run/tgin.sock`.
+    pub address: String,
+    pub ssl: Option<SslConfig>,
+    #[serde(default = "default_listener_serve")]
+    pub serve: Vec<ListenerComponent>,
+    /// Read the client address from a PROXY protocol header.
+    #[serde(default)]
+    pub proxy_protocol: bool,
+    /// Load balancers allowed to send that header, required with `proxy_protocol`.
+    #[serde(default)]
+    pub proxy_protocol_from: Vec<String>,
+}
+
+fn default_listener_serve() -> Vec<ListenerComponent> {
+    ALL_COMPONENTS.to_vec()
+}
+
+#[derive(Deserialize, Serialize, Debug)]
 pub struct SslConfig {
     pub cert: String,
     pub key: String,
+    /// Certificates picked by the SNI name of the client, `cert`/`key` stay the default.
+    #[serde(default)]
+    pub sni: Vec<SniCertConfig>,
+    /// Seconds between checks of the files for changes, SIGHUP always reloads.
+    pub watch_every: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SniCertConfig {
+    /// `bot.example.com` or `*.example.com`.
+    pub domains: Vec<String>,
+    pub cert: String,
+    pub key: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct OrderingConfig {
+    #[serde(default)]
+    pub key: OrderingKey,
+    #[serde(default = "default_lanes")]
+    pub lanes: usize,
+    #[serde(default = "default_queue_limit")]
+    pub queue_limit: usize,
 }
 
-#[derive(Deserialize, Debug)]
+fn default_lanes() -> usize {
+    256
+}
+
+fn default_queue_limit() -> usize {
+    1000
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct FloodControlConfig {
+    pub per_user: Option<RateLimit>,
+    pub per_chat: Option<RateLimit>,
+    #[serde(default)]
+    pub per_type: HashMap<String, FloodLimits>,
+    #[serde(default)]
+    pub allowlist: Vec<i64>,
+    pub quarantine: Option<RouteConfig>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
 pub struct ApiConfig {
     pub base_path: String,
+    /// Rewrite the config file after every change made through the API.
+    #[serde(default)]
+    pub persist: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct LongPollUpdateConfig {
+    pub token: String,
+    pub url: Option<String>,
+    #[serde(default = "default_timeout")]
+    pub default_timeout_sleep: u64,
+    #[serde(default = "default_timeout")]
+    pub error_timeout_sleep: u64,
+    /// Cap for the exponential backoff after failed calls, in milliseconds.
+    pub max_error_sleep: Option<u64>,
+    /// `getUpdates` long poll timeout in seconds.
+    pub timeout: Option<u64>,
+    pub limit: Option<u32>,
+    pub allowed_updates: Option<Vec<String>>,
+    #[serde(default)]
+    pub delete_webhook_on_conflict: bool,
+    pub http: Option<HttpConfig>,
 }
 
-#[derive(Deserialize, Debug)]
-pub enum UpdateConfig {
-    LongPollUpdate {
-        token: String,
-        url: Option<String>,
-        #[serde(default = "default_timeout")]
-        default_timeout_sleep: u64,
-        #[serde(default = "default_timeout")]
-        error_timeout_sleep: u64,
-    },
-    WebhookUpdate {
-        path: String,
-        registration: Option<RegistrationWebhookConfig>,
-    },
+#[derive(Deserialize, Serialize, Debug)]
+pub struct WebhookUpdateConfig {
+    pub path: String,
+    pub registration: Option<RegistrationWebhookConfig>,
+    /// CIDR blocks or addresses allowed to deliver updates, `telegram` for
+    /// Telegram's published subnets. Empty allows everyone.
+    #[serde(default)]
+    pub allowed_ips: Vec<String>,
+    /// Proxies whose `X-Forwarded-For` header is trusted.
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    #[serde(default)]
+    pub when_full: WhenFull,
+    pub max_body_bytes: Option<usize>,
+    pub request_timeout_ms: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ReplayUpdateConfig {
+    pub path: String,
+    #[serde(default)]
+    pub speed: ReplaySpeed,
 }
 
 fn default_timeout() -> u64 {
     100
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct RegistrationWebhookConfig {
     pub public_ip: String,
     pub set_webhook_url: Option<String>,
     pub token: String,
+    pub http: Option<HttpConfig>,
 }
-#[derive(Deserialize, Debug)]
-pub enum RouteStrategyConfig {
-    RoundRobinLB { routes: Vec<RouteConfig> },
-    AllLB { routes: Vec<RouteConfig> },
+
+
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct LongPollRouteConfig {
+    pub path: String,
+    pub stale_after: Option<u64>,
+    pub ttl: Option<u64>,
+    #[serde(default)]
+    pub ttl_from: UpdateAge,
+    pub sweep_every: Option<u64>,
+    pub expired_route: Option<RouteConfig>,
+    /// Only takes effect under a `RoundRobinLB`.
+    pub redeliver_after: Option<u64>,
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+pub struct WebhookRouteConfig {
+    pub url: String,
+    /// Per delivery, falls back to `http.timeout_ms` and then to 30 seconds.
+    pub timeout_ms: Option<u64>,
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    pub http: Option<HttpConfig>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Sent as `X-Telegram-Bot-Api-Secret-Token`.
+    pub secret_token: Option<String>,
+    /// HMAC-SHA256 key for the `X-Tgin-Signature` header.
+    pub signing_secret: Option<String>,
+}
 
+#[derive(Deserialize, Serialize, Debug)]
+pub struct CircuitBreakerConfig {
+    #[serde(default = "default_error_rate")]
+    pub error_rate: f64,
+    #[serde(default = "default_breaker_window")]
+    pub window: usize,
+    #[serde(default = "default_min_requests")]
+    pub min_requests: usize,
+    pub slow_call_ms: Option<u64>,
+    #[serde(default = "default_open_for")]
+    pub open_for: u64,
+    #[serde(default = "default_half_open_probes")]
+    pub half_open_probes: usize,
+}
+
+fn default_error_rate() -> f64 {
+    50.0
+}
+
+fn default_breaker_window() -> usize {
+    20
+}
+
+fn default_min_requests() -> usize {
+    10
+}
+
+fn default_open_for() -> u64 {
+    30
+}
+
+fn default_half_open_probes() -> usize {
+    1
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct FileSinkRouteConfig {
+    pub path: String,
+    pub prefix: Option<String>,
+    pub max_size: Option<u64>,
+    pub rotate_every: Option<u64>,
+    #[serde(default)]
+    pub gzip: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct StreamRouteConfig {
+    pub path: String,
+    pub prefetch: Option<usize>,
+    pub reclaim_after: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct MirrorRouteConfig {
+    pub primary: RouteConfig,
+    pub shadows: Vec<RouteConfig>,
+    #[serde(default = "default_sample")]
+    pub sample: f64,
+    pub max_shadow_in_flight: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct FilterRouteConfig {
+    pub route: RouteConfig,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub allow: FilterList,
+    #[serde(default)]
+    pub deny: FilterList,
+    pub file: Option<String>,
+    #[serde(default = "default_watch_every")]
+    pub watch_every: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct MediaGroupRouteConfig {
+    pub route: RouteConfig,
+    pub window_ms: Option<u64>,
+    pub max_size: Option<usize>,
+    #[serde(default)]
+    pub mode: MediaGroupMode,
+}
+
+fn default_watch_every() -> u64 {
+    5
+}
+
+fn default_sample() -> f64 {
+    100.0
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RoundRobinLBConfig {
+    pub routes: Vec<RouteConfig>,
+    pub affinity: Option<AffinityConfig>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct AffinityConfig {
+    /// Seconds a message stays pinned to its route.
+    #[serde(default = "default_affinity_ttl")]
+    pub ttl: u64,
+    #[serde(default = "default_affinity_entries")]
+    pub max_entries: usize,
+    /// Path of the Bot API proxy the replicas send through, e.g. `"/tg"`.
+    pub proxy_path: Option<String>,
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+    pub http: Option<HttpConfig>,
+}
+
+fn default_affinity_ttl() -> u64 {
+    3600
+}
+
+fn default_affinity_entries() -> usize {
+    100_000
+}
+
+fn default_api_url() -> String {
+    "https://api.telegram.org".to_string()
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SplitLBConfig {
+    pub name: Option<String>,
+    #[serde(default)]
+    pub assignment: SplitAssignment,
+    pub routes: Vec<SplitTargetConfig>,
+}
 
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SplitTargetConfig {
+    pub share: f64,
+    pub route: RouteConfig,
+}
 
+#[derive(Deserialize, Serialize, Debug)]
+pub struct AllLBConfig {
+    pub routes: Vec<RouteConfig>,
+}
 
-#[derive(Deserialize, Debug)]
-pub enum RouteConfig {
-    LongPollRoute { path: String },
-    WebhookRoute { url: String },
-    
-    RoundRobinLB { routes: Vec<RouteConfig> },
-    AllLB { routes: Vec<RouteConfig> },
-}
\ No newline at end of file
+#[derive(Deserialize, Serialize, Debug)]
+pub struct FailoverLBConfig {
+    pub groups: Vec<Vec<RouteConfig>>,
+    pub recover_after: Option<u64>,
+}
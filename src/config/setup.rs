@@ -1,14 +1,39 @@
 use crate::base::{RouteableComponent, UpdaterComponent};
-use crate::lb::{roundrobin::RoundRobinLB, all::AllLB};
+use crate::lb::{roundrobin::RoundRobinLB, all::AllLB, split::SplitLB, failover::FailoverLB, affinity::Affinity};
 use crate::route::longpull::LongPollRoute;
 use crate::route::webhook::WebhookRoute;
+use crate::route::filesink::FileSinkRoute;
+use crate::route::stream::StreamRoute;
+use crate::route::mirror::MirrorRoute;
+use crate::route::filter::{FilterRoute, FilterLists};
+use crate::route::mediagroup::MediaGroupRoute;
+use crate::utils::breaker::CircuitBreaker;
+use crate::dispatch::flood::{FloodControl, FloodLimits};
+use crate::listener::{ListenAddr, Listener};
+use crate::utils::tls::TlsCerts;
+use crate::utils::source_ip::{parse_networks, SourceFilter};
 use crate::update::longpull::LongPollUpdate;
-use crate::update::webhook::{WebhookUpdate};
-use crate::config::schema::{TginConfig, UpdateConfig,RouteConfig};
+use crate::update::replay::ReplayUpdate;
+use crate::update::webhook::{WebhookUpdate, RegistrationWebhookConfig};
+use crate::config::registry::{ComponentRegistry, RouteSpec, UpdateSpec};
+use crate::config::document::{ConfigDocument, ConfigEdit};
+use crate::api::router::Api;
+use crate::tgin::Tgin;
+use crate::config::schema::{
+    TginConfig, UpdateConfig, RouteConfig, FloodControlConfig, HttpConfig, ListenerConfig, SslConfig,
+    LongPollUpdateConfig, WebhookUpdateConfig, ReplayUpdateConfig,
+    LongPollRouteConfig, WebhookRouteConfig, CircuitBreakerConfig, FileSinkRouteConfig, StreamRouteConfig,
+    MirrorRouteConfig, FilterRouteConfig, MediaGroupRouteConfig, RoundRobinLBConfig, AffinityConfig, AllLBConfig, SplitLBConfig, FailoverLBConfig,
+};
 
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use std::fs;
 
+use once_cell::sync::Lazy;
+use reqwest::{Certificate, Client, Identity, Proxy};
+use serde_json::Value;
+
 use std::env;
 use regex::Regex;
 
@@ -20,70 +45,634 @@ pub fn load_config(path: &str) -> TginConfig {
 }
 
 
-fn substitute_env_vars(input: &str) -> String {
-    let re = Regex::new(r"\$\{(\w+)\}").unwrap();
+static PLACEHOLDER: Lazy<Regex> = Lazy::new(|| Regex::new(r"\$\{(\w+)\}").unwrap());
+
+pub(crate) fn substitute_env_vars(input: &str) -> String {
+    try_substitute_env_vars(input).unwrap_or_else(|e| panic!("{}", e))
+}
 
-    re.replace_all(input, |caps: &regex::Captures| {
+fn try_substitute_env_vars(input: &str) -> Result<String, String> {
+    let mut unset = None;
+    let output = PLACEHOLDER.replace_all(input, |caps: &regex::Captures| {
         let var_name = &caps[1];
         
         match env::var(var_name) {
             Ok(val) => val,
             Err(_) => {
-                panic!("Environment variable '${}' is not set", var_name);
+                unset.get_or_insert_with(|| var_name.to_string());
+                String::new()
+            }
+        }
+    }).to_string();
+    match unset {
+        Some(var_name) => Err(format!("Environment variable '${}' is not set", var_name)),
+        None => Ok(output),
+    }
+}
+
+pub(crate) fn has_env_vars(input: &str) -> bool {
+    PLACEHOLDER.is_match(input)
+}
+
+/// Substitutes the `${VAR}` placeholders in every string of `value`, e.g. the
+/// options of a component added through the API.
+pub(crate) fn substitute_env_vars_in(value: &mut Value) -> Result<(), String> {
+    match value {
+        Value::String(text) => *text = try_substitute_env_vars(text)?,
+        Value::Array(values) => values.iter_mut().try_for_each(substitute_env_vars_in)?,
+        Value::Object(map) => map.values_mut().try_for_each(substitute_env_vars_in)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Assembles the instance `cfg` describes. `document` is a second copy of
+/// the configuration for the API to keep up to date, only used with `api`.
+/// The `http` defaults are process wide.
+pub fn build_tgin(cfg: TginConfig, mut document: ConfigDocument) -> Result<Tgin, String> {
+    if let Some(http) = cfg.http {
+        build_client(&http)?;
+        set_default_http(http);
+    }
+
+    let mut tgin = Tgin::new(
+        build_updates(cfg.updates)?,
+        build_route(cfg.route)?,
+        cfg.dark_threads,
+        cfg.server_port,
+    );
+    tgin.set_paused_updates(cfg.paused_updates);
+
+    if let Some(queue_capacity) = cfg.queue_capacity {
+        tgin.set_queue_capacity(queue_capacity);
+    }
+
+    if let Some(api) = cfg.api {
+        document.set_persist(api.persist);
+        tgin.set_config(document);
+        tgin.set_api(Api::new(api.base_path));
+    }
+
+    if let Some(ordering) = cfg.ordering {
+        tgin.set_ordering(ordering.key, ordering.lanes, ordering.queue_limit);
+    }
+
+    if let Some(flood) = cfg.flood_control {
+        tgin.set_flood_control(build_flood_control(flood)?);
+    }
+
+    for listener in cfg.listeners {
+        tgin.add_listener(build_listener(listener)?);
+    }
+
+    if let Some(ssl) = cfg.ssl {
+        let tls = build_tls(ssl);
+        // fail before starting instead of inside the listener task
+        tls.server_config()?;
+        tgin.set_tls(tls);
+    }
+
+    Ok(tgin)
+}
+
+pub fn register_builtins(registry: &mut ComponentRegistry) {
+    registry.register_update::<LongPollUpdateConfig>(LongPollUpdate::KIND);
+    registry.register_update::<WebhookUpdateConfig>(WebhookUpdate::KIND);
+    registry.register_update::<ReplayUpdateConfig>(ReplayUpdate::KIND);
+
+    registry.register_route::<LongPollRouteConfig>(LongPollRoute::KIND);
+    registry.register_route::<WebhookRouteConfig>(WebhookRoute::KIND);
+    registry.register_route::<FileSinkRouteConfig>(FileSinkRoute::KIND);
+    registry.register_route::<StreamRouteConfig>(StreamRoute::KIND);
+    registry.register_route::<MirrorRouteConfig>(MirrorRoute::KIND);
+    registry.register_route::<FilterRouteConfig>(FilterRoute::KIND);
+    registry.register_route::<MediaGroupRouteConfig>(MediaGroupRoute::KIND);
+    registry.register_route::<RoundRobinLBConfig>(RoundRobinLB::KIND);
+    registry.register_route::<AllLBConfig>(AllLB::KIND);
+    registry.register_route::<SplitLBConfig>(SplitLB::KIND);
+    registry.register_route::<FailoverLBConfig>(FailoverLB::KIND);
+}
+
+pub fn build_tls(cfg: SslConfig) -> TlsCerts {
+    let mut tls = TlsCerts::new(cfg.cert, cfg.key);
+    for sni in cfg.sni {
+        tls.add_sni(sni.domains, sni.cert, sni.key);
+    }
+    if let Some(secs) = cfg.watch_every {
+        tls.set_watch_every(Duration::from_secs(secs.max(1)));
+    }
+    tls
+}
+
+pub fn build_listener(cfg: ListenerConfig) -> Result<Listener, String> {
+    let addr: ListenAddr = cfg.address.parse()?;
+    if matches!(addr, ListenAddr::Unix(_)) && cfg.ssl.is_some() {
+        return Err(format!("TLS is not supported on {}", addr));
+    }
+    if matches!(addr, ListenAddr::Unix(_)) && cfg.proxy_protocol {
+        return Err(format!("PROXY protocol is not supported on {}", addr));
+    }
+    let mut listener = Listener::new(addr);
+    if let Some(ssl) = cfg.ssl {
+        let tls = build_tls(ssl);
+        tls.server_config()?;
+        listener.set_tls(tls);
+    }
+    listener.set_serve(cfg.serve);
+    if cfg.proxy_protocol {
+        if cfg.proxy_protocol_from.is_empty() {
+            return Err(format!("proxy_protocol on {} needs proxy_protocol_from", listener.addr));
+        }
+        let from = parse_networks(&cfg.proxy_protocol_from).map_err(|e| format!("invalid proxy_protocol_from: {}", e))?;
+        listener.set_proxy_protocol(from);
+    }
+    Ok(listener)
+}
+
+/// `http` block of the top level config, applied to every client built afterwards.
+static DEFAULT_HTTP: Lazy<RwLock<HttpConfig>> = Lazy::new(|| RwLock::new(HttpConfig::default()));
+
+pub fn set_default_http(cfg: HttpConfig) {
+    *DEFAULT_HTTP.write().expect("Http config lock poisoned") = cfg;
+}
+
+/// Fills the fields a component left unset from the global `http` block.
+fn http_config(cfg: Option<HttpConfig>) -> HttpConfig {
+    let defaults = DEFAULT_HTTP.read().expect("Http config lock poisoned").clone();
+    let Some(cfg) = cfg else {
+        return defaults;
+    };
+    HttpConfig {
+        proxy: cfg.proxy.or(defaults.proxy),
+        proxy_username: cfg.proxy_username.or(defaults.proxy_username),
+        proxy_password: cfg.proxy_password.or(defaults.proxy_password),
+        ca_bundle: cfg.ca_bundle.or(defaults.ca_bundle),
+        client_cert: cfg.client_cert.or(defaults.client_cert),
+        client_key: cfg.client_key.or(defaults.client_key),
+        connect_timeout_ms: cfg.connect_timeout_ms.or(defaults.connect_timeout_ms),
+        timeout_ms: cfg.timeout_ms.or(defaults.timeout_ms),
+        pool_max_idle_per_host: cfg.pool_max_idle_per_host.or(defaults.pool_max_idle_per_host),
+        user_agent: cfg.user_agent.or(defaults.user_agent),
+    }
+}
+
+pub fn build_client(cfg: &HttpConfig) -> Result<Client, String> {
+    let mut builder = Client::builder();
+
+    if let Some(url) = &cfg.proxy {
+        let mut proxy = Proxy::all(url).map_err(|e| format!("invalid proxy {}: {}", url, e))?;
+        if let Some(username) = &cfg.proxy_username {
+            proxy = proxy.basic_auth(username, cfg.proxy_password.as_deref().unwrap_or(""));
+        }
+        builder = builder.proxy(proxy);
+    }
+    if let Some(path) = &cfg.ca_bundle {
+        let pem = fs::read(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+        let certs = Certificate::from_pem_bundle(&pem).map_err(|e| format!("invalid CA bundle {}: {}", path, e))?;
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    match (&cfg.client_cert, &cfg.client_key) {
+        (Some(cert), Some(key)) => {
+            let mut pem = fs::read(cert).map_err(|e| format!("failed to read {}: {}", cert, e))?;
+            pem.push(b'\n');
+            pem.extend(fs::read(key).map_err(|e| format!("failed to read {}: {}", key, e))?);
+            let identity = Identity::from_pem(&pem).map_err(|e| format!("invalid client certificate {}: {}", cert, e))?;
+            // PEM identities are only supported by the rustls backend
+            builder = builder.use_rustls_tls().identity(identity);
+        }
+        (None, None) => {}
+        _ => return Err("client_cert and client_key must be set together".to_string()),
+    }
+    if let Some(ms) = cfg.connect_timeout_ms {
+        builder = builder.connect_timeout(Duration::from_millis(ms));
+    }
+    if let Some(ms) = cfg.timeout_ms {
+        builder = builder.timeout(Duration::from_millis(ms));
+    }
+    if let Some(max) = cfg.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+    if let Some(user_agent) = &cfg.user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+fn client_for(cfg: &HttpConfig) -> Result<Client, String> {
+    build_client(cfg).map_err(|e| format!("invalid http config: {}", e))
+}
+
+pub fn build_updates(configs: Vec<UpdateConfig>) -> Result<Vec<Box<dyn UpdaterComponent>>, String> {
+    configs.into_iter().map(|cfg| cfg.spec.build()).collect()
+}
+
+pub fn build_route(cfg: RouteConfig) -> Result<Arc<dyn RouteableComponent>, String> {
+    cfg.spec.build()
+}
+
+pub fn build_flood_control(cfg: FloodControlConfig) -> Result<FloodControl, String> {
+    let mut flood = FloodControl::new(FloodLimits {
+        per_user: cfg.per_user,
+        per_chat: cfg.per_chat,
+    });
+    for (kind, limits) in cfg.per_type {
+        flood.set_type_limits(&kind, limits)?;
+    }
+    flood.set_allowlist(cfg.allowlist);
+    if let Some(quarantine) = cfg.quarantine {
+        flood.set_quarantine(build_route(quarantine)?);
+    }
+    Ok(flood)
+}
+
+
+impl UpdateSpec for LongPollUpdateConfig {
+    fn build(self: Box<Self>) -> Result<Box<dyn UpdaterComponent>, String> {
+        let http = http_config(self.http);
+        let mut up = LongPollUpdate::new(self.token);
+        up.set_client(client_for(&http)?);
+        if let Some(ms) = http.timeout_ms {
+            up.set_request_timeout(Duration::from_millis(ms));
+        }
+        if let Some(u) = self.url {
+            up.set_url(u); 
+        }
+        up.set_timeouts(self.default_timeout_sleep, self.error_timeout_sleep);
+        if let Some(max) = self.max_error_sleep {
+            up.set_max_error_sleep(max);
+        }
+        up.set_poll(self.timeout.unwrap_or(30), self.limit.unwrap_or(100));
+        if let Some(allowed) = self.allowed_updates {
+            up.set_allowed_updates(allowed);
+        }
+        up.set_delete_webhook_on_conflict(self.delete_webhook_on_conflict);
+        Ok(Box::new(up))
+    }
+
+    fn serialized(&self) -> Option<&dyn erased_serde::Serialize> {
+        Some(self)
+    }
+}
+
+impl UpdateSpec for WebhookUpdateConfig {
+    fn build(self: Box<Self>) -> Result<Box<dyn UpdaterComponent>, String> {
+        let mut up = WebhookUpdate::new(self.path);
+        if let Some(reg) = self.registration {
+            let mut registration = RegistrationWebhookConfig::new(reg.token, reg.public_ip);
+            registration.set_client(client_for(&http_config(reg.http))?);
+            if let Some(url) = reg.set_webhook_url {
+                registration.set_webhook_url(url);
             }
+            up.set_registration(registration);
+        }
+        if !self.allowed_ips.is_empty() {
+            let allowed = parse_networks(&self.allowed_ips).map_err(|e| format!("invalid allowed_ips: {}", e))?;
+            let mut filter = SourceFilter::new(allowed);
+            filter.set_trusted_proxies(parse_networks(&self.trusted_proxies).map_err(|e| format!("invalid trusted_proxies: {}", e))?);
+            up.set_source_filter(filter);
+        }
+        up.set_when_full(self.when_full)?;
+        if let Some(max_body_bytes) = self.max_body_bytes {
+            up.set_max_body_bytes(max_body_bytes);
         }
-    }).to_string()
+        if let Some(ms) = self.request_timeout_ms {
+            up.set_request_timeout(Duration::from_millis(ms));
+        }
+        Ok(Box::new(up))
+    }
+
+    fn serialized(&self) -> Option<&dyn erased_serde::Serialize> {
+        Some(self)
+    }
 }
 
-pub fn build_updates(configs: Vec<UpdateConfig>) -> Vec<Box<dyn UpdaterComponent>> {
-    let mut result: Vec<Box<dyn UpdaterComponent>> = Vec::new();
+impl UpdateSpec for ReplayUpdateConfig {
+    fn build(self: Box<Self>) -> Result<Box<dyn UpdaterComponent>, String> {
+        let mut up = ReplayUpdate::new(self.path);
+        up.set_speed(self.speed);
+        Ok(Box::new(up))
+    }
+
+    fn serialized(&self) -> Option<&dyn erased_serde::Serialize> {
+        Some(self)
+    }
+}
 
-    for cfg in configs {
-        match cfg {
-            UpdateConfig::LongPollUpdate { token, url, default_timeout_sleep, error_timeout_sleep } => {
-                let mut up = LongPollUpdate::new(token);
-                if let Some(u) = url {
-                    up.set_url(u); 
-                }
-                up.set_timeouts(default_timeout_sleep, error_timeout_sleep); 
-                result.push(Box::new(up));
+impl RouteSpec for LongPollRouteConfig {
+    fn build(self: Box<Self>) -> Result<Arc<dyn RouteableComponent>, String> {
+        let mut route = LongPollRoute::new(self.path);
+        if let Some(secs) = self.stale_after {
+            route.set_stale_after(Duration::from_secs(secs));
+        }
+        if let Some(secs) = self.redeliver_after {
+            route.set_redeliver_after(Duration::from_secs(secs));
+        }
+        if let Some(secs) = self.ttl {
+            route.set_ttl(Duration::from_secs(secs), self.ttl_from);
+            if let Some(expired_route) = self.expired_route {
+                route.set_expired_route(build_route(expired_route)?);
             }
-            UpdateConfig::WebhookUpdate { path, registration } => {
-                let mut up = WebhookUpdate::new(path);
-                if let Some(reg) = registration {
-                }
-                result.push(Box::new(up));
+            if let Some(secs) = self.sweep_every {
+                route.set_sweep_every(Duration::from_secs(secs.max(1)));
             }
         }
+        Ok(Arc::new(route))
+    }
+
+    fn serialized(&self) -> Option<&dyn erased_serde::Serialize> {
+        Some(self)
+    }
+
+    fn routes_mut(&mut self) -> Vec<&mut RouteConfig> {
+        self.expired_route.iter_mut().collect()
     }
-    result
 }
 
-pub fn build_route(cfg: RouteConfig) -> Arc<dyn RouteableComponent> {
-    match cfg {
-        RouteConfig::LongPollRoute { path } => {
-            Arc::new(LongPollRoute::new(path))
+impl RouteSpec for WebhookRouteConfig {
+    fn build(self: Box<Self>) -> Result<Arc<dyn RouteableComponent>, String> {
+        let http = http_config(self.http);
+        let mut route = WebhookRoute::new(self.url);
+        route.set_client(client_for(&http)?);
+        // a per-request timeout replaces the client one, so the client one is the fallback
+        if let Some(ms) = self.timeout_ms.or(http.timeout_ms) {
+            route.set_timeout(Duration::from_millis(ms));
         }
-        RouteConfig::WebhookRoute { url } => {
-            Arc::new(WebhookRoute::new(url))
+        if let Some(breaker) = self.circuit_breaker {
+            route.set_circuit_breaker(build_breaker(breaker)?);
         }
-        
-        RouteConfig::RoundRobinLB { routes } => {
-            let built_routes: Vec<Arc<dyn RouteableComponent>> = routes
-                .into_iter()
-                .map(build_route) 
-                .collect();
-            
-            Arc::new(RoundRobinLB::new(built_routes))
+        route.set_headers(self.headers.into_iter().collect())?;
+        if let Some(token) = self.secret_token {
+            route.set_secret_token(token)?;
         }
-        
-        RouteConfig::AllLB { routes } => {
-            let built_routes: Vec<Arc<dyn RouteableComponent>> = routes
-                .into_iter()
-                .map(build_route) 
-                .collect();
+        if let Some(secret) = self.signing_secret {
+            route.set_signing_secret(secret);
+        }
+        Ok(Arc::new(route))
+    }
+
+    fn serialized(&self) -> Option<&dyn erased_serde::Serialize> {
+        Some(self)
+    }
+}
+
+fn build_breaker(cfg: CircuitBreakerConfig) -> Result<CircuitBreaker, String> {
+    // at 0% a single success would already open the circuit
+    if !(cfg.error_rate > 0.0 && cfg.error_rate <= 100.0) {
+        return Err(format!("circuit breaker error_rate must be above 0 and at most 100, got {}", cfg.error_rate));
+    }
+
+    let mut breaker = CircuitBreaker::new();
+    breaker.set_error_rate(cfg.error_rate, cfg.window, cfg.min_requests);
+    breaker.set_open_for(Duration::from_secs(cfg.open_for));
+    breaker.set_half_open_probes(cfg.half_open_probes);
+    if let Some(ms) = cfg.slow_call_ms {
+        breaker.set_slow_call(Duration::from_millis(ms));
+    }
+    Ok(breaker)
+}
+
+impl RouteSpec for FileSinkRouteConfig {
+    fn build(self: Box<Self>) -> Result<Arc<dyn RouteableComponent>, String> {
+        let mut route = FileSinkRoute::new(self.path);
+        if let Some(prefix) = self.prefix {
+            route.set_prefix(prefix);
+        }
+        route.set_rotation(self.max_size, self.rotate_every.map(Duration::from_secs));
+        route.set_gzip(self.gzip);
+        Ok(Arc::new(route))
+    }
+
+    fn serialized(&self) -> Option<&dyn erased_serde::Serialize> {
+        Some(self)
+    }
+}
+
+impl RouteSpec for StreamRouteConfig {
+    fn build(self: Box<Self>) -> Result<Arc<dyn RouteableComponent>, String> {
+        let mut route = StreamRoute::new(self.path);
+        if let Some(prefetch) = self.prefetch {
+            route.set_prefetch(prefetch);
+        }
+        if let Some(reclaim_after) = self.reclaim_after {
+            route.set_reclaim_after(Duration::from_secs(reclaim_after));
+        }
+        Ok(Arc::new(route))
+    }
+
+    fn serialized(&self) -> Option<&dyn erased_serde::Serialize> {
+        Some(self)
+    }
+}
+
+impl RouteSpec for MirrorRouteConfig {
+    fn build(self: Box<Self>) -> Result<Arc<dyn RouteableComponent>, String> {
+        let shadows: Vec<Arc<dyn RouteableComponent>> = self.shadows
+            .into_iter()
+            .map(build_route)
+            .collect::<Result<_, _>>()?;
+
+        let mut route = MirrorRoute::new(build_route(self.primary)?, shadows);
+        route.set_sample(self.sample);
+        if let Some(max) = self.max_shadow_in_flight {
+            route.set_max_shadow_in_flight(max);
+        }
+        Ok(Arc::new(route))
+    }
+
+    fn serialized(&self) -> Option<&dyn erased_serde::Serialize> {
+        Some(self)
+    }
+
+    fn routes_mut(&mut self) -> Vec<&mut RouteConfig> {
+        std::iter::once(&mut self.primary).chain(self.shadows.iter_mut()).collect()
+    }
+}
+
+impl RouteSpec for FilterRouteConfig {
+    fn build(self: Box<Self>) -> Result<Arc<dyn RouteableComponent>, String> {
+        let mut route = FilterRoute::new(
+            build_route(self.route)?,
+            FilterLists { allow: self.allow, deny: self.deny },
+        );
+        if let Some(file) = self.file {
+            route.set_file(file, Duration::from_secs(self.watch_every.max(1)))
+                .map_err(|e| format!("failed to load filter lists: {}", e))?;
+        }
+        if let Some(name) = self.name {
+            route.set_name(name);
+        }
+        Ok(Arc::new(route))
+    }
+
+    fn serialized(&self) -> Option<&dyn erased_serde::Serialize> {
+        Some(self)
+    }
+
+    fn routes_mut(&mut self) -> Vec<&mut RouteConfig> {
+        vec![&mut self.route]
+    }
+
+    fn add_route(&mut self, route: RouteConfig) -> Result<(), RouteConfig> {
+        self.route.spec.add_route(route)
+    }
+
+    // lists kept in a file are written back by the filter itself
+    fn edit(&mut self, edit: &ConfigEdit) -> bool {
+        let ConfigEdit::Filter { name, lists } = edit else {
+            return false;
+        };
+        if self.name.as_ref() != Some(name) {
+            return false;
+        }
+        if self.file.is_none() {
+            self.allow = lists.allow.clone();
+            self.deny = lists.deny.clone();
+        }
+        true
+    }
+}
+
+impl RouteSpec for MediaGroupRouteConfig {
+    fn build(self: Box<Self>) -> Result<Arc<dyn RouteableComponent>, String> {
+        let mut route = MediaGroupRoute::new(build_route(self.route)?);
+        route.set_mode(self.mode);
+        if let Some(ms) = self.window_ms {
+            route.set_window(Duration::from_millis(ms));
+        }
+        if let Some(max_size) = self.max_size {
+            route.set_max_size(max_size);
+        }
+        Ok(Arc::new(route))
+    }
+
+    fn serialized(&self) -> Option<&dyn erased_serde::Serialize> {
+        Some(self)
+    }
+
+    fn routes_mut(&mut self) -> Vec<&mut RouteConfig> {
+        vec![&mut self.route]
+    }
+
+    fn add_route(&mut self, route: RouteConfig) -> Result<(), RouteConfig> {
+        self.route.spec.add_route(route)
+    }
+}
+
+impl RouteSpec for RoundRobinLBConfig {
+    fn build(self: Box<Self>) -> Result<Arc<dyn RouteableComponent>, String> {
+        let built_routes: Vec<Arc<dyn RouteableComponent>> = self.routes
+            .into_iter()
+            .map(build_route)
+            .collect::<Result<_, _>>()?;
+
+        let mut lb = RoundRobinLB::new(built_routes);
+        if let Some(affinity) = self.affinity {
+            lb.set_affinity(build_affinity(affinity)?);
+        }
+        Ok(Arc::new(lb))
+    }
+
+    fn serialized(&self) -> Option<&dyn erased_serde::Serialize> {
+        Some(self)
+    }
+
+    fn routes_mut(&mut self) -> Vec<&mut RouteConfig> {
+        self.routes.iter_mut().collect()
+    }
+
+    fn add_route(&mut self, route: RouteConfig) -> Result<(), RouteConfig> {
+        self.routes.push(route);
+        Ok(())
+    }
+}
+
+fn build_affinity(cfg: AffinityConfig) -> Result<Affinity, String> {
+    let mut affinity = Affinity::new(Duration::from_secs(cfg.ttl));
+    affinity.set_client(client_for(&http_config(cfg.http))?);
+    affinity.set_max_entries(cfg.max_entries);
+    if let Some(path) = cfg.proxy_path {
+        affinity.set_proxy(path, cfg.api_url);
+    }
+    Ok(affinity)
+}
+
+impl RouteSpec for AllLBConfig {
+    fn build(self: Box<Self>) -> Result<Arc<dyn RouteableComponent>, String> {
+        let built_routes: Vec<Arc<dyn RouteableComponent>> = self.routes
+            .into_iter()
+            .map(build_route)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Arc::new(AllLB::new(built_routes)))
+    }
+
+    fn serialized(&self) -> Option<&dyn erased_serde::Serialize> {
+        Some(self)
+    }
+
+    fn routes_mut(&mut self) -> Vec<&mut RouteConfig> {
+        self.routes.iter_mut().collect()
+    }
+}
+
+impl RouteSpec for SplitLBConfig {
+    fn build(self: Box<Self>) -> Result<Arc<dyn RouteableComponent>, String> {
+        let built_routes: Vec<(f64, Arc<dyn RouteableComponent>)> = self.routes
+            .into_iter()
+            .map(|target| Ok((target.share, build_route(target.route)?)))
+            .collect::<Result<_, String>>()?;
 
-            Arc::new(AllLB::new(built_routes))
+        let mut lb = SplitLB::new(built_routes);
+        lb.set_assignment(self.assignment);
+        if let Some(name) = self.name {
+            lb.set_name(name)?;
         }
+        Ok(Arc::new(lb))
     }
-}
\ No newline at end of file
+
+    fn serialized(&self) -> Option<&dyn erased_serde::Serialize> {
+        Some(self)
+    }
+
+    fn routes_mut(&mut self) -> Vec<&mut RouteConfig> {
+        self.routes.iter_mut().map(|target| &mut target.route).collect()
+    }
+
+    fn edit(&mut self, edit: &ConfigEdit) -> bool {
+        let ConfigEdit::Shares { name, shares } = edit else {
+            return false;
+        };
+        if self.name.as_ref() != Some(name) || shares.len() != self.routes.len() {
+            return false;
+        }
+        for (target, share) in self.routes.iter_mut().zip(shares) {
+            target.share = *share;
+        }
+        true
+    }
+}
+
+impl RouteSpec for FailoverLBConfig {
+    fn build(self: Box<Self>) -> Result<Arc<dyn RouteableComponent>, String> {
+        let built_groups: Vec<Vec<Arc<dyn RouteableComponent>>> = self.groups
+            .into_iter()
+            .map(|group| group.into_iter().map(build_route).collect())
+            .collect::<Result<_, _>>()?;
+
+        let mut lb = FailoverLB::new(built_groups);
+        if let Some(secs) = self.recover_after {
+            lb.set_recover_after(Duration::from_secs(secs));
+        }
+        Ok(Arc::new(lb))
+    }
+
+    fn serialized(&self) -> Option<&dyn erased_serde::Serialize> {
+        Some(self)
+    }
+
+    fn routes_mut(&mut self) -> Vec<&mut RouteConfig> {
+        self.groups.iter_mut().flatten().collect()
+    }
+}
@@ -0,0 +1,236 @@
+use crate::base::RouteableComponent;
+use crate::utils::time::unix_millis;
+use crate::utils::update::{chat_id, update_type, user_id, UPDATE_TYPES};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+
+/// Token bucket refilled with `rate` tokens per second, holding at most `burst`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub rate: f64,
+    pub burst: u32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default)]
+pub struct FloodLimits {
+    /// Bucket per `from.id`.
+    pub per_user: Option<RateLimit>,
+    /// Bucket per `chat.id`.
+    pub per_chat: Option<RateLimit>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Scope {
+    User,
+    Chat,
+}
+
+impl Scope {
+    fn name(&self) -> &'static str {
+        match self {
+            Scope::User => "user",
+            Scope::Chat => "chat",
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    limit: RateLimit,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst as f64);
+        self.updated = now;
+    }
+}
+
+struct Offender {
+    dropped: u64,
+    last_drop: u64,
+}
+
+/// Buckets are keyed by scope, id and the update type they count ("*" when
+/// the type has no limits of its own).
+type BucketKey = (Scope, i64, &'static str);
+
+#[derive(Default)]
+struct FloodState {
+    buckets: HashMap<BucketKey, Bucket>,
+    offenders: HashMap<(Scope, i64), Offender>,
+    checks: u64,
+}
+
+/// How long an offender stays in the report after its last dropped update.
+const OFFENDER_TTL: Duration = Duration::from_secs(3600);
+
+/// Rate limit stage in front of the route tree. Updates over the limit of
+/// their user or chat are dropped, or handed to the quarantine route if one is set.
+pub struct FloodControl {
+    limits: FloodLimits,
+    per_type: HashMap<&'static str, FloodLimits>,
+    allowlist: HashSet<i64>,
+    quarantine: Option<Arc<dyn RouteableComponent>>,
+    state: Mutex<FloodState>,
+    passed: AtomicU64,
+    limited: AtomicU64,
+}
+
+impl FloodControl {
+    pub fn new(limits: FloodLimits) -> Self {
+        Self {
+            limits,
+            per_type: HashMap::new(),
+            allowlist: HashSet::new(),
+            quarantine: None,
+            state: Mutex::new(FloodState::default()),
+            passed: AtomicU64::new(0),
+            limited: AtomicU64::new(0),
+        }
+    }
+
+    /// Separate limits for one update type (`"message"`, `"callback_query"`, ...).
+    /// These updates get their own buckets and do not use up the shared ones.
+    pub fn set_type_limits(&mut self, kind: &str, limits: FloodLimits) -> Result<(), String> {
+        let kind = UPDATE_TYPES.iter()
+            .find(|t| **t == kind)
+            .ok_or_else(|| format!("unknown update type {}", kind))?;
+        self.per_type.insert(kind, limits);
+        Ok(())
+    }
+
+    /// User or chat ids that are never limited.
+    pub fn set_allowlist(&mut self, ids: Vec<i64>) {
+        self.allowlist = ids.into_iter().collect();
+    }
+
+    pub fn set_quarantine(&mut self, route: Arc<dyn RouteableComponent>) {
+        self.quarantine = Some(route);
+    }
+
+    pub fn quarantine(&self) -> Option<&Arc<dyn RouteableComponent>> {
+        self.quarantine.as_ref()
+    }
+
+    /// Takes a token for the update. Returns `false` when it is over the limit,
+    /// in which case it was already sent to quarantine or dropped.
+    pub fn admit(&self, update: &Value) -> bool {
+        let user = user_id(update);
+        let chat = chat_id(update);
+        if [user, chat].iter().flatten().any(|id| self.allowlist.contains(id)) {
+            self.passed.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+
+        let kind = update_type(update);
+        let (limits, bucket_kind) = match kind.and_then(|k| self.per_type.get_key_value(k)) {
+            Some((kind, limits)) => (*limits, *kind),
+            None => (self.limits, "*"),
+        };
+
+        let checks = [(Scope::User, user, limits.per_user), (Scope::Chat, chat, limits.per_chat)];
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        state.checks += 1;
+        if state.checks.is_multiple_of(1024) {
+            Self::prune(&mut state, now);
+        }
+
+        // check every bucket before taking tokens, so an exhausted chat bucket does not also cost the user one
+        let mut offender = None;
+        for (scope, id, limit) in checks {
+            let (Some(id), Some(limit)) = (id, limit) else { continue };
+            let bucket = state.buckets.entry((scope, id, bucket_kind)).or_insert_with(|| Bucket {
+                tokens: limit.burst as f64,
+                updated: now,
+                limit,
+            });
+            bucket.refill(now);
+            if bucket.tokens < 1.0 && offender.is_none() {
+                offender = Some((scope, id));
+            }
+        }
+
+        if let Some(key) = offender {
+            let entry = state.offenders.entry(key).or_insert(Offender { dropped: 0, last_drop: 0 });
+            entry.dropped += 1;
+            entry.last_drop = unix_millis();
+            drop(state);
+
+            self.limited.fetch_add(1, Ordering::Relaxed);
+            if let Some(quarantine) = &self.quarantine {
+                let quarantine = quarantine.clone();
+                let update = update.clone();
+                tokio::spawn(async move {
+                    quarantine.process(update).await;
+                });
+            }
+            return false;
+        }
+
+        for (scope, id, limit) in checks {
+            if let (Some(id), Some(_)) = (id, limit) {
+                if let Some(bucket) = state.buckets.get_mut(&(scope, id, bucket_kind)) {
+                    bucket.tokens -= 1.0;
+                }
+            }
+        }
+        self.passed.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Forgets buckets that have refilled completely and offenders that went quiet.
+    fn prune(state: &mut FloodState, now: Instant) {
+        state.buckets.retain(|_, bucket| {
+            bucket.refill(now);
+            bucket.tokens < bucket.limit.burst as f64
+        });
+
+        let cutoff = unix_millis().saturating_sub(OFFENDER_TTL.as_millis() as u64);
+        state.offenders.retain(|_, offender| offender.last_drop >= cutoff);
+    }
+
+    pub fn json_struct(&self) -> Value {
+        let state = self.state.lock().unwrap();
+
+        let mut offenders: Vec<_> = state.offenders.iter().collect();
+        offenders.sort_by_key(|(_, offender)| std::cmp::Reverse(offender.dropped));
+        let offenders: Vec<Value> = offenders.into_iter().take(100).map(|((scope, id), offender)| json!({
+            "scope": scope.name(),
+            "id": id,
+            "dropped": offender.dropped,
+            "last_drop": offender.last_drop
+        })).collect();
+
+        let limits_json = |limits: &FloodLimits| json!({
+            "per_user": limits.per_user.map(|l| json!({ "rate": l.rate, "burst": l.burst })),
+            "per_chat": limits.per_chat.map(|l| json!({ "rate": l.rate, "burst": l.burst }))
+        });
+        let per_type: serde_json::Map<String, Value> = self.per_type.iter()
+            .map(|(kind, limits)| (kind.to_string(), limits_json(limits)))
+            .collect();
+
+        json!({
+            "options": {
+                "limits": limits_json(&self.limits),
+                "per_type": per_type,
+                "allowlist": self.allowlist,
+                "quarantine": self.quarantine.is_some()
+            },
+            "passed": self.passed.load(Ordering::Relaxed),
+            "limited": self.limited.load(Ordering::Relaxed),
+            "offenders": offenders
+        })
+    }
+}
@@ -0,0 +1,39 @@
+pub mod ordered;
+pub mod flood;
+
+use crate::base::RouteableComponent;
+use crate::dispatch::ordered::OrderedDispatcher;
+
+use serde_json::Value;
+use std::sync::Arc;
+
+
+/// Hands updates coming out of the updaters to the root route.
+pub enum Dispatcher {
+    /// Every update runs in its own task, arrival order is not kept.
+    Unordered(Arc<dyn RouteableComponent>),
+    /// Updates with the same key run one after another.
+    Ordered(Arc<OrderedDispatcher>),
+}
+
+impl Dispatcher {
+    /// Waits while an ordered dispatcher has no free lane.
+    pub async fn dispatch(&self, update: Value) {
+        match self {
+            Dispatcher::Unordered(route) => {
+                let route = route.clone();
+                tokio::spawn(async move {
+                    route.process(update).await;
+                });
+            }
+            Dispatcher::Ordered(ordered) => ordered.dispatch(update).await,
+        }
+    }
+
+    pub fn ordered(&self) -> Option<&OrderedDispatcher> {
+        match self {
+            Dispatcher::Unordered(_) => None,
+            Dispatcher::Ordered(ordered) => Some(ordered),
+        }
+    }
+}
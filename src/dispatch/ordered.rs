@@ -0,0 +1,151 @@
+use crate::base::RouteableComponent;
+use crate::utils::update::{chat_id, user_id};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum OrderingKey {
+    /// `chat.id` of the update, callback queries use the chat of their message.
+    #[default]
+    Chat,
+    /// `from.id` of the update.
+    User,
+}
+
+impl OrderingKey {
+    pub fn extract(&self, update: &Value) -> Option<i64> {
+        match self {
+            OrderingKey::Chat => chat_id(update),
+            OrderingKey::User => user_id(update),
+        }
+    }
+}
+
+/// Processes updates that share a key strictly in arrival order while different
+/// keys run in parallel. At most `lanes` keys (or keyless updates) are in the
+/// route tree at once, each buffering at most `queue_limit` updates behind the
+/// running one. When every lane is taken `dispatch` waits for one, so the
+/// backlog stays in the bounded update queue instead of piling up here.
+pub struct OrderedDispatcher {
+    route: Arc<dyn RouteableComponent>,
+    key: OrderingKey,
+    lanes: usize,
+    queue_limit: usize,
+    free: Arc<Semaphore>,
+    // a key is present while it holds a lane
+    pending: Mutex<HashMap<i64, VecDeque<Value>>>,
+    dropped: AtomicU64,
+}
+
+impl OrderedDispatcher {
+    pub fn new(route: Arc<dyn RouteableComponent>, key: OrderingKey, lanes: usize, queue_limit: usize) -> Self {
+        let lanes = lanes.max(1);
+        Self {
+            route,
+            key,
+            lanes,
+            queue_limit,
+            free: Arc::new(Semaphore::new(lanes)),
+            pending: Mutex::new(HashMap::new()),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    pub async fn dispatch(self: &Arc<Self>, update: Value) {
+        let Some(key) = self.key.extract(&update) else {
+            // nothing to order by, e.g. inline queries when ordering by chat
+            let lane = self.lane().await;
+            let this = self.clone();
+            tokio::spawn(async move {
+                this.route.process(update).await;
+                drop(lane);
+            });
+            return;
+        };
+
+        let update = match self.enqueue(key, update) {
+            Ok(()) => return,
+            Err(update) => update,
+        };
+
+        let lane = self.lane().await;
+        {
+            // another `dispatch` may have claimed the key while this one
+            // waited for a lane
+            let mut pending = self.pending.lock().unwrap();
+            if let Some(queue) = pending.get_mut(&key) {
+                self.push(queue, update);
+                return;
+            }
+            pending.insert(key, VecDeque::new());
+        }
+
+        let this = self.clone();
+        tokio::spawn(async move {
+            this.run(key, update).await;
+            drop(lane);
+        });
+    }
+
+    async fn lane(&self) -> OwnedSemaphorePermit {
+        self.free.clone().acquire_owned().await.expect("Lanes are never closed")
+    }
+
+    /// Queues `update` behind the running one of `key`, hands it back when
+    /// `key` has no lane yet.
+    fn enqueue(&self, key: i64, update: Value) -> Result<(), Value> {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.get_mut(&key) {
+            Some(queue) => {
+                self.push(queue, update);
+                Ok(())
+            }
+            None => Err(update),
+        }
+    }
+
+    fn push(&self, queue: &mut VecDeque<Value>, update: Value) {
+        if queue.len() >= self.queue_limit {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        } else {
+            queue.push_back(update);
+        }
+    }
+
+    async fn run(&self, key: i64, first: Value) {
+        let mut update = first;
+        loop {
+            self.route.process(update).await;
+
+            let mut pending = self.pending.lock().unwrap();
+            match pending.get_mut(&key).and_then(|queue| queue.pop_front()) {
+                Some(next) => update = next,
+                None => {
+                    pending.remove(&key);
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn json_struct(&self) -> Value {
+        let pending = self.pending.lock().unwrap();
+        json!({
+            "key": self.key,
+            "lanes": self.lanes,
+            "queue_limit": self.queue_limit,
+            "busy_lanes": self.lanes - self.free.available_permits(),
+            "keys": pending.len(),
+            "queued": pending.values().map(VecDeque::len).sum::<usize>(),
+            "dropped": self.dropped.load(Ordering::Relaxed)
+        })
+    }
+}
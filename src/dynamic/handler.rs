@@ -1,77 +1,61 @@
 
-use axum::http::{Method, header::CONTENT_TYPE};
-use axum::{extract::{Request}, Json}; 
+use axum::{extract::{Request, State}, http::StatusCode, response::Response, Json}; 
 use serde_json::{json, Value};
+use tokio::sync::mpsc::Sender;
+use tower::ServiceExt;
 
-use crate::dynamic::longpoll_registry::LONGPOLL_REGISTRY;
-
-use crate::route::longpull::GetUpdatesParams;
+use crate::dynamic::router::{DYNAMIC_INGRESS, DYNAMIC_ROUTER};
 
 
 pub async fn dynamic_handler(
+    State(tx): State<Sender<Value>>,
     request: Request, 
-) -> Json<Value> {
-    let (parts, body) = request.into_parts();
-    
-    let method = parts.method;
-    let uri = parts.uri;
-    let headers = parts.headers;
-    let path = uri.path().to_string();
-
-    let body_bytes = match axum::body::to_bytes(body, usize::MAX).await {
-        Ok(b) => b,
-        Err(_) => return Json(json!({
-            "ok": false,
-            "error_code": 400,
-            "description": "failed to read request body"
-        })),
-    };
+) -> Response {
+    let router = DYNAMIC_ROUTER.read().expect("Registry lock poisoned").clone();
 
-    if method != Method::POST {
-        return Json(json!({ 
-            "ok": false, 
-            "error_code": 405, 
-            "description": "method not allowed" 
-        }));
+    match router.with_state(tx).oneshot(request).await {
+        Ok(response) => response,
+        Err(infallible) => match infallible {},
     }
+}
 
-    let content_type = headers
-        .get(CONTENT_TYPE)
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("");
 
-    let params: GetUpdatesParams = if content_type.contains("application/json") {
-        match serde_json::from_slice(&body_bytes) {
-            Ok(p) => p,
-            Err(_) => return Json(json!({ 
-                "ok": false, 
-                "error_code": 400, 
-                "description": "invalid json body" 
-            })),
-        }
-    } else {
-        match serde_urlencoded::from_bytes(&body_bytes) {
-            Ok(p) => p,
-            Err(_) => GetUpdatesParams { 
-                offset: None, 
-                timeout: None, 
-                limit: None 
-            },
-        }
-    };
+/// Fallback of listeners serving ingress, `WebhookUpdate`s added through the API.
+pub async fn dynamic_ingress_handler(
+    State(tx): State<Sender<Value>>,
+    request: Request,
+) -> Response {
+    let router = DYNAMIC_INGRESS.read().expect("Registry lock poisoned").clone();
 
-    let route = {
-        let registry = LONGPOLL_REGISTRY.read().expect("Registry lock poisoned");
-        registry.get(&path).cloned()
-    };
-
-    if let Some(route) = route {
-        return route.handle_request(params).await;
+    match router.with_state(tx).oneshot(request).await {
+        Ok(response) => response,
+        Err(infallible) => match infallible {},
+    }
+}
+
+/// Fallback of listeners serving both, ingress paths win.
+pub async fn dynamic_any_handler(
+    State(tx): State<Sender<Value>>,
+    request: Request,
+) -> Response {
+    let router = DYNAMIC_INGRESS.read().expect("Registry lock poisoned").clone();
+
+    match router.fallback(dynamic_handler).with_state(tx).oneshot(request).await {
+        Ok(response) => response,
+        Err(infallible) => match infallible {},
     }
+}
+
+
+pub async fn ingress_not_found() -> StatusCode {
+    StatusCode::NOT_FOUND
+}
+
 
+pub async fn not_found(request: Request) -> Json<Value> {
     Json(json!({ 
         "ok": false, 
         "error_code": 404, 
-        "description": format!("Path {} not found in dynamic registry", path)
+        "description": format!("Path {} not found in dynamic registry", request.uri().path())
     }))
-}
\ No newline at end of file
+}
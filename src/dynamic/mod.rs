@@ -1,2 +1,2 @@
-pub mod longpoll_registry;
-pub mod handler;
\ No newline at end of file
+pub mod router;
+pub mod handler;
@@ -0,0 +1,114 @@
+use axum::Router;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc::Sender;
+
+use crate::base::{RouteableComponent, UpdaterComponent};
+use crate::dynamic::handler::{ingress_not_found, not_found};
+
+
+pub static DYNAMIC_ROUTER: Lazy<RwLock<Router<Sender<Value>>>> = Lazy::new(|| RwLock::new(Router::new().fallback(not_found)));
+
+/// Endpoints of updaters added through the API, e.g. `WebhookUpdate` paths.
+pub static DYNAMIC_INGRESS: Lazy<RwLock<Router<Sender<Value>>>> = Lazy::new(|| RwLock::new(Router::new().fallback(ingress_not_found)));
+
+/// Paths mounted into either registry. A listener serving both asks the
+/// ingress one first, so a path may only be in one of them.
+static DYNAMIC_PATHS: Lazy<RwLock<HashSet<String>>> = Lazy::new(|| RwLock::new(HashSet::new()));
+
+
+/// Paths the static routers of an instance serve. The registries are only
+/// their fallback, a path in both would never reach the dynamic one.
+#[derive(Default)]
+pub struct StaticPaths(HashSet<String>);
+
+impl StaticPaths {
+    pub fn extend(&mut self, paths: Vec<String>) {
+        self.0.extend(paths.iter().map(|path| route_key(path)));
+    }
+}
+
+/// `/a/:id` and `/a/:name` are the same route to axum.
+fn route_key(path: &str) -> String {
+    path.split('/')
+        .map(|segment| match segment.chars().next() {
+            Some(':') => ":",
+            Some('*') => "*",
+            _ => segment,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Rejects what axum would panic on.
+fn check_path(path: &str) -> Result<(), String> {
+    if !path.starts_with('/') {
+        return Err(format!("path {} does not start with /", path));
+    }
+    let segments: Vec<&str> = path.split('/').collect();
+    for (index, segment) in segments.iter().enumerate() {
+        let invalid = matches!(*segment, ":" | "*")
+            || segment.get(1..).is_some_and(|rest| rest.contains([':', '*']))
+            || (segment.starts_with('*') && index + 1 < segments.len());
+        if invalid {
+            return Err(format!("path {} is not a valid route", path));
+        }
+    }
+    Ok(())
+}
+
+/// Takes the paths of a component about to be mounted, or names the first
+/// one that is taken already.
+fn claim(paths: &[String], taken: &StaticPaths) -> Result<(), String> {
+    let mut dynamic = DYNAMIC_PATHS.write().expect("Registry lock poisoned");
+    let mut claimed = HashSet::new();
+    for path in paths {
+        check_path(path)?;
+        let key = route_key(path);
+        if taken.0.contains(&key) || dynamic.contains(&key) || !claimed.insert(key) {
+            return Err(format!("path {} is already taken", path));
+        }
+    }
+    dynamic.extend(claimed);
+    Ok(())
+}
+
+
+/// The routes mounted so far, `restore` goes back to them when a change that
+/// mounted more is undone.
+pub struct Mounted {
+    router: Router<Sender<Value>>,
+    paths: HashSet<String>,
+}
+
+pub fn snapshot() -> Mounted {
+    Mounted {
+        router: DYNAMIC_ROUTER.read().expect("Registry lock poisoned").clone(),
+        paths: DYNAMIC_PATHS.read().expect("Registry lock poisoned").clone(),
+    }
+}
+
+pub fn restore(mounted: Mounted) {
+    *DYNAMIC_ROUTER.write().expect("Registry lock poisoned") = mounted.router;
+    *DYNAMIC_PATHS.write().expect("Registry lock poisoned") = mounted.paths;
+}
+
+pub async fn mount(route: Arc<dyn RouteableComponent>, taken: &StaticPaths) -> Result<(), String> {
+    claim(&route.paths().await, taken)?;
+
+    let router = DYNAMIC_ROUTER.read().expect("Registry lock poisoned").clone();
+    let router = route.set_server(router).await;
+    *DYNAMIC_ROUTER.write().expect("Registry lock poisoned") = router;
+    Ok(())
+}
+
+pub async fn mount_ingress(updater: Arc<dyn UpdaterComponent>, taken: &StaticPaths) -> Result<(), String> {
+    claim(&updater.paths().await, taken)?;
+
+    let router = DYNAMIC_INGRESS.read().expect("Registry lock poisoned").clone();
+    let router = updater.set_server(router).await;
+    *DYNAMIC_INGRESS.write().expect("Registry lock poisoned") = router;
+    Ok(())
+}
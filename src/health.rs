@@ -0,0 +1,133 @@
+use crate::base::RouteableComponent;
+use crate::update::manager::Updaters;
+use crate::config::schema::TginConfig;
+use crate::listener::{ListenAddr, ListenerComponent};
+
+use axum::{http::StatusCode, routing::get, Json, Router};
+use serde_json::{json, Value};
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::Sender;
+
+
+pub const LIVENESS_PATH: &str = "/healthz";
+pub const READINESS_PATH: &str = "/readyz";
+
+/// State behind the probe endpoints. `/healthz` answers as long as the
+/// runtime serves requests, `/readyz` once the listeners are bound, every
+/// updater receives updates and the route tree is healthy.
+pub struct Health {
+    started: Instant,
+    listening: AtomicBool,
+    updaters: Arc<Updaters>,
+    route: Arc<dyn RouteableComponent>,
+}
+
+impl Health {
+    pub fn new(updaters: Arc<Updaters>, route: Arc<dyn RouteableComponent>) -> Self {
+        Self {
+            started: Instant::now(),
+            listening: AtomicBool::new(false),
+            updaters,
+            route,
+        }
+    }
+
+    /// Called once every listener is bound.
+    pub fn set_listening(&self) {
+        self.listening.store(true, Ordering::SeqCst);
+    }
+
+    pub fn router(self: &Arc<Self>) -> Router<Sender<Value>> {
+        let live = self.clone();
+        let ready = self.clone();
+        Router::new()
+            .route(LIVENESS_PATH, get(move || {
+                let live = live.clone();
+                async move { Json(live.liveness()) }
+            }))
+            .route(READINESS_PATH, get(move || {
+                let ready = ready.clone();
+                async move { ready.readiness().await }
+            }))
+    }
+
+    pub fn liveness(&self) -> Value {
+        json!({
+            "status": "ok",
+            "uptime_secs": self.started.elapsed().as_secs(),
+        })
+    }
+
+    pub async fn readiness(&self) -> (StatusCode, Json<Value>) {
+        let listening = self.listening.load(Ordering::SeqCst);
+
+        let mut updates = Vec::new();
+        let mut updates_ready = true;
+        // paused updaters are not expected to receive anything
+        for update in self.updaters.active() {
+            let ready = update.is_ready();
+            updates_ready &= ready;
+            updates.push(json!({ "name": update.print().await.trim(), "ready": ready }));
+        }
+        let route = self.route.is_healthy().await;
+
+        let ready = listening && updates_ready && route;
+        let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+        (status, Json(json!({
+            "status": if ready { "ready" } else { "not_ready" },
+            "checks": {
+                "config": true,
+                "listeners": listening,
+                "updates": updates,
+                "route": route,
+            },
+        })))
+    }
+}
+
+
+/// Base URL of the first listener of `config` that serves the probes, with
+/// wildcard addresses replaced by loopback. Unix sockets are skipped.
+pub fn local_url(config: &TginConfig) -> Option<String> {
+    if let Some(port) = config.server_port {
+        let scheme = if config.ssl.is_some() { "https" } else { "http" };
+        return Some(format!("{}://127.0.0.1:{}", scheme, port));
+    }
+    config.listeners.iter()
+        .filter(|listener| listener.serve.contains(&ListenerComponent::Health))
+        .find_map(|listener| {
+            let ListenAddr::Tcp(mut addr) = listener.address.parse().ok()? else {
+                return None;
+            };
+            match addr.ip() {
+                IpAddr::V4(ip) if ip.is_unspecified() => addr.set_ip(Ipv4Addr::LOCALHOST.into()),
+                IpAddr::V6(ip) if ip.is_unspecified() => addr.set_ip(Ipv6Addr::LOCALHOST.into()),
+                _ => {}
+            }
+            let scheme = if listener.ssl.is_some() { "https" } else { "http" };
+            Some(format!("{}://{}", scheme, addr))
+        })
+}
+
+/// Calls a probe endpoint, for `tgin health`. Certificates are not verified:
+/// the probe runs next to the server and only checks that it answers.
+pub async fn probe(url: &str) -> Result<Value, String> {
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_secs(5))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let response = client.get(url).send().await.map_err(|e| format!("{}: {}", url, e))?;
+    let status = response.status();
+    let body: Value = response.json().await.unwrap_or(Value::Null);
+    if status.is_success() {
+        Ok(body)
+    } else {
+        Err(format!("{} answered {}: {}", url, status, body))
+    }
+}
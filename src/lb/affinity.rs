@@ -0,0 +1,208 @@
+use crate::utils::update::payload;
+
+use axum::{
+    body::Body,
+    extract::{DefaultBodyLimit, Path, State},
+    http::{header::{CONTENT_LENGTH, CONTENT_TYPE}, HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing::any,
+    Router,
+};
+use reqwest::Client;
+use serde_json::{json, Value};
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::Sender;
+
+
+type MessageKey = (i64, i64);
+
+/// Under the proxy path.
+const PROXY_ROUTE: &str = "/:index/:bot/:method";
+
+struct Table {
+    entries: HashMap<MessageKey, (usize, Instant)>,
+    /// Keys in the order they were pinned. A key pinned again is listed twice,
+    /// only the position carrying the time of its entry counts.
+    order: VecDeque<(MessageKey, Instant)>,
+}
+
+/// Remembers which child route a message belongs to, so callback queries for
+/// it go back to the replica that has its context. Entries come from delivered
+/// messages and from messages the replicas send through the Bot API proxy.
+pub struct Affinity {
+    table: Mutex<Table>,
+    ttl: Duration,
+    max_entries: usize,
+    proxy: Option<(String, String)>,
+    client: Client,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Affinity {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            table: Mutex::new(Table { entries: HashMap::new(), order: VecDeque::new() }),
+            ttl,
+            max_entries: 100_000,
+            proxy: None,
+            client: Client::new(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn set_max_entries(&mut self, max_entries: usize) {
+        self.max_entries = max_entries.max(1);
+    }
+
+    pub fn set_client(&mut self, client: Client) {
+        self.client = client;
+    }
+
+    /// Serves `{path}/<route index>/bot<token>/<method>` and forwards it to
+    /// `api_url`. Messages in the response are pinned to that route.
+    pub fn set_proxy(&mut self, path: String, api_url: String) {
+        self.proxy = Some((path.trim_end_matches('/').to_string(), api_url.trim_end_matches('/').to_string()));
+    }
+
+    /// Route index pinned for the message a callback query was pressed on.
+    pub fn lookup(&self, update: &Value) -> Option<usize> {
+        let message = update.get("callback_query")?.get("message")?;
+        let key = Self::message_key(message)?;
+
+        let mut table = self.table.lock().unwrap();
+        let found = match table.entries.get(&key) {
+            Some((index, at)) if at.elapsed() < self.ttl => Some(*index),
+            Some(_) => {
+                table.entries.remove(&key);
+                None
+            }
+            None => None,
+        };
+        drop(table);
+
+        let counter = if found.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    /// Pins an incoming message to the route it was delivered to.
+    pub fn record_update(&self, update: &Value, index: usize) {
+        if let Some(("message" | "channel_post" | "business_message", message)) = payload(update) {
+            self.record(message, index);
+        }
+    }
+
+    fn record(&self, message: &Value, index: usize) {
+        let Some(key) = Self::message_key(message) else { return };
+        let mut table = self.table.lock().unwrap();
+
+        // oldest first, so expired entries, stale positions and, when the table
+        // is full, the entry to evict are all at the front
+        while let Some(&(oldest, at)) = table.order.front() {
+            let current = table.entries.get(&oldest).is_some_and(|(_, pinned)| *pinned == at);
+            if current && at.elapsed() < self.ttl && table.entries.len() < self.max_entries {
+                break;
+            }
+            table.order.pop_front();
+            if current {
+                table.entries.remove(&oldest);
+            }
+        }
+
+        let now = Instant::now();
+        table.entries.insert(key, (index, now));
+        table.order.push_back((key, now));
+    }
+
+    fn message_key(message: &Value) -> Option<MessageKey> {
+        let chat = message.get("chat")?.get("id")?.as_i64()?;
+        let message_id = message.get("message_id")?.as_i64()?;
+        Some((chat, message_id))
+    }
+
+    pub fn set_server(self: &Arc<Self>, router: Router<Sender<Value>>) -> Router<Sender<Value>> {
+        let Some((path, _)) = &self.proxy else {
+            return router;
+        };
+        // uploads are streamed through, Telegram takes up to 50 MB and a local Bot API server more
+        let proxy = Router::new()
+            .route(PROXY_ROUTE, any(proxy_call))
+            .layer(DefaultBodyLimit::disable())
+            .with_state(self.clone());
+        router.nest(path, proxy)
+    }
+
+    pub fn paths(&self) -> Vec<String> {
+        self.proxy.iter().map(|(path, _)| format!("{}{}", path, PROXY_ROUTE)).collect()
+    }
+
+    pub fn json_struct(&self) -> Value {
+        json!({
+            "ttl": self.ttl.as_secs(),
+            "max_entries": self.max_entries,
+            "proxy": self.proxy.as_ref().map(|(path, _)| path),
+            "entries": self.table.lock().unwrap().entries.len(),
+            "hits": self.hits.load(Ordering::Relaxed),
+            "misses": self.misses.load(Ordering::Relaxed)
+        })
+    }
+}
+
+async fn proxy_call(
+    State(affinity): State<Arc<Affinity>>,
+    Path((index, bot, method)): Path<(usize, String, String)>,
+    request_method: Method,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    let Some((_, api_url)) = &affinity.proxy else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    // reqwest is on a different `http` major than axum, go through the raw values
+    let request_method = reqwest::Method::from_bytes(request_method.as_str().as_bytes()).unwrap_or(reqwest::Method::POST);
+    let mut request = affinity.client.request(request_method, format!("{}/{}/{}", api_url, bot, method));
+    if let Some(content_type) = headers.get(CONTENT_TYPE) {
+        request = request.header("content-type", content_type.as_bytes());
+    }
+    // keeps the streamed upload from being sent chunked
+    if let Some(length) = headers.get(CONTENT_LENGTH) {
+        request = request.header("content-length", length.as_bytes());
+    }
+
+    let body = reqwest::Body::wrap_stream(body.into_data_stream());
+    let response = match request.body(body).send().await {
+        Ok(response) => response,
+        Err(err) => return (
+            StatusCode::BAD_GATEWAY,
+            axum::Json(json!({ "ok": false, "error_code": 502, "description": err.to_string() })),
+        ).into_response(),
+    };
+
+    let status = StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    let content_type = response.headers().get("content-type")
+        .and_then(|v| HeaderValue::from_bytes(v.as_bytes()).ok());
+    let bytes = response.bytes().await.unwrap_or_default();
+
+    // sendMessage, sendPhoto, ... return the Message, sendMediaGroup a list of them
+    if let Ok(parsed) = serde_json::from_slice::<Value>(&bytes) {
+        match parsed.get("result") {
+            Some(Value::Array(messages)) => messages.iter().for_each(|m| affinity.record(m, index)),
+            Some(message) => affinity.record(message, index),
+            None => {}
+        }
+    }
+
+    let mut response = (status, bytes).into_response();
+    if let Some(content_type) = content_type {
+        response.headers_mut().insert(CONTENT_TYPE, content_type);
+    }
+    response
+}
@@ -16,6 +16,8 @@ pub struct AllLB {
 }
 
 impl AllLB {
+    pub const KIND: &'static str = "AllLB";
+
     pub fn new(routes: Vec<Arc<dyn RouteableComponent>>) -> Self {
         Self {
             routes: RwLock::new(routes),
@@ -36,6 +38,16 @@ impl Routeable for AllLB {
             });
         }
     }
+
+    async fn is_healthy(&self) -> bool {
+        let routes = self.routes.read().await;
+        for route in routes.iter() {
+            if route.is_healthy().await {
+                return true;
+            }
+        }
+        false
+    }
 }
 
 #[async_trait]
@@ -47,6 +59,14 @@ impl Serverable for AllLB {
         }
         router
     }
+
+    async fn paths(&self) -> Vec<String> {
+        let mut paths = Vec::new();
+        for route in self.routes.read().await.iter() {
+            paths.extend(route.paths().await);
+        }
+        paths
+    }
 }
 
 #[async_trait]
@@ -71,6 +91,7 @@ impl Printable for AllLB {
         json!({
             "type": "load-balancer",
             "name": "all",
+            "kind": Self::KIND,
             "routes": routes_json
         })
     }
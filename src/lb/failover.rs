@@ -0,0 +1,248 @@
+
+use crate::base::{Routeable, RouteableComponent, Serverable, Printable};
+
+use tokio::sync::mpsc::Sender;
+use axum::Router;
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+use serde_json::{Value, json};
+
+
+/// Routes are grouped by priority. Traffic goes to the first group that has a
+/// healthy member, round robin inside the group. An update the group could not
+/// deliver falls through to the next group.
+pub struct FailoverLB {
+    groups: Vec<Vec<Arc<dyn RouteableComponent>>>,
+    cursors: Vec<AtomicUsize>,
+    recover_after: Duration,
+    state: Mutex<FailoverState>,
+    failovers: AtomicU64,
+    dropped: AtomicU64,
+}
+
+struct FailoverState {
+    active: usize,
+    /// When each group last turned healthy, used to hold off failback until it stays up.
+    healthy_since: Vec<Option<Instant>>,
+}
+
+impl FailoverLB {
+    pub const KIND: &'static str = "FailoverLB";
+
+    pub fn new(groups: Vec<Vec<Arc<dyn RouteableComponent>>>) -> Self {
+        let cursors = groups.iter().map(|_| AtomicUsize::new(0)).collect();
+        let healthy_since = groups.iter().map(|_| Some(Instant::now())).collect();
+        Self {
+            groups,
+            cursors,
+            recover_after: Duration::from_secs(30),
+            state: Mutex::new(FailoverState { active: 0, healthy_since }),
+            failovers: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// How long a higher priority group has to stay healthy before traffic moves back to it.
+    pub fn set_recover_after(&mut self, recover_after: Duration) {
+        self.recover_after = recover_after;
+    }
+
+    async fn group_healthy(&self, group: usize) -> bool {
+        for route in &self.groups[group] {
+            if route.is_healthy().await {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Refreshes group health and returns the group traffic should start at.
+    async fn select(&self) -> usize {
+        let mut health = Vec::with_capacity(self.groups.len());
+        for group in 0..self.groups.len() {
+            health.push(self.group_healthy(group).await);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        for (since, healthy) in state.healthy_since.iter_mut().zip(&health) {
+            match (*healthy, since.is_some()) {
+                (true, false) => *since = Some(Instant::now()),
+                (false, true) => *since = None,
+                _ => {}
+            }
+        }
+
+        let recovered = |group: usize| state.healthy_since[group].is_some_and(|s| s.elapsed() >= self.recover_after);
+        let active = state.active;
+        let next = match (0..active).find(|&g| recovered(g)) {
+            Some(group) => group,
+            None if health.get(active).copied().unwrap_or(false) => active,
+            None => health.iter().position(|h| *h).unwrap_or(active),
+        };
+        if next != active {
+            state.active = next;
+            if next > active {
+                self.failovers.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        next
+    }
+
+    /// Tries the members of one group in round robin order, unhealthy ones last.
+    async fn try_group(&self, group: usize, mut update: Value) -> Result<(), Value> {
+        let routes = &self.groups[group];
+        if routes.is_empty() {
+            return Err(update);
+        }
+
+        let start = self.cursors[group].fetch_add(1, Ordering::Relaxed);
+        let mut standby = Vec::new();
+        for offset in 0..routes.len() {
+            let route = &routes[(start + offset) % routes.len()];
+            if !route.is_healthy().await {
+                standby.push(route);
+                continue;
+            }
+            match route.try_process(update).await {
+                Ok(()) => return Ok(()),
+                Err(back) => update = back,
+            }
+        }
+        for route in standby {
+            match route.try_process(update).await {
+                Ok(()) => return Ok(()),
+                Err(back) => update = back,
+            }
+        }
+        Err(update)
+    }
+
+    fn mark_failed(&self, group: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.healthy_since[group] = None;
+        if state.active == group && group + 1 < self.groups.len() {
+            state.active = group + 1;
+            self.failovers.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[async_trait]
+impl Routeable for FailoverLB {
+    async fn process(&self, update: Value) {
+        if let Err(update) = self.try_process(update).await {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            eprintln!("Failover: no group could take update {}", update.get("update_id").unwrap_or(&Value::Null));
+        }
+    }
+
+    /// The batch goes to one healthy member of the active group; delivery
+    /// failures inside it are not retried on other groups.
+    async fn process_batch(&self, updates: Vec<Value>) {
+        let start = self.select().await;
+        for group in start..self.groups.len() {
+            let routes = &self.groups[group];
+            let first = self.cursors[group].fetch_add(1, Ordering::Relaxed);
+            for offset in 0..routes.len() {
+                let route = &routes[(first + offset) % routes.len()];
+                if route.is_healthy().await {
+                    route.process_batch(updates).await;
+                    return;
+                }
+            }
+        }
+        self.dropped.fetch_add(updates.len() as u64, Ordering::Relaxed);
+        eprintln!("Failover: no healthy route for a batch of {} updates", updates.len());
+    }
+
+    async fn try_process(&self, mut update: Value) -> Result<(), Value> {
+        let start = self.select().await;
+        for group in start..self.groups.len() {
+            match self.try_group(group, update).await {
+                Ok(()) => return Ok(()),
+                Err(back) => {
+                    self.mark_failed(group);
+                    update = back;
+                }
+            }
+        }
+        Err(update)
+    }
+
+    async fn is_healthy(&self) -> bool {
+        for group in 0..self.groups.len() {
+            if self.group_healthy(group).await {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[async_trait]
+impl Serverable for FailoverLB {
+    async fn set_server(&self, mut router: Router<Sender<Value>>) -> Router<Sender<Value>> {
+        for route in self.groups.iter().flatten() {
+            router = route.set_server(router).await;
+        }
+        router
+    }
+
+    async fn paths(&self) -> Vec<String> {
+        let mut paths = Vec::new();
+        for route in self.groups.iter().flatten() {
+            paths.extend(route.paths().await);
+        }
+        paths
+    }
+}
+
+#[async_trait]
+impl Printable for FailoverLB {
+    async fn print(&self) -> String {
+        let active = self.state.lock().unwrap().active;
+        let mut text = String::from("LOAD BALANCER Failover\n\n");
+
+        for (index, group) in self.groups.iter().enumerate() {
+            let marker = if index == active { " (active)" } else { "" };
+            text.push_str(&format!("priority {}{}\n\n", index, marker));
+            for route in group {
+                text.push_str(&format!("{}\n\n", route.print().await));
+            }
+        }
+        text
+    }
+
+    async fn json_struct(&self) -> Value {
+        let mut groups_json: Vec<Value> = Vec::new();
+        for (index, group) in self.groups.iter().enumerate() {
+            let mut routes_json: Vec<Value> = Vec::new();
+            for route in group {
+                routes_json.push(route.json_struct().await);
+            }
+            groups_json.push(json!({
+                "priority": index,
+                "healthy": self.group_healthy(index).await,
+                "routes": routes_json
+            }));
+        }
+
+        json!({
+            "type": "load-balancer",
+            "name": "failover",
+            "kind": Self::KIND,
+            "options": {
+                "recover_after": self.recover_after.as_secs()
+            },
+            "active": self.state.lock().unwrap().active,
+            "failovers": self.failovers.load(Ordering::Relaxed),
+            "dropped": self.dropped.load(Ordering::Relaxed),
+            "groups": groups_json
+        })
+    }
+}
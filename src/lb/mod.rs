@@ -1,3 +1,6 @@
 
 pub mod roundrobin;
-pub mod all;
\ No newline at end of file
+pub mod all;
+pub mod split;
+pub mod failover;
+pub mod affinity;
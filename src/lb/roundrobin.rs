@@ -1,88 +1,199 @@
 
-use crate::base::{Routeable, RouteableComponent, Serverable, Printable};
+use crate::base::{Redelivered, Redelivery, Routeable, RouteableComponent, Serverable, Printable};
+use crate::lb::affinity::Affinity;
 
-use crate::api::message::AddRouteType;
-
-use tokio::sync::mpsc::Sender;
-use axum::{Router, Json};
+use tokio::sync::mpsc::{self, Sender, UnboundedReceiver, UnboundedSender};
+use axum::Router;
 
 use tokio::sync::RwLock;
 
-use std::mem::zeroed;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
-
-use crate::dynamic::longpoll_registry::LONGPOLL_REGISTRY;
+use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
 
 use serde_json::{Value, json};
 
+type Routes = Arc<RwLock<Vec<Arc<dyn RouteableComponent>>>>;
+
 pub struct RoundRobinLB {
-    routes: RwLock<Vec<Arc<dyn RouteableComponent>>>,
-    current: AtomicUsize,
+    routes: Routes,
+    current: Arc<AtomicUsize>,
+    redelivery_tx: UnboundedSender<Redelivered>,
+    redelivery_rx: Mutex<Option<UnboundedReceiver<Redelivered>>>,
+    affinity: Option<Arc<Affinity>>,
 }
 
 impl RoundRobinLB {
+    pub const KIND: &'static str = "RoundRobinLB";
+
     pub fn new(routes: Vec<Arc<dyn RouteableComponent>>) -> Self {
+        let (redelivery_tx, redelivery_rx) = mpsc::unbounded_channel();
+        let routes: Routes = Arc::new(RwLock::new(routes));
+        for (index, route) in routes.try_read().unwrap().iter().enumerate() {
+            route.set_redelivery(Redelivery::new(redelivery_tx.clone(), index, Arc::downgrade(&routes)));
+        }
+
         Self {
-            routes:RwLock::new(routes),
-            current: AtomicUsize::new(0),
+            routes,
+            current: Arc::new(AtomicUsize::new(0)),
+            redelivery_tx,
+            redelivery_rx: Mutex::new(Some(redelivery_rx)),
+            affinity: None,
         }
     }
-}
 
-#[async_trait]
-impl Routeable for RoundRobinLB {
-    async fn process(&self, update: Value) {
-        let routes = self.routes.read().await;
+    /// Sends callback queries to the route that delivered or sent the message
+    /// they belong to, as long as that route is healthy.
+    pub fn set_affinity(&mut self, affinity: Affinity) {
+        self.affinity = Some(Arc::new(affinity));
+    }
+
+    /// Next route in turn, skipping routes that would refuse the update, e.g.
+    /// an open circuit breaker. Falls back to the next route when none is healthy.
+    async fn next(routes: &Routes, current: &AtomicUsize) -> Option<(usize, Arc<dyn RouteableComponent>)> {
+        let routes = routes.read().await;
         if routes.is_empty() {
-            return;
+            return None;
+        }
+        let current = current.fetch_add(1, Ordering::Relaxed);
+
+        for offset in 0..routes.len() {
+            let index = (current + offset) % routes.len();
+            if routes[index].is_healthy().await {
+                return Some((index, routes[index].clone()));
+            }
         }
-        let current = self.current.fetch_add(1, Ordering::Relaxed);
         let index = current % routes.len();
+        Some((index, routes[index].clone()))
+    }
 
-        let route = routes[index].clone();
+    /// Next healthy route in turn other than `exclude`.
+    async fn next_sibling(routes: &Routes, current: &AtomicUsize, exclude: usize) -> Option<Arc<dyn RouteableComponent>> {
+        let routes = routes.read().await;
+        let current = current.fetch_add(1, Ordering::Relaxed);
 
-        drop(routes); 
+        for offset in 0..routes.len() {
+            let index = (current + offset) % routes.len();
+            if index != exclude && routes[index].is_healthy().await {
+                return Some(routes[index].clone());
+            }
+        }
+        None
+    }
 
-        route.process(update).await;
+    /// Like `next`, but an update pinned to a healthy route goes there. Updates
+    /// that carry a message get pinned to whichever route is chosen.
+    async fn pick(&self, update: &Value) -> Option<(usize, Arc<dyn RouteableComponent>)> {
+        let Some(affinity) = &self.affinity else {
+            return Self::next(&self.routes, &self.current).await;
+        };
+
+        if let Some(index) = affinity.lookup(update) {
+            let pinned = self.routes.read().await.get(index).cloned();
+            if let Some(route) = pinned {
+                if route.is_healthy().await {
+                    return Some((index, route));
+                }
+            }
+        }
 
+        let picked = Self::next(&self.routes, &self.current).await;
+        if let Some((index, _)) = &picked {
+            affinity.record_update(update, *index);
+        }
+        picked
     }
 
-    async fn add_route(&self, route: AddRouteType) -> Result<(), ()>{
-        let mut routes = self.routes.write().await;
+    /// Hands updates a child gave back to one of its siblings.
+    fn start_redelivery(&self) {
+        let Some(mut rx) = self.redelivery_rx.lock().unwrap().take() else {
+            return;
+        };
+        let routes = self.routes.clone();
+        let current = self.current.clone();
+
+        tokio::spawn(async move {
+            while let Some((origin, update, hops)) = rx.recv().await {
+                let target = match Self::next_sibling(&routes, &current, origin).await {
+                    Some(route) => route,
+                    // the siblings went down since the origin checked, it keeps the update
+                    None => match routes.read().await.get(origin) {
+                        Some(route) => route.clone(),
+                        None => continue,
+                    },
+                };
+                target.process_redelivered(update, hops).await;
+            }
+        });
+    }
+}
 
-        match route {
-            AddRouteType::Longpull(route_arc) => {
-                match LONGPOLL_REGISTRY.write() {
-                    Ok(mut registry) => {
-                        registry.insert(route_arc.path.clone(), route_arc.clone());
-                        routes.push(route_arc); 
-                        Ok(())
-                    }
-                    Err(_) => {
-                        Err(())
-                    }
-                }
-            },
-            AddRouteType::Webhook(route) => {
-                routes.push(route); 
-                Ok(())
-            },
+#[async_trait]
+impl Routeable for RoundRobinLB {
+    async fn process(&self, update: Value) {
+        if let Some((_, route)) = self.pick(&update).await {
+            route.process(update).await;
+        }
+    }
+
+    /// The whole batch follows its first update.
+    async fn process_batch(&self, updates: Vec<Value>) {
+        let Some(first) = updates.first() else { return };
+        if let Some((index, route)) = self.pick(first).await {
+            if let Some(affinity) = &self.affinity {
+                updates.iter().skip(1).for_each(|update| affinity.record_update(update, index));
+            }
+            route.process_batch(updates).await;
+        }
+    }
+
+    async fn try_process(&self, update: Value) -> Result<(), Value> {
+        match self.pick(&update).await {
+            Some((_, route)) => route.try_process(update).await,
+            None => Err(update),
         }
     }
+
+    async fn is_healthy(&self) -> bool {
+        let routes = self.routes.read().await;
+        for route in routes.iter() {
+            if route.is_healthy().await {
+                return true;
+            }
+        }
+        false
+    }
+
+    async fn add_route(&self, route: Arc<dyn RouteableComponent>) -> Result<(), ()>{
+        let mut routes = self.routes.write().await;
+        route.set_redelivery(Redelivery::new(self.redelivery_tx.clone(), routes.len(), Arc::downgrade(&self.routes)));
+        routes.push(route); 
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl Serverable for RoundRobinLB {
     async fn set_server(&self, mut router: Router<Sender<Value>>) -> Router<Sender<Value>> {
+        self.start_redelivery();
+        if let Some(affinity) = &self.affinity {
+            router = affinity.set_server(router);
+        }
         let routes = self.routes.read().await;
         for route in routes.iter() {
             router = route.set_server(router).await;
         }
         router
     }
+
+    async fn paths(&self) -> Vec<String> {
+        let mut paths = self.affinity.as_ref().map(|affinity| affinity.paths()).unwrap_or_default();
+        for route in self.routes.read().await.iter() {
+            paths.extend(route.paths().await);
+        }
+        paths
+    }
 }
 
 #[async_trait]
@@ -108,6 +219,8 @@ impl Printable for RoundRobinLB {
         json!({
             "type": "load-balancer",
             "name": "round-robin",
+            "kind": Self::KIND,
+            "affinity": self.affinity.as_ref().map(|a| a.json_struct()),
             "routes": routes_json
         })
     }
@@ -0,0 +1,226 @@
+
+use crate::base::{Routeable, RouteableComponent, Serverable, Printable};
+use crate::utils::update::{chat_id, user_id};
+
+use tokio::sync::mpsc::Sender;
+use axum::Router;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+
+use serde_json::{Value, json};
+
+
+pub type Shares = Arc<RwLock<Vec<f64>>>;
+
+/// Named `SplitLB` share tables, so the management API can change them at runtime.
+pub static SPLIT_REGISTRY: Lazy<RwLock<HashMap<String, Shares>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum SplitAssignment {
+    /// The same user always lands on the same route while shares stay the same.
+    #[default]
+    User,
+    /// Same as `User`, keyed by chat id.
+    Chat,
+    /// Every update is assigned independently.
+    Random,
+}
+
+pub struct SplitLB {
+    routes: Vec<Arc<dyn RouteableComponent>>,
+    shares: Shares,
+    assignment: SplitAssignment,
+    name: Option<String>,
+}
+
+impl SplitLB {
+    pub const KIND: &'static str = "SplitLB";
+
+    pub fn new(routes: Vec<(f64, Arc<dyn RouteableComponent>)>) -> Self {
+        let (shares, routes) = routes.into_iter().unzip();
+        Self {
+            routes,
+            shares: Arc::new(RwLock::new(shares)),
+            assignment: SplitAssignment::User,
+            name: None,
+        }
+    }
+
+    pub fn set_assignment(&mut self, assignment: SplitAssignment) {
+        self.assignment = assignment;
+    }
+
+    /// Registers the share table under `name` for `set_shares`. The name
+    /// stays taken until this split is dropped.
+    pub fn set_name(&mut self, name: String) -> Result<(), String> {
+        let mut registry = SPLIT_REGISTRY.write().expect("Registry lock poisoned");
+        if registry.contains_key(&name) {
+            return Err(format!("split {} already exists", name));
+        }
+        registry.insert(name.clone(), self.shares.clone());
+        self.name = Some(name);
+        Ok(())
+    }
+
+    /// Point in `[0, 1)` that picks the route. Sticky points come from a fixed
+    /// mix of the id, so assignments survive restarts.
+    fn point(&self, update: &Value) -> f64 {
+        let key = match self.assignment {
+            SplitAssignment::User => user_id(update),
+            SplitAssignment::Chat => chat_id(update),
+            SplitAssignment::Random => None,
+        };
+
+        match key {
+            Some(id) => (splitmix64(id as u64) >> 11) as f64 / (1u64 << 53) as f64,
+            None => rand::random::<f64>(),
+        }
+    }
+
+    fn pick(&self, point: f64) -> Option<usize> {
+        let shares = self.shares.read().expect("Shares lock poisoned");
+        let total: f64 = shares.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+
+        // cumulative ranges in route order, growing the last route's share
+        // only ever moves users onto it
+        let target = point * total;
+        let mut upper = 0.0;
+        for (index, share) in shares.iter().enumerate() {
+            upper += share;
+            if target < upper && *share > 0.0 {
+                return Some(index);
+            }
+        }
+        shares.iter().rposition(|s| *s > 0.0)
+    }
+}
+
+/// Replaces the shares of the `SplitLB` registered as `name`.
+pub fn set_shares(name: &str, new_shares: Vec<f64>) -> Result<Vec<f64>, String> {
+    let registry = SPLIT_REGISTRY.read().expect("Registry lock poisoned");
+    let shares = registry.get(name).ok_or_else(|| format!("split {} not found", name))?;
+    let mut shares = shares.write().expect("Shares lock poisoned");
+
+    if new_shares.len() != shares.len() {
+        return Err(format!("split {} has {} routes, got {} shares", name, shares.len(), new_shares.len()));
+    }
+    if new_shares.iter().any(|s| !s.is_finite() || *s < 0.0) || new_shares.iter().sum::<f64>() <= 0.0 {
+        return Err("shares must be non-negative and not all zero".to_string());
+    }
+
+    *shares = new_shares;
+    Ok(shares.clone())
+}
+
+impl Drop for SplitLB {
+    fn drop(&mut self) {
+        let Some(name) = &self.name else { return };
+        let mut registry = SPLIT_REGISTRY.write().expect("Registry lock poisoned");
+        if registry.get(name).is_some_and(|shares| Arc::ptr_eq(shares, &self.shares)) {
+            registry.remove(name);
+        }
+    }
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[async_trait]
+impl Routeable for SplitLB {
+    async fn process(&self, update: Value) {
+        let point = self.point(&update);
+        if let Some(index) = self.pick(point) {
+            self.routes[index].process(update).await;
+        }
+    }
+
+    async fn process_batch(&self, updates: Vec<Value>) {
+        let Some(first) = updates.first() else { return };
+        let point = self.point(first);
+        if let Some(index) = self.pick(point) {
+            self.routes[index].process_batch(updates).await;
+        }
+    }
+
+    async fn try_process(&self, update: Value) -> Result<(), Value> {
+        let point = self.point(&update);
+        match self.pick(point) {
+            Some(index) => self.routes[index].try_process(update).await,
+            None => Err(update),
+        }
+    }
+
+    async fn is_healthy(&self) -> bool {
+        for route in self.routes.iter() {
+            if route.is_healthy().await {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[async_trait]
+impl Serverable for SplitLB {
+    async fn set_server(&self, mut router: Router<Sender<Value>>) -> Router<Sender<Value>> {
+        for route in self.routes.iter() {
+            router = route.set_server(router).await;
+        }
+        router
+    }
+
+    async fn paths(&self) -> Vec<String> {
+        let mut paths = Vec::new();
+        for route in self.routes.iter() {
+            paths.extend(route.paths().await);
+        }
+        paths
+    }
+}
+
+#[async_trait]
+impl Printable for SplitLB {
+    async fn print(&self) -> String {
+        let shares = self.shares.read().expect("Shares lock poisoned").clone();
+        let mut text = format!("LOAD BALANCER Split by {:?}\n\n", self.assignment);
+
+        for (route, share) in self.routes.iter().zip(shares) {
+            text.push_str(&format!("{}% {}\n\n", share, route.print().await));
+        }
+        text
+    }
+
+    async fn json_struct(&self) -> Value {
+        let shares = self.shares.read().expect("Shares lock poisoned").clone();
+        let mut routes_json: Vec<Value> = Vec::new();
+        for route in self.routes.iter() {
+            routes_json.push(route.json_struct().await);
+        }
+
+        json!({
+            "type": "load-balancer",
+            "name": "split",
+            "kind": Self::KIND,
+            "options": {
+                "name": self.name,
+                "assignment": format!("{:?}", self.assignment),
+                "shares": shares
+            },
+            "routes": routes_json
+        })
+    }
+}
@@ -0,0 +1,15 @@
+pub mod base;
+pub mod lb;
+pub mod route;
+pub mod tgin;
+pub mod update;
+pub mod config;
+pub mod utils;
+pub mod dynamic;
+pub mod dispatch;
+pub mod listener;
+pub mod health;
+
+pub mod api;
+
+pub use crate::tgin::Tgin;
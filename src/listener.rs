@@ -0,0 +1,245 @@
+use crate::utils::proxy_protocol;
+use crate::utils::tls::TlsCerts;
+
+use axum::extract::Request;
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream, UnixListener};
+use tokio_rustls::TlsAcceptor;
+
+use ipnet::IpNet;
+
+/// How long a new connection may take to send its PROXY header.
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a new connection may take to finish the TLS handshake.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/// Parts of the HTTP surface a listener can serve.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ListenerComponent {
+    /// Endpoints of update providers, e.g. `WebhookUpdate` paths.
+    Ingress,
+    /// Endpoints of the route tree, e.g. `LongPollRoute` paths, including routes added through the API.
+    Routes,
+    /// The management API.
+    Api,
+    /// The `/healthz` and `/readyz` probes.
+    Health,
+}
+
+pub const ALL_COMPONENTS: [ListenerComponent; 4] = [
+    ListenerComponent::Ingress,
+    ListenerComponent::Routes,
+    ListenerComponent::Api,
+    ListenerComponent::Health,
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+/// `0.0.0.0:8080`, `[::1]:8080` or `unix:/run/tgin.sock`.
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err("unix socket path is empty".to_string());
+            }
+            return Ok(ListenAddr::Unix(PathBuf::from(path)));
+        }
+        s.parse()
+            .map(ListenAddr::Tcp)
+            .map_err(|e| format!("invalid listen address {}: {}", s, e))
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "{}", addr),
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Address of the client a request came from, added to the request
+/// extensions by TCP listeners. Behind the PROXY protocol it is the address
+/// reported by the load balancer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientAddr(pub SocketAddr);
+
+pub struct Listener {
+    pub addr: ListenAddr,
+    pub tls: Option<TlsCerts>,
+    pub serve: Vec<ListenerComponent>,
+    /// Peers whose PROXY protocol header is read, `None` when it is off.
+    pub proxy_protocol: Option<Arc<Vec<IpNet>>>,
+}
+
+impl Listener {
+    pub fn new(addr: ListenAddr) -> Self {
+        Self {
+            addr,
+            tls: None,
+            serve: ALL_COMPONENTS.to_vec(),
+            proxy_protocol: None,
+        }
+    }
+
+    /// Certificate and key PEM files. Not supported on Unix sockets.
+    pub fn set_ssl(&mut self, cert: String, key: String) {
+        self.tls = Some(TlsCerts::new(cert, key));
+    }
+
+    pub fn set_tls(&mut self, tls: TlsCerts) {
+        self.tls = Some(tls);
+    }
+
+    pub fn set_serve(&mut self, serve: Vec<ListenerComponent>) {
+        self.serve = serve;
+    }
+
+    /// Expects a PROXY protocol header (v1 or v2) on every connection, as sent by
+    /// HAProxy or AWS NLB. Only peers inside `from` may send one, connections
+    /// from anywhere else and connections without a header are dropped.
+    pub fn set_proxy_protocol(&mut self, from: Vec<IpNet>) {
+        self.proxy_protocol = Some(Arc::new(from));
+    }
+
+    pub fn serves(&self, component: ListenerComponent) -> bool {
+        self.serve.contains(&component)
+    }
+
+    /// Binds the listener and serves `app` on it in a background task.
+    pub async fn spawn(&self, app: Router) {
+        match (&self.addr, &self.tls) {
+            (ListenAddr::Tcp(addr), tls) => {
+                let tls = tls.as_ref().map(|tls| tls.rustls_config()
+                    .unwrap_or_else(|e| panic!("Failed to load SSL certificates: {}", e)));
+                let listener = TcpListener::bind(addr).await
+                    .unwrap_or_else(|e| panic!("Failed to bind {}: {}", addr, e));
+                tokio::spawn(serve_tcp(listener, app, tls, self.proxy_protocol.clone()));
+            }
+            (ListenAddr::Unix(_), Some(_)) => panic!("TLS is not supported on unix socket {}", self.addr),
+            (ListenAddr::Unix(path), None) => {
+                if self.proxy_protocol.is_some() {
+                    panic!("PROXY protocol is not supported on unix socket {}", self.addr);
+                }
+                // a socket file left over from a previous run would make bind fail
+                if std::fs::metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+                    let _ = std::fs::remove_file(path);
+                }
+                let listener = UnixListener::bind(path)
+                    .unwrap_or_else(|e| panic!("Failed to bind {}: {}", self.addr, e));
+                tokio::spawn(serve_unix(listener, app));
+            }
+        }
+    }
+}
+
+async fn serve_tcp(listener: TcpListener, app: Router, tls: Option<RustlsConfig>, proxy_protocol: Option<Arc<Vec<IpNet>>>) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                eprintln!("TCP accept failed: {}", err);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                continue;
+            }
+        };
+        let app = app.clone();
+        let tls = tls.clone();
+        let proxy_protocol = proxy_protocol.clone();
+        tokio::spawn(async move {
+            let _ = serve_tcp_connection(stream, peer, app, tls, proxy_protocol).await;
+        });
+    }
+}
+
+async fn serve_tcp_connection(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    app: Router,
+    tls: Option<RustlsConfig>,
+    proxy_protocol: Option<Arc<Vec<IpNet>>>,
+) -> std::io::Result<()> {
+    let mut client = peer;
+    if let Some(from) = proxy_protocol {
+        // anyone else could claim to be any client
+        let ip = peer.ip().to_canonical();
+        if !from.iter().any(|net| net.contains(&ip)) {
+            eprintln!("Dropping connection from {}: not allowed to send a PROXY header", peer);
+            return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "untrusted PROXY peer"));
+        }
+        let header = tokio::time::timeout(PROXY_HEADER_TIMEOUT, proxy_protocol::read_header(&mut stream)).await
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "PROXY header timed out"))?;
+        match header {
+            Ok(Some(addr)) => client = addr,
+            Ok(None) => {}
+            Err(err) => {
+                eprintln!("Dropping connection from {}: {}", peer, err);
+                return Err(err);
+            }
+        }
+    }
+
+    let service = app.map_request(move |mut request: Request<_>| {
+        request.extensions_mut().insert(ClientAddr(client));
+        request
+    });
+    match tls {
+        Some(tls) => {
+            let stream = tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, TlsAcceptor::from(tls.get_inner()).accept(stream)).await
+                .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "TLS handshake timed out"))??;
+            serve_connection(stream, service).await;
+        }
+        None => serve_connection(stream, service).await,
+    }
+    Ok(())
+}
+
+async fn serve_connection<S, T>(stream: S, service: T)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T: tower::Service<Request<hyper::body::Incoming>, Response = axum::response::Response, Error = std::convert::Infallible>
+        + Clone + Send + 'static,
+    T::Future: Send + 'static,
+{
+    let _ = auto::Builder::new(TokioExecutor::new())
+        .serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(service))
+        .await;
+}
+
+async fn serve_unix(listener: UnixListener, app: Router) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                eprintln!("Unix socket accept failed: {}", err);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                continue;
+            }
+        };
+        tokio::spawn(serve_connection(stream, app.clone()));
+    }
+}
@@ -1,18 +1,8 @@
-mod base;
-mod lb;
-mod route;
-mod tgin;
-mod update;
-mod config;
-mod utils;
-mod dynamic;
+use tgin::health;
+use tgin::config::document::ConfigDocument;
+use tgin::config::setup::{load_config, build_tgin};
 
-mod api;
-
-use crate::tgin::Tgin;
-use crate::config::setup::{load_config, build_updates, build_route};
-
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, Command};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Command::new("tgin")
@@ -24,6 +14,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .value_name("FILE")
                 .help("Path to the configuration file")
                 .default_value("tgin.ron")
+                .global(true)
+        )
+        .subcommand(
+            Command::new("health")
+                .about("Calls the readiness probe of a running tgin, exits non-zero when it is not ready")
+                .arg(
+                    Arg::new("url")
+                        .long("url")
+                        .value_name("URL")
+                        .help("Base URL of the server, taken from the configuration file by default")
+                )
+                .arg(
+                    Arg::new("live")
+                        .long("live")
+                        .action(ArgAction::SetTrue)
+                        .help("Call the liveness probe instead")
+                )
         );
 
     let matches = cli.get_matches();
@@ -32,28 +39,29 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .map(|s| s.as_str())
         .unwrap();
 
+    if let Some(health) = matches.subcommand_matches("health") {
+        return check_health(config_path, health.get_one::<String>("url"), health.get_flag("live"));
+    }
 
-    let conf = load_config(config_path); 
-    let inputs = build_updates(conf.updates);
-    let lb = build_route(conf.route);
-
-    let mut tgin = Tgin::new(
-        inputs,
-        lb,
-        conf.dark_threads,
-        conf.server_port,
-    );
+    // a second copy for the API, the first one is consumed building the components
+    let tgin = build_tgin(load_config(config_path), ConfigDocument::load(config_path))?;
+    tgin.run();
 
-    if let Some(api) = conf.api {
-        let api = api::router::Api::new(api.base_path);
-        tgin.set_api(api);
-    }
+    Ok(())
+}
 
-    if let Some(ssl) = conf.ssl {
-        tgin.set_ssl(ssl.cert, ssl.key);
-    }
 
-    tgin.run();
+fn check_health(config_path: &str, url: Option<&String>, live: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let base = match url {
+        Some(url) => url.clone(),
+        None => health::local_url(&load_config(config_path))
+            .ok_or("no TCP listener serves the health probes, pass --url")?,
+    };
+    let path = if live { health::LIVENESS_PATH } else { health::READINESS_PATH };
+    let url = format!("{}{}", base.trim_end_matches('/'), path);
 
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let body = runtime.block_on(health::probe(&url))?;
+    println!("{}", body);
     Ok(())
 }
@@ -0,0 +1,172 @@
+use crate::base::{Routeable, Serverable, Printable};
+use crate::utils::time::unix_millis;
+use async_trait::async_trait;
+
+use serde_json::{json, Value};
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+
+struct SinkFile {
+    file: File,
+    path: PathBuf,
+    written: u64,
+    opened_at: Instant,
+}
+
+pub struct FileSinkRoute {
+    dir: PathBuf,
+    prefix: String,
+    max_size: Option<u64>,
+    rotate_every: Option<Duration>,
+    gzip: bool,
+    current: Mutex<Option<SinkFile>>,
+}
+
+impl FileSinkRoute {
+    pub const KIND: &'static str = "FileSinkRoute";
+
+    pub fn new(dir: String) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+            prefix: "updates".to_string(),
+            max_size: None,
+            rotate_every: None,
+            gzip: false,
+            current: Mutex::new(None),
+        }
+    }
+
+    pub fn set_prefix(&mut self, prefix: String) {
+        self.prefix = prefix;
+    }
+
+    pub fn set_rotation(&mut self, max_size: Option<u64>, rotate_every: Option<Duration>) {
+        self.max_size = max_size;
+        self.rotate_every = rotate_every;
+    }
+
+    pub fn set_gzip(&mut self, gzip: bool) {
+        self.gzip = gzip;
+    }
+
+    fn needs_rotation(&self, sink: &SinkFile) -> bool {
+        let too_big = self.max_size.is_some_and(|max| sink.written >= max);
+        let too_old = self.rotate_every.is_some_and(|every| sink.opened_at.elapsed() >= every);
+        too_big || too_old
+    }
+
+    async fn open(&self) -> std::io::Result<SinkFile> {
+        fs::create_dir_all(&self.dir).await?;
+
+        // millis alone can collide when files rotate quickly, the counter keeps names unique and ordered
+        let stamp = unix_millis();
+        let mut seq = 0;
+        let path = loop {
+            let path = self.dir.join(format!("{}-{}-{:04}.jsonl", self.prefix, stamp, seq));
+            if !fs::try_exists(&path).await? && !fs::try_exists(path.with_extension("jsonl.gz")).await? {
+                break path;
+            }
+            seq += 1;
+        };
+
+        let file = OpenOptions::new().create(true).append(true).open(&path).await?;
+        Ok(SinkFile { file, path, written: 0, opened_at: Instant::now() })
+    }
+
+    async fn close(&self, mut sink: SinkFile) -> std::io::Result<()> {
+        sink.file.flush().await?;
+        drop(sink.file);
+
+        if self.gzip {
+            let path = sink.path;
+            tokio::task::spawn_blocking(move || compress(&path)).await??;
+        }
+        Ok(())
+    }
+
+    async fn write(&self, line: &[u8]) -> std::io::Result<()> {
+        let mut current = self.current.lock().await;
+
+        let rotated = match current.take() {
+            Some(sink) if self.needs_rotation(&sink) => Some(sink),
+            sink => {
+                *current = sink;
+                None
+            }
+        };
+
+        let sink = match current.as_mut() {
+            Some(sink) => sink,
+            None => current.insert(self.open().await?),
+        };
+
+        sink.file.write_all(line).await?;
+        sink.written += line.len() as u64;
+        drop(current);
+
+        // compressing can take a while, writes go on to the new file meanwhile
+        match rotated {
+            Some(sink) => self.close(sink).await,
+            None => Ok(()),
+        }
+    }
+}
+
+fn compress(path: &Path) -> std::io::Result<()> {
+    let gz_path = path.with_extension("jsonl.gz");
+
+    let mut encoder = GzEncoder::new(std::fs::File::create(&gz_path)?, Compression::default());
+    std::io::copy(&mut std::fs::File::open(path)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+
+    std::fs::remove_file(path)
+}
+
+
+#[async_trait]
+impl Routeable for FileSinkRoute {
+    async fn process(&self, update: Value) {
+        let record = json!({
+            "ts": unix_millis(),
+            "update": update
+        });
+        let mut line = record.to_string().into_bytes();
+        line.push(b'\n');
+
+        if let Err(err) = self.write(&line).await {
+            eprintln!("File sink error in {}: {:?}", self.dir.display(), err);
+        }
+    }
+}
+
+impl Serverable for FileSinkRoute {}
+
+#[async_trait]
+impl Printable for FileSinkRoute {
+    async fn print(&self) -> String {
+        format!("file sink: {}/{}-*.jsonl{}", self.dir.display(), self.prefix, if self.gzip { " (gzip)" } else { "" })
+    }
+
+    async fn json_struct(&self) -> Value {
+        json!({
+            "type": "file-sink",
+            "kind": Self::KIND,
+            "options": {
+                "path": self.dir,
+                "prefix": self.prefix,
+                "max_size": self.max_size,
+                "rotate_every": self.rotate_every.map(|d| d.as_secs()),
+                "gzip": self.gzip
+            }
+        })
+    }
+}
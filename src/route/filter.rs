@@ -0,0 +1,307 @@
+use crate::base::{Redelivery, Routeable, RouteableComponent, Serverable, Printable};
+use crate::utils::update::{chat_id, chat_type, user_id};
+
+use async_trait::async_trait;
+use axum::Router;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::mpsc::Sender;
+
+
+/// Named `FilterRoute` lists, so the management API can edit them at runtime.
+pub static FILTER_REGISTRY: Lazy<RwLock<HashMap<String, Arc<FilterShared>>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct FilterList {
+    #[serde(default)]
+    pub users: BTreeSet<i64>,
+    #[serde(default)]
+    pub chats: BTreeSet<i64>,
+    /// `private`, `group`, `supergroup` or `channel`.
+    #[serde(default)]
+    pub chat_types: BTreeSet<String>,
+}
+
+impl FilterList {
+    fn extend(&mut self, other: FilterList) {
+        self.users.extend(other.users);
+        self.chats.extend(other.chats);
+        self.chat_types.extend(other.chat_types);
+    }
+
+    fn remove(&mut self, other: &FilterList) {
+        self.users.retain(|id| !other.users.contains(id));
+        self.chats.retain(|id| !other.chats.contains(id));
+        self.chat_types.retain(|t| !other.chat_types.contains(t));
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct FilterLists {
+    #[serde(default)]
+    pub allow: FilterList,
+    #[serde(default)]
+    pub deny: FilterList,
+}
+
+impl FilterLists {
+    /// Deny entries always win. A non-empty allow list only lets matching
+    /// updates through; updates without that field are not checked against it.
+    pub fn permits(&self, update: &Value) -> bool {
+        let user = user_id(update);
+        let chat = chat_id(update);
+        let kind = chat_type(update);
+
+        let denied = user.is_some_and(|id| self.deny.users.contains(&id))
+            || chat.is_some_and(|id| self.deny.chats.contains(&id))
+            || kind.is_some_and(|t| self.deny.chat_types.contains(t));
+        if denied {
+            return false;
+        }
+
+        let allowed = |list: &BTreeSet<i64>, id: Option<i64>| list.is_empty() || id.is_none_or(|id| list.contains(&id));
+        allowed(&self.allow.users, user)
+            && allowed(&self.allow.chats, chat)
+            && (self.allow.chat_types.is_empty() || kind.is_none_or(|t| self.allow.chat_types.contains(t)))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterSide {
+    Allow,
+    Deny,
+}
+
+/// Entries to add to and remove from one side of a filter.
+#[derive(Deserialize, Debug, Clone)]
+pub struct FilterEdit {
+    pub list: FilterSide,
+    #[serde(default)]
+    pub add: FilterList,
+    #[serde(default)]
+    pub remove: FilterList,
+}
+
+/// Lists of one filter, shared by the route, its file watcher and the API.
+pub struct FilterShared {
+    lists: RwLock<FilterLists>,
+    file: Option<PathBuf>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl FilterShared {
+    fn lists(&self) -> FilterLists {
+        self.lists.read().expect("Filter lock poisoned").clone()
+    }
+
+    fn load(&self, path: &Path) -> Result<(), String> {
+        let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        let content = std::fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let lists: FilterLists = ron::from_str(&content).map_err(|e| format!("failed to parse {}: {}", path.display(), e))?;
+
+        *self.lists.write().expect("Filter lock poisoned") = lists;
+        *self.modified.lock().unwrap() = modified;
+        Ok(())
+    }
+
+    fn reload_if_changed(&self, path: &Path) {
+        let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified == *self.modified.lock().unwrap() {
+            return;
+        }
+        if let Err(err) = self.load(path) {
+            eprintln!("Filter reload failed, keeping previous lists: {}", err);
+        }
+    }
+
+    /// Writes the lists back to the file so edits made through the API survive a restart.
+    fn persist(&self, path: &Path, lists: &FilterLists) -> Result<(), String> {
+        let content = ron::ser::to_string_pretty(lists, ron::ser::PrettyConfig::default())
+            .map_err(|e| e.to_string())?;
+        std::fs::write(path, content).map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+        *self.modified.lock().unwrap() = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        Ok(())
+    }
+
+    fn edit(&self, edit: FilterEdit) -> Result<FilterLists, String> {
+        let mut lists = self.lists.write().expect("Filter lock poisoned");
+        let mut updated = lists.clone();
+
+        let side = match edit.list {
+            FilterSide::Allow => &mut updated.allow,
+            FilterSide::Deny => &mut updated.deny,
+        };
+        side.remove(&edit.remove);
+        side.extend(edit.add);
+
+        if let Some(path) = &self.file {
+            self.persist(path, &updated)?;
+        }
+        *lists = updated.clone();
+        Ok(updated)
+    }
+}
+
+/// Applies an edit to the `FilterRoute` registered as `name`.
+pub fn edit_filter(name: &str, edit: FilterEdit) -> Result<FilterLists, String> {
+    let shared = FILTER_REGISTRY.read().expect("Registry lock poisoned")
+        .get(name)
+        .cloned()
+        .ok_or_else(|| format!("filter {} not found", name))?;
+    shared.edit(edit)
+}
+
+
+/// Drops updates from blocked users, chats and chat types before they reach `route`.
+pub struct FilterRoute {
+    route: Arc<dyn RouteableComponent>,
+    shared: Arc<FilterShared>,
+    name: Option<String>,
+    dropped: AtomicU64,
+}
+
+impl FilterRoute {
+    pub const KIND: &'static str = "FilterRoute";
+
+    pub fn new(route: Arc<dyn RouteableComponent>, lists: FilterLists) -> Self {
+        Self {
+            route,
+            shared: Arc::new(FilterShared {
+                lists: RwLock::new(lists),
+                file: None,
+                modified: Mutex::new(None),
+            }),
+            name: None,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Loads the lists from a RON file, replacing the inline ones, and checks it
+    /// for changes every `watch_every`. API edits are written back to the file.
+    pub fn set_file(&mut self, path: String, watch_every: Duration) -> Result<(), String> {
+        let path = PathBuf::from(path);
+        let shared = Arc::new(FilterShared {
+            lists: RwLock::new(self.shared.lists()),
+            file: Some(path.clone()),
+            modified: Mutex::new(None),
+        });
+        shared.load(&path)?;
+
+        // a plain thread, routes are built before the runtime exists
+        let watched: Weak<FilterShared> = Arc::downgrade(&shared);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(watch_every);
+            match watched.upgrade() {
+                Some(shared) => shared.reload_if_changed(&path),
+                None => break,
+            }
+        });
+
+        self.shared = shared;
+        if let Some(name) = self.name.clone() {
+            self.set_name(name);
+        }
+        Ok(())
+    }
+
+    /// Registers the lists under `name` for `edit_filter`.
+    pub fn set_name(&mut self, name: String) {
+        FILTER_REGISTRY.write().expect("Registry lock poisoned").insert(name.clone(), self.shared.clone());
+        self.name = Some(name);
+    }
+}
+
+#[async_trait]
+impl Routeable for FilterRoute {
+    async fn process(&self, update: Value) {
+        if self.shared.lists.read().expect("Filter lock poisoned").permits(&update) {
+            self.route.process(update).await;
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    async fn process_batch(&self, updates: Vec<Value>) {
+        let total = updates.len();
+        let permitted: Vec<Value> = {
+            let lists = self.shared.lists.read().expect("Filter lock poisoned");
+            updates.into_iter().filter(|u| lists.permits(u)).collect()
+        };
+        self.dropped.fetch_add((total - permitted.len()) as u64, Ordering::Relaxed);
+        if !permitted.is_empty() {
+            self.route.process_batch(permitted).await;
+        }
+    }
+
+    async fn process_redelivered(&self, update: Value, hops: u32) {
+        if self.shared.lists.read().expect("Filter lock poisoned").permits(&update) {
+            self.route.process_redelivered(update, hops).await;
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    async fn try_process(&self, update: Value) -> Result<(), Value> {
+        if self.shared.lists.read().expect("Filter lock poisoned").permits(&update) {
+            self.route.try_process(update).await
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    async fn is_healthy(&self) -> bool {
+        self.route.is_healthy().await
+    }
+
+    async fn add_route(&self, route: Arc<dyn RouteableComponent>) -> Result<(), ()> {
+        self.route.add_route(route).await
+    }
+
+    fn set_redelivery(&self, redelivery: Redelivery) {
+        self.route.set_redelivery(redelivery);
+    }
+}
+
+#[async_trait]
+impl Serverable for FilterRoute {
+    async fn set_server(&self, router: Router<Sender<Value>>) -> Router<Sender<Value>> {
+        self.route.set_server(router).await
+    }
+
+    async fn paths(&self) -> Vec<String> {
+        self.route.paths().await
+    }
+}
+
+#[async_trait]
+impl Printable for FilterRoute {
+    async fn print(&self) -> String {
+        format!("FILTER {}\n\n{}", self.name.as_deref().unwrap_or(""), self.route.print().await)
+    }
+
+    async fn json_struct(&self) -> Value {
+        json!({
+            "type": "filter",
+            "kind": Self::KIND,
+            "options": {
+                "name": self.name,
+                "file": self.shared.file
+            },
+            "lists": self.shared.lists(),
+            "dropped": self.dropped.load(Ordering::Relaxed),
+            "route": self.route.json_struct().await
+        })
+    }
+}
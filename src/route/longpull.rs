@@ -1,18 +1,24 @@
-use crate::base::{Routeable, Serverable, Printable};
+use crate::base::{Redelivery, Routeable, RouteableComponent, Serverable, Printable, MAX_REDELIVERIES};
+use crate::utils::time::unix_millis;
+use crate::utils::update::date;
 use async_trait::async_trait;
 
 use std::collections::VecDeque;
 
-use axum::{extract::Form, routing::post, Json, Router}; 
-use serde::Deserialize;
+use axum::{extract::Request, http::header::CONTENT_TYPE, routing::post, Json, Router}; 
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{Mutex, Notify};
 use tokio::sync::mpsc::Sender;
 use tokio::time::timeout as tokio_timeout;
 
-#[derive(Deserialize, Debug)]
+/// `getUpdates` parameters fit in a few numbers, anything bigger is not a poll.
+const MAX_PARAMS_BODY: usize = 4 * 1024;
+
+#[derive(Deserialize, Debug, Default)]
 pub struct GetUpdatesParams {
     #[serde(default)]
     pub offset: Option<i64>,
@@ -23,23 +29,269 @@ pub struct GetUpdatesParams {
     
 }
 
+impl GetUpdatesParams {
+    pub async fn from_request(request: Request) -> Result<Self, Json<Value>> {
+        let (parts, body) = request.into_parts();
+
+        let body_bytes = match axum::body::to_bytes(body, MAX_PARAMS_BODY).await {
+            Ok(b) => b,
+            Err(_) => return Err(Json(json!({
+                "ok": false,
+                "error_code": 400,
+                "description": "failed to read request body"
+            }))),
+        };
+
+        let content_type = parts.headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+
+        if content_type.contains("application/json") {
+            serde_json::from_slice(&body_bytes).map_err(|_| Json(json!({ 
+                "ok": false, 
+                "error_code": 400, 
+                "description": "invalid json body" 
+            })))
+        } else {
+            serde_urlencoded::from_bytes(&body_bytes).map_err(|_| Json(json!({
+                "ok": false,
+                "error_code": 400,
+                "description": "invalid form body"
+            })))
+        }
+    }
+}
+
+/// What the age of a queued update is measured from.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum UpdateAge {
+    /// When TGIN put the update into the queue.
+    #[default]
+    Received,
+    /// The message `date` set by Telegram, updates without one fall back to `Received`.
+    Date,
+}
+
+#[derive(Clone)]
+struct Expiry {
+    ttl: Duration,
+    age: UpdateAge,
+    route: Option<Arc<dyn RouteableComponent>>,
+    sweep_every: Option<Duration>,
+    sweeping: Arc<AtomicBool>,
+    expired: Arc<AtomicU64>,
+}
+
+/// An update waiting for a consumer.
+struct Queued {
+    /// Unix millis of when it was put into this queue.
+    received: u64,
+    /// How often it was handed on from a sibling before.
+    hops: u32,
+    update: Value,
+}
+
 #[derive(Clone)] 
 pub struct LongPollRoute {
-    updates: Arc<Mutex<VecDeque<Value>>>,
+    updates: Arc<Mutex<VecDeque<Queued>>>,
     notify: Arc<Notify>,
     pub path: String,
+
+    last_poll: Arc<AtomicU64>,
+    active_polls: Arc<AtomicUsize>,
+    stale_after: Option<Duration>,
+
+    expiry: Option<Expiry>,
+
+    redeliver_after: Option<Duration>,
+    redelivery: Arc<std::sync::Mutex<Option<Redelivery>>>,
+    redelivering: Arc<AtomicBool>,
+    redelivered: Arc<AtomicU64>,
+}
+
+/// Counts a running `getUpdates` call and stamps its start and end.
+struct PollGuard<'a>(&'a LongPollRoute);
+
+impl<'a> PollGuard<'a> {
+    fn new(route: &'a LongPollRoute) -> Self {
+        route.active_polls.fetch_add(1, Ordering::Relaxed);
+        route.last_poll.store(unix_millis(), Ordering::Relaxed);
+        Self(route)
+    }
+}
+
+impl Drop for PollGuard<'_> {
+    fn drop(&mut self) {
+        self.0.last_poll.store(unix_millis(), Ordering::Relaxed);
+        self.0.active_polls.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 impl LongPollRoute {
+    pub const KIND: &'static str = "LongPollRoute";
+
     pub fn new(path: String) -> Self {
         Self {
             updates: Arc::new(Mutex::new(VecDeque::new())),
             notify: Arc::new(Notify::new()),
             path,
+            last_poll: Arc::new(AtomicU64::new(unix_millis())),
+            active_polls: Arc::new(AtomicUsize::new(0)),
+            stale_after: None,
+            expiry: None,
+            redeliver_after: None,
+            redelivery: Arc::new(std::sync::Mutex::new(None)),
+            redelivering: Arc::new(AtomicBool::new(false)),
+            redelivered: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Gives updates nobody fetched within `redeliver_after` back to the parent
+    /// balancer, which sends them to a sibling route.
+    pub fn set_redeliver_after(&mut self, redeliver_after: Duration) {
+        self.redeliver_after = Some(redeliver_after);
+    }
+
+    /// Returns updates that waited longer than `redeliver_after` to the parent
+    /// balancer. Updates already redelivered `MAX_REDELIVERIES` times stay.
+    fn give_back(&self, redelivery: &Redelivery, after: Duration, queue: &mut VecDeque<Queued>) {
+        let cutoff = unix_millis().saturating_sub(after.as_millis() as u64);
+        // the queue is in arrival order, so the overdue updates are at the front
+        let mut index = 0;
+        while queue.get(index).is_some_and(|queued| queued.received <= cutoff) {
+            if queue[index].hops >= MAX_REDELIVERIES {
+                index += 1;
+                continue;
+            }
+            let queued = queue.remove(index).unwrap();
+            if let Err(update) = redelivery.send(queued.update, queued.hops + 1) {
+                queue.insert(index, Queued { update, ..queued });
+                return;
+            }
+            self.redelivered.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn start_redelivery(&self) {
+        let Some(after) = self.redeliver_after else { return };
+        if self.redelivering.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval((after / 4).max(Duration::from_millis(100)));
+            loop {
+                interval.tick().await;
+                let Some(redelivery) = this.redelivery.lock().unwrap().clone() else { continue };
+                // with nowhere better to go the updates wait here for a consumer
+                if !redelivery.has_healthy_sibling().await {
+                    continue;
+                }
+                let mut queue = this.updates.lock().await;
+                this.give_back(&redelivery, after, &mut queue);
+            }
+        });
+    }
+
+    /// Drops updates that waited longer than `ttl` instead of handing them to
+    /// a consumer that comes back after an outage.
+    pub fn set_ttl(&mut self, ttl: Duration, age: UpdateAge) {
+        self.expiry = Some(Expiry {
+            ttl,
+            age,
+            route: None,
+            sweep_every: None,
+            sweeping: Arc::new(AtomicBool::new(false)),
+            expired: Arc::new(AtomicU64::new(0)),
+        });
+    }
+
+    /// Sends expired updates to `route` instead of dropping them. Needs `set_ttl`.
+    pub fn set_expired_route(&mut self, route: Arc<dyn RouteableComponent>) {
+        if let Some(expiry) = self.expiry.as_mut() {
+            expiry.route = Some(route);
+        }
+    }
+
+    /// Also removes expired updates every `sweep_every` while nobody polls. Needs `set_ttl`.
+    pub fn set_sweep_every(&mut self, sweep_every: Duration) {
+        if let Some(expiry) = self.expiry.as_mut() {
+            expiry.sweep_every = Some(sweep_every);
+        }
+    }
+
+    fn is_expired(expiry: &Expiry, received: u64, update: &Value, now: u64) -> bool {
+        let since = match expiry.age {
+            UpdateAge::Received => received,
+            UpdateAge::Date => date(update).map(|d| d.max(0) as u64 * 1000).unwrap_or(received),
+        };
+        now.saturating_sub(since) > expiry.ttl.as_millis() as u64
+    }
+
+    /// Takes expired updates out of the queue and drops or reroutes them.
+    fn expire(&self, queue: &mut VecDeque<Queued>) {
+        let Some(expiry) = &self.expiry else { return };
+        let now = unix_millis();
+
+        let mut expired = Vec::new();
+        queue.retain(|queued| {
+            if Self::is_expired(expiry, queued.received, &queued.update, now) {
+                expired.push(queued.update.clone());
+                false
+            } else {
+                true
+            }
+        });
+        if expired.is_empty() {
+            return;
+        }
+
+        expiry.expired.fetch_add(expired.len() as u64, Ordering::Relaxed);
+        if let Some(route) = &expiry.route {
+            let route = route.clone();
+            tokio::spawn(async move {
+                for update in expired {
+                    route.process(update).await;
+                }
+            });
+        }
+    }
+
+    fn start_sweeper(&self) {
+        let Some(expiry) = &self.expiry else { return };
+        let Some(every) = expiry.sweep_every else { return };
+        if expiry.sweeping.swap(true, Ordering::Relaxed) {
+            return;
         }
+
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            loop {
+                interval.tick().await;
+                let mut queue = this.updates.lock().await;
+                this.expire(&mut queue);
+            }
+        });
+    }
+
+    /// Consider the route unhealthy when no consumer polled it for `stale_after`.
+    pub fn set_stale_after(&mut self, stale_after: Duration) {
+        self.stale_after = Some(stale_after);
+    }
+
+    pub fn is_stale(&self) -> bool {
+        let Some(stale_after) = self.stale_after else {
+            return false;
+        };
+        let idle = unix_millis().saturating_sub(self.last_poll.load(Ordering::Relaxed));
+        self.active_polls.load(Ordering::Relaxed) == 0 && idle >= stale_after.as_millis() as u64
     }
 
     pub async fn handle_request(&self, params: GetUpdatesParams) -> Json<Value>{
+        let _poll = PollGuard::new(self);
 
         let updates = self.updates.clone();
         let notify = self.notify.clone();
@@ -51,6 +303,7 @@ impl LongPollRoute {
         loop {
             {
                 let mut lock = updates.lock().await;
+                self.expire(&mut lock);
 
                 if !lock.is_empty() {
 
@@ -59,8 +312,8 @@ impl LongPollRoute {
                     let limit = params.limit.unwrap_or(1000) as usize;
 
                     while batch.len() < limit {
-                        if let Some(upd) = lock.pop_front() {
-                            batch.push(upd);
+                        if let Some(queued) = lock.pop_front() {
+                            batch.push(queued.update);
                         } else {
                             break;
                         }
@@ -91,10 +344,22 @@ impl LongPollRoute {
 #[async_trait]
 impl Routeable for LongPollRoute {
     async fn process(&self, update: Value) {
+        self.process_redelivered(update, 0).await;
+    }
+
+    async fn process_redelivered(&self, update: Value, hops: u32) {
         let mut lock = self.updates.lock().await;
-        lock.push_back(update);
+        lock.push_back(Queued { received: unix_millis(), hops, update });
         self.notify.notify_waiters();
     }
+
+    async fn is_healthy(&self) -> bool {
+        !self.is_stale()
+    }
+
+    fn set_redelivery(&self, redelivery: Redelivery) {
+        *self.redelivery.lock().unwrap() = Some(redelivery);
+    }
 }
 
 #[async_trait]
@@ -103,16 +368,34 @@ impl Serverable for LongPollRoute {
         let this = self.clone(); 
         let path = self.path.clone();
 
-        let handler = move |Form(params): Form<GetUpdatesParams>| {
+        self.start_sweeper();
+        self.start_redelivery();
+        let mut router = router;
+        if let Some(route) = self.expiry.as_ref().and_then(|e| e.route.as_ref()) {
+            router = route.set_server(router).await;
+        }
+
+        let handler = move |request: Request| {
             let this = this.clone();
             
             async move {
-                this.handle_request(params).await
+                match GetUpdatesParams::from_request(request).await {
+                    Ok(params) => this.handle_request(params).await,
+                    Err(error) => error,
+                }
             }
         };
 
         router.route(&path, post(handler))
     }
+
+    async fn paths(&self) -> Vec<String> {
+        let mut paths = vec![self.path.clone()];
+        if let Some(route) = self.expiry.as_ref().and_then(|e| e.route.as_ref()) {
+            paths.extend(route.paths().await);
+        }
+        paths
+    }
 }
 
 
@@ -127,8 +410,22 @@ impl Printable for LongPollRoute {
     async fn json_struct(&self) -> Value {
         json!({
             "type": "longpoll",
+            "kind": Self::KIND,
             "options": {
-                "path": self.path
+                "path": self.path,
+                "stale_after": self.stale_after.map(|d| d.as_secs()),
+                "ttl": self.expiry.as_ref().map(|e| e.ttl.as_secs()),
+                "ttl_from": self.expiry.as_ref().map(|e| format!("{:?}", e.age)),
+                "sweep_every": self.expiry.as_ref().and_then(|e| e.sweep_every).map(|d| d.as_secs()),
+                "redeliver_after": self.redeliver_after.map(|d| d.as_secs())
+            },
+            "redelivered": self.redelivered.load(Ordering::Relaxed),
+            "healthy": !self.is_stale(),
+            "queued": self.updates.lock().await.len(),
+            "expired": self.expiry.as_ref().map(|e| e.expired.load(Ordering::Relaxed)),
+            "expired_route": match self.expiry.as_ref().and_then(|e| e.route.as_ref()) {
+                Some(route) => route.json_struct().await,
+                None => Value::Null,
             }
         })
     }
@@ -0,0 +1,177 @@
+use crate::base::{Redelivery, Routeable, RouteableComponent, Serverable, Printable};
+use crate::utils::update::{payload, update_id};
+
+use async_trait::async_trait;
+use axum::Router;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::mpsc::Sender;
+
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum MediaGroupMode {
+    /// The album's updates are delivered one after another to the same child.
+    #[default]
+    Consecutive,
+    /// The album is delivered as one update:
+    /// `{"update_id": <first>, "media_group_id": "...", "updates": [...]}`.
+    Envelope,
+}
+
+/// Holds updates that share a `media_group_id` for `window` and passes them on
+/// together, so a balancer below sends the whole album to one replica.
+pub struct MediaGroupRoute {
+    route: Arc<dyn RouteableComponent>,
+    window: Duration,
+    max_size: usize,
+    mode: MediaGroupMode,
+    pending: Arc<Mutex<HashMap<String, Vec<Value>>>>,
+    groups: Arc<AtomicU64>,
+}
+
+impl MediaGroupRoute {
+    pub const KIND: &'static str = "MediaGroupRoute";
+
+    pub fn new(route: Arc<dyn RouteableComponent>) -> Self {
+        Self {
+            route,
+            window: Duration::from_millis(500),
+            // Telegram albums hold at most 10 items
+            max_size: 10,
+            mode: MediaGroupMode::Consecutive,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            groups: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// How long to wait for the rest of an album after its first update.
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    /// An album is passed on as soon as it has this many updates.
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size.max(1);
+    }
+
+    pub fn set_mode(&mut self, mode: MediaGroupMode) {
+        self.mode = mode;
+    }
+
+    fn media_group_id(update: &Value) -> Option<String> {
+        let (_, payload) = payload(update)?;
+        payload.get("media_group_id")?.as_str().map(|id| id.to_string())
+    }
+
+    async fn flush(route: &Arc<dyn RouteableComponent>, mode: MediaGroupMode, group_id: String, mut updates: Vec<Value>) {
+        updates.sort_by_key(|u| update_id(u).unwrap_or(i64::MAX));
+        match mode {
+            MediaGroupMode::Consecutive => route.process_batch(updates).await,
+            MediaGroupMode::Envelope => {
+                let envelope = json!({
+                    "update_id": updates.first().and_then(update_id),
+                    "media_group_id": group_id,
+                    "updates": updates
+                });
+                route.process(envelope).await;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Routeable for MediaGroupRoute {
+    async fn process(&self, update: Value) {
+        let Some(group_id) = Self::media_group_id(&update) else {
+            self.route.process(update).await;
+            return;
+        };
+
+        let (first, complete) = {
+            let mut pending = self.pending.lock().unwrap();
+            let group = pending.entry(group_id.clone()).or_default();
+            group.push(update);
+            let first = group.len() == 1;
+            let complete = if group.len() >= self.max_size { pending.remove(&group_id) } else { None };
+            (first, complete)
+        };
+
+        if let Some(updates) = complete {
+            self.groups.fetch_add(1, Ordering::Relaxed);
+            Self::flush(&self.route, self.mode, group_id, updates).await;
+            return;
+        }
+
+        if first {
+            let route = self.route.clone();
+            let pending = self.pending.clone();
+            let groups = self.groups.clone();
+            let (window, mode) = (self.window, self.mode);
+            tokio::spawn(async move {
+                tokio::time::sleep(window).await;
+                // already gone when the album filled up before the window ended
+                let Some(updates) = pending.lock().unwrap().remove(&group_id) else { return };
+                groups.fetch_add(1, Ordering::Relaxed);
+                Self::flush(&route, mode, group_id, updates).await;
+            });
+        }
+    }
+
+    // the album was already kept together on its way to the sibling
+    async fn process_redelivered(&self, update: Value, hops: u32) {
+        self.route.process_redelivered(update, hops).await;
+    }
+
+    async fn is_healthy(&self) -> bool {
+        self.route.is_healthy().await
+    }
+
+    async fn add_route(&self, route: Arc<dyn RouteableComponent>) -> Result<(), ()> {
+        self.route.add_route(route).await
+    }
+
+    fn set_redelivery(&self, redelivery: Redelivery) {
+        self.route.set_redelivery(redelivery);
+    }
+}
+
+#[async_trait]
+impl Serverable for MediaGroupRoute {
+    async fn set_server(&self, router: Router<Sender<Value>>) -> Router<Sender<Value>> {
+        self.route.set_server(router).await
+    }
+
+    async fn paths(&self) -> Vec<String> {
+        self.route.paths().await
+    }
+}
+
+#[async_trait]
+impl Printable for MediaGroupRoute {
+    async fn print(&self) -> String {
+        format!("MEDIA GROUPS {:?} within {}ms\n\n{}", self.mode, self.window.as_millis(), self.route.print().await)
+    }
+
+    async fn json_struct(&self) -> Value {
+        let waiting = self.pending.lock().unwrap().len();
+        json!({
+            "type": "media-group",
+            "kind": Self::KIND,
+            "options": {
+                "window_ms": self.window.as_millis() as u64,
+                "max_size": self.max_size,
+                "mode": format!("{:?}", self.mode)
+            },
+            "waiting": waiting,
+            "groups": self.groups.load(Ordering::Relaxed),
+            "route": self.route.json_struct().await
+        })
+    }
+}
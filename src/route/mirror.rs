@@ -0,0 +1,175 @@
+use crate::base::{Redelivery, Routeable, RouteableComponent, Serverable, Printable};
+use async_trait::async_trait;
+
+use axum::Router;
+use serde_json::{json, Value};
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::mpsc::Sender;
+use tokio::sync::Semaphore;
+
+
+#[derive(Default)]
+struct MirrorMetrics {
+    primary: AtomicU64,
+    shadowed: AtomicU64,
+    sampled_out: AtomicU64,
+    shadow_dropped: AtomicU64,
+}
+
+/// Sends every update to `primary` and a sampled copy to each shadow route.
+/// Shadow copies run in their own tasks and are never awaited, so a slow or
+/// failing shadow cannot hold up the primary.
+pub struct MirrorRoute {
+    primary: Arc<dyn RouteableComponent>,
+    shadows: Vec<Arc<dyn RouteableComponent>>,
+    sample: f64,
+    shadow_slots: Arc<Semaphore>,
+    max_shadow_in_flight: usize,
+    metrics: MirrorMetrics,
+}
+
+impl MirrorRoute {
+    pub const KIND: &'static str = "MirrorRoute";
+
+    pub fn new(primary: Arc<dyn RouteableComponent>, shadows: Vec<Arc<dyn RouteableComponent>>) -> Self {
+        Self {
+            primary,
+            shadows,
+            sample: 100.0,
+            shadow_slots: Arc::new(Semaphore::new(1000)),
+            max_shadow_in_flight: 1000,
+            metrics: MirrorMetrics::default(),
+        }
+    }
+
+    /// Percentage of updates (0-100) copied to the shadows.
+    pub fn set_sample(&mut self, sample: f64) {
+        self.sample = sample.clamp(0.0, 100.0);
+    }
+
+    /// Shadow deliveries allowed to run at once, copies beyond that are dropped.
+    pub fn set_max_shadow_in_flight(&mut self, max: usize) {
+        self.shadow_slots = Arc::new(Semaphore::new(max));
+        self.max_shadow_in_flight = max;
+    }
+
+    fn sampled(&self) -> bool {
+        self.sample >= 100.0 || rand::random::<f64>() * 100.0 < self.sample
+    }
+
+    fn shadow(&self, update: &Value) {
+        if !self.sampled() {
+            self.metrics.sampled_out.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        for shadow in &self.shadows {
+            let Ok(slot) = self.shadow_slots.clone().try_acquire_owned() else {
+                self.metrics.shadow_dropped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            };
+            self.metrics.shadowed.fetch_add(1, Ordering::Relaxed);
+
+            let shadow = shadow.clone();
+            let update = update.clone();
+            tokio::spawn(async move {
+                shadow.process(update).await;
+                drop(slot);
+            });
+        }
+    }
+}
+
+#[async_trait]
+impl Routeable for MirrorRoute {
+    async fn process(&self, update: Value) {
+        self.shadow(&update);
+        self.metrics.primary.fetch_add(1, Ordering::Relaxed);
+        self.primary.process(update).await;
+    }
+
+    async fn process_batch(&self, updates: Vec<Value>) {
+        for update in &updates {
+            self.shadow(update);
+        }
+        self.metrics.primary.fetch_add(updates.len() as u64, Ordering::Relaxed);
+        self.primary.process_batch(updates).await;
+    }
+
+    async fn try_process(&self, update: Value) -> Result<(), Value> {
+        self.shadow(&update);
+        self.metrics.primary.fetch_add(1, Ordering::Relaxed);
+        self.primary.try_process(update).await
+    }
+
+    // shadowed when it first arrived
+    async fn process_redelivered(&self, update: Value, hops: u32) {
+        self.metrics.primary.fetch_add(1, Ordering::Relaxed);
+        self.primary.process_redelivered(update, hops).await;
+    }
+
+    async fn is_healthy(&self) -> bool {
+        self.primary.is_healthy().await
+    }
+
+    fn set_redelivery(&self, redelivery: Redelivery) {
+        self.primary.set_redelivery(redelivery);
+    }
+}
+
+#[async_trait]
+impl Serverable for MirrorRoute {
+    async fn set_server(&self, mut router: Router<Sender<Value>>) -> Router<Sender<Value>> {
+        router = self.primary.set_server(router).await;
+        for shadow in &self.shadows {
+            router = shadow.set_server(router).await;
+        }
+        router
+    }
+
+    async fn paths(&self) -> Vec<String> {
+        let mut paths = self.primary.paths().await;
+        for shadow in &self.shadows {
+            paths.extend(shadow.paths().await);
+        }
+        paths
+    }
+}
+
+#[async_trait]
+impl Printable for MirrorRoute {
+    async fn print(&self) -> String {
+        let mut text = format!("MIRROR {}% of traffic\n\nprimary {}\n\n", self.sample, self.primary.print().await);
+        for shadow in &self.shadows {
+            text.push_str(&format!("shadow {}\n\n", shadow.print().await));
+        }
+        text
+    }
+
+    async fn json_struct(&self) -> Value {
+        let mut shadows_json: Vec<Value> = Vec::new();
+        for shadow in &self.shadows {
+            shadows_json.push(shadow.json_struct().await);
+        }
+
+        json!({
+            "type": "mirror",
+            "kind": Self::KIND,
+            "options": {
+                "sample": self.sample,
+                "max_shadow_in_flight": self.max_shadow_in_flight
+            },
+            "primary": self.primary.json_struct().await,
+            "shadows": shadows_json,
+            "metrics": {
+                "primary": self.metrics.primary.load(Ordering::Relaxed),
+                "shadowed": self.metrics.shadowed.load(Ordering::Relaxed),
+                "sampled_out": self.metrics.sampled_out.load(Ordering::Relaxed),
+                "shadow_dropped": self.metrics.shadow_dropped.load(Ordering::Relaxed)
+            }
+        })
+    }
+}
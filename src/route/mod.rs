@@ -1,3 +1,8 @@
 
 pub mod webhook;
 pub mod longpull;
+pub mod filesink;
+pub mod stream;
+pub mod mirror;
+pub mod filter;
+pub mod mediagroup;
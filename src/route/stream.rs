@@ -0,0 +1,359 @@
+use crate::base::{Routeable, Serverable, Printable};
+use async_trait::async_trait;
+
+use axum::{
+    extract::{ws::{Message, WebSocket, WebSocketUpgrade}, Query},
+    response::{sse::{Event, KeepAlive, Sse}, IntoResponse},
+    routing::{get, post},
+    Json, Router,
+};
+use futures_util::stream::{self, Stream};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::mpsc::{self, Sender, UnboundedReceiver, UnboundedSender};
+
+
+#[derive(Deserialize, Debug)]
+pub struct ConnectParams {
+    pub consumer: Option<String>,
+    pub prefetch: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AckParams {
+    pub consumer: String,
+    pub ack: AckIds,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum AckIds {
+    One(u64),
+    Many(Vec<u64>),
+}
+
+impl AckIds {
+    fn into_vec(self) -> Vec<u64> {
+        match self {
+            AckIds::One(id) => vec![id],
+            AckIds::Many(ids) => ids,
+        }
+    }
+}
+
+type Delivery = (u64, Value);
+
+struct Consumer {
+    in_flight: BTreeMap<u64, Value>,
+    prefetch: usize,
+    connection: Option<UnboundedSender<Delivery>>,
+    generation: u64,
+}
+
+struct Hub {
+    queue: VecDeque<Delivery>,
+    consumers: HashMap<String, Consumer>,
+    next_id: u64,
+    cursor: usize,
+}
+
+impl Hub {
+    fn has_room(consumer: &Consumer) -> bool {
+        consumer.connection.is_some() && consumer.in_flight.len() < consumer.prefetch
+    }
+
+    fn send(consumer: &mut Consumer, (id, update): Delivery) {
+        if let Some(connection) = &consumer.connection {
+            let _ = connection.send((id, update.clone()));
+        }
+        consumer.in_flight.insert(id, update);
+    }
+
+    /// Hands queued updates to connected consumers with free prefetch slots,
+    /// rotating the starting consumer so competing clients share the load.
+    fn dispatch(&mut self) {
+        let mut names: Vec<String> = self.consumers.keys().cloned().collect();
+        names.sort();
+
+        while !self.queue.is_empty() {
+            let free = names.iter().enumerate()
+                .map(|(i, _)| (self.cursor + i) % names.len().max(1))
+                .find(|&i| self.consumers.get(&names[i]).is_some_and(Self::has_room));
+
+            let Some(index) = free else { break };
+            self.cursor = index + 1;
+
+            let delivery = self.queue.pop_front().unwrap();
+            Self::send(self.consumers.get_mut(&names[index]).unwrap(), delivery);
+        }
+    }
+}
+
+
+#[derive(Clone)]
+pub struct StreamRoute {
+    hub: Arc<Mutex<Hub>>,
+    pub path: String,
+    prefetch: usize,
+    reclaim_after: Duration,
+}
+
+impl StreamRoute {
+    pub const KIND: &'static str = "StreamRoute";
+
+    pub fn new(path: String) -> Self {
+        Self {
+            hub: Arc::new(Mutex::new(Hub {
+                queue: VecDeque::new(),
+                consumers: HashMap::new(),
+                next_id: 1,
+                cursor: 0,
+            })),
+            path,
+            prefetch: 10,
+            reclaim_after: Duration::from_secs(30),
+        }
+    }
+
+    pub fn set_prefetch(&mut self, prefetch: usize) {
+        self.prefetch = prefetch.max(1);
+    }
+
+    pub fn set_reclaim_after(&mut self, reclaim_after: Duration) {
+        self.reclaim_after = reclaim_after;
+    }
+
+    /// Registers a connection for `consumer`. Updates it has not acked yet are
+    /// sent again first; an older connection with the same id is replaced.
+    fn connect(&self, consumer: String, prefetch: usize) -> (u64, UnboundedReceiver<Delivery>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut hub = self.hub.lock().unwrap();
+
+        let state = hub.consumers.entry(consumer).or_insert_with(|| Consumer {
+            in_flight: BTreeMap::new(),
+            prefetch,
+            connection: None,
+            generation: 0,
+        });
+        state.generation += 1;
+        state.prefetch = prefetch;
+        for (id, update) in state.in_flight.iter() {
+            let _ = tx.send((*id, update.clone()));
+        }
+        state.connection = Some(tx);
+        let generation = state.generation;
+
+        hub.dispatch();
+        (generation, rx)
+    }
+
+    /// Keeps the consumer's unacked updates for `reclaim_after` so a reconnect
+    /// gets them back, then returns them to the shared queue.
+    fn disconnect(&self, consumer: String, generation: u64) {
+        {
+            let mut hub = self.hub.lock().unwrap();
+            match hub.consumers.get_mut(&consumer) {
+                Some(state) if state.generation == generation => state.connection = None,
+                _ => return,
+            }
+        }
+
+        let this = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(this.reclaim_after).await;
+
+            let mut hub = this.hub.lock().unwrap();
+            let reclaimed = match hub.consumers.get(&consumer) {
+                Some(state) if state.generation == generation && state.connection.is_none() => {
+                    hub.consumers.remove(&consumer).unwrap().in_flight
+                }
+                _ => return,
+            };
+            for delivery in reclaimed.into_iter().rev() {
+                hub.queue.push_front(delivery);
+            }
+            hub.dispatch();
+        });
+    }
+
+    fn ack(&self, consumer: &str, ids: Vec<u64>) -> bool {
+        let mut hub = self.hub.lock().unwrap();
+        let Some(state) = hub.consumers.get_mut(consumer) else {
+            return false;
+        };
+        for id in ids {
+            state.in_flight.remove(&id);
+        }
+        hub.dispatch();
+        true
+    }
+
+    fn consumer_id(params: &ConnectParams) -> String {
+        params.consumer.clone().unwrap_or_else(|| format!("anonymous-{:016x}", rand::random::<u64>()))
+    }
+
+    fn frame((id, update): Delivery) -> String {
+        json!({ "id": id, "update": update }).to_string()
+    }
+
+    async fn serve_websocket(self, mut socket: WebSocket, consumer: String, prefetch: usize) {
+        let (generation, mut rx) = self.connect(consumer.clone(), prefetch);
+
+        let hello = json!({ "consumer": consumer }).to_string();
+        if socket.send(Message::Text(hello)).await.is_ok() {
+            loop {
+                tokio::select! {
+                    incoming = socket.recv() => match incoming {
+                        Some(Ok(Message::Text(text))) => {
+                            if let Ok(ack) = serde_json::from_str::<Value>(&text) {
+                                if let Some(ids) = ack.get("ack").cloned().and_then(|a| serde_json::from_value::<AckIds>(a).ok()) {
+                                    self.ack(&consumer, ids.into_vec());
+                                }
+                            }
+                        }
+                        Some(Ok(_)) => {}
+                        _ => break,
+                    },
+                    delivery = rx.recv() => match delivery {
+                        Some(delivery) => {
+                            if socket.send(Message::Text(Self::frame(delivery))).await.is_err() {
+                                break;
+                            }
+                        }
+                        None => break,
+                    },
+                }
+            }
+        }
+
+        self.disconnect(consumer, generation);
+    }
+
+    fn sse_stream(self, consumer: String, prefetch: usize) -> impl Stream<Item = Result<Event, Infallible>> {
+        let (generation, rx) = self.connect(consumer.clone(), prefetch);
+        let hello = Event::default().event("hello").data(json!({ "consumer": consumer }).to_string());
+        let guard = DisconnectGuard { route: self, consumer, generation };
+
+        let updates = stream::unfold((rx, guard), |(mut rx, guard)| async move {
+            let delivery = rx.recv().await?;
+            let event = Event::default()
+                .id(delivery.0.to_string())
+                .event("update")
+                .data(Self::frame(delivery));
+            Some((Ok(event), (rx, guard)))
+        });
+
+        futures_util::StreamExt::chain(stream::once(async move { Ok(hello) }), updates)
+    }
+}
+
+/// SSE has no close callback, the consumer is disconnected when axum drops the stream.
+struct DisconnectGuard {
+    route: StreamRoute,
+    consumer: String,
+    generation: u64,
+}
+
+impl Drop for DisconnectGuard {
+    fn drop(&mut self) {
+        self.route.disconnect(std::mem::take(&mut self.consumer), self.generation);
+    }
+}
+
+
+#[async_trait]
+impl Routeable for StreamRoute {
+    async fn process(&self, update: Value) {
+        let mut hub = self.hub.lock().unwrap();
+        let id = hub.next_id;
+        hub.next_id += 1;
+        hub.queue.push_back((id, update));
+        hub.dispatch();
+    }
+}
+
+#[async_trait]
+impl Serverable for StreamRoute {
+    async fn set_server(&self, router: Router<Sender<Value>>) -> Router<Sender<Value>> {
+        let path = self.path.trim_end_matches('/').to_string();
+
+        let this = self.clone();
+        let ws = move |Query(params): Query<ConnectParams>, upgrade: WebSocketUpgrade| {
+            let this = this.clone();
+            async move {
+                let consumer = Self::consumer_id(&params);
+                let prefetch = params.prefetch.unwrap_or(this.prefetch).max(1);
+                upgrade.on_upgrade(move |socket| this.serve_websocket(socket, consumer, prefetch))
+            }
+        };
+
+        let this = self.clone();
+        let sse = move |Query(params): Query<ConnectParams>| {
+            let this = this.clone();
+            async move {
+                let consumer = Self::consumer_id(&params);
+                let prefetch = params.prefetch.unwrap_or(this.prefetch).max(1);
+                Sse::new(this.sse_stream(consumer, prefetch)).keep_alive(KeepAlive::default())
+            }
+        };
+
+        let this = self.clone();
+        let ack = move |Json(params): Json<AckParams>| {
+            let this = this.clone();
+            async move {
+                if this.ack(&params.consumer, params.ack.into_vec()) {
+                    Json(json!({ "ok": true })).into_response()
+                } else {
+                    Json(json!({
+                        "ok": false,
+                        "error_code": 404,
+                        "description": format!("consumer {} is not known", params.consumer)
+                    })).into_response()
+                }
+            }
+        };
+
+        router
+            .route(&format!("{}/ws", path), get(ws))
+            .route(&format!("{}/sse", path), get(sse))
+            .route(&format!("{}/ack", path), post(ack))
+    }
+
+    async fn paths(&self) -> Vec<String> {
+        let path = self.path.trim_end_matches('/');
+        ["ws", "sse", "ack"].iter().map(|endpoint| format!("{}/{}", path, endpoint)).collect()
+    }
+}
+
+#[async_trait]
+impl Printable for StreamRoute {
+    async fn print(&self) -> String {
+        let path = self.path.trim_end_matches('/');
+        format!("stream: ws://0.0.0.0{}/ws sse: http://0.0.0.0{}/sse", path, path)
+    }
+
+    async fn json_struct(&self) -> Value {
+        let hub = self.hub.lock().unwrap();
+        let connected = hub.consumers.values().filter(|c| c.connection.is_some()).count();
+        let in_flight: usize = hub.consumers.values().map(|c| c.in_flight.len()).sum();
+
+        json!({
+            "type": "stream",
+            "kind": Self::KIND,
+            "options": {
+                "path": self.path,
+                "prefetch": self.prefetch,
+                "reclaim_after": self.reclaim_after.as_secs()
+            },
+            "consumers": connected,
+            "queued": hub.queue.len(),
+            "in_flight": in_flight
+        })
+    }
+}
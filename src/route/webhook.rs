@@ -1,32 +1,137 @@
 use crate::base::{Routeable, Serverable, Printable};
+use crate::utils::breaker::CircuitBreaker;
+use crate::utils::time::unix_millis;
 use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
 use reqwest::Client;
+use ring::hmac;
 use serde_json::{Value, json};
 
-use axum::Json;
+use std::time::{Duration, Instant};
+
+
+/// Header bots behind a Telegram webhook check against their configured secret.
+pub const SECRET_TOKEN_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+pub const SIGNATURE_HEADER: &str = "X-Tgin-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Tgin-Timestamp";
+
+/// Used when neither `timeout_ms` nor the `http` block sets one. Without a bound
+/// a backend that stops answering would pile up delivery tasks forever.
+pub const DEFAULT_DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
 
 pub struct WebhookRoute {
     client: Client,
     url: String,
+    timeout: Duration,
+    breaker: Option<CircuitBreaker>,
+    headers: HeaderMap,
+    signing_key: Option<hmac::Key>,
 }
 
 impl WebhookRoute {
+    pub const KIND: &'static str = "WebhookRoute";
+
     pub fn new(url: String) -> Self {
         Self {
             client: Client::new(),
             url,
+            timeout: DEFAULT_DELIVERY_TIMEOUT,
+            breaker: None,
+            headers: HeaderMap::new(),
+            signing_key: None,
         }
     }
 
     pub fn set_client(&mut self, client: Client) {
         self.client = client;
     }
+
+    /// Upper bound for a single delivery, a timed out request counts as failed.
+    /// `DEFAULT_DELIVERY_TIMEOUT` unless set.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn set_circuit_breaker(&mut self, breaker: CircuitBreaker) {
+        self.breaker = Some(breaker);
+    }
+
+    /// Static headers sent with every delivery, e.g. `Authorization`.
+    pub fn set_headers(&mut self, headers: Vec<(String, String)>) -> Result<(), String> {
+        for (name, value) in headers {
+            let header = HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("invalid header name {}", name))?;
+            let value = HeaderValue::from_str(&value).map_err(|_| format!("invalid value for header {}", name))?;
+            self.headers.insert(header, value);
+        }
+        Ok(())
+    }
+
+    /// Sends `X-Telegram-Bot-Api-Secret-Token` like Telegram does, so bots that
+    /// check it accept deliveries from TGIN.
+    pub fn set_secret_token(&mut self, token: String) -> Result<(), String> {
+        self.set_headers(vec![(SECRET_TOKEN_HEADER.to_string(), token)])
+    }
+
+    /// Signs every delivery: `X-Tgin-Timestamp` carries the unix time in seconds
+    /// and `X-Tgin-Signature` is `sha256=` followed by the hex HMAC-SHA256 of
+    /// `"{timestamp}.{body}"` with `secret`. Receivers should reject old timestamps.
+    pub fn set_signing_secret(&mut self, secret: String) {
+        self.signing_key = Some(hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()));
+    }
+
+    fn signature(key: &hmac::Key, timestamp: u64, body: &[u8]) -> String {
+        let mut context = hmac::Context::with_key(key);
+        context.update(timestamp.to_string().as_bytes());
+        context.update(b".");
+        context.update(body);
+        let tag = context.sign();
+        let hex: String = tag.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+        format!("sha256={}", hex)
+    }
+
+    async fn post(&self, update: &Value) -> bool {
+        let Ok(body) = serde_json::to_vec(update) else {
+            return false;
+        };
+        let mut request = self.client.post(&self.url)
+            .headers(self.headers.clone())
+            .header(CONTENT_TYPE, "application/json");
+        if let Some(key) = &self.signing_key {
+            let timestamp = unix_millis() / 1000;
+            request = request
+                .header(TIMESTAMP_HEADER, timestamp)
+                .header(SIGNATURE_HEADER, Self::signature(key, timestamp, &body));
+        }
+        request = request.body(body).timeout(self.timeout);
+        matches!(request.send().await, Ok(res) if res.status().is_success())
+    }
 }
 
 #[async_trait]
 impl Routeable for WebhookRoute {
     async fn process(&self, update: Value) {
-        let _ = self.client.post(&self.url).json(&update).send().await;
+        let _ = self.try_process(update).await;
+    }
+
+    async fn try_process(&self, update: Value) -> Result<(), Value> {
+        let Some(breaker) = &self.breaker else {
+            return if self.post(&update).await { Ok(()) } else { Err(update) };
+        };
+
+        // fail fast while the circuit is open instead of waiting on a dead backend
+        if !breaker.acquire() {
+            return Err(update);
+        }
+        let started = Instant::now();
+        let ok = self.post(&update).await;
+        breaker.record(ok, started.elapsed());
+
+        if ok { Ok(()) } else { Err(update) }
+    }
+
+    async fn is_healthy(&self) -> bool {
+        self.breaker.as_ref().is_none_or(|b| b.is_available())
     }
 }
 
@@ -35,15 +140,24 @@ impl Serverable for WebhookRoute {}
 #[async_trait]
 impl Printable for WebhookRoute {
     async fn print(&self) -> String {
-        format!("webhook: {}", self.url)
+        match &self.breaker {
+            Some(breaker) => format!("webhook: {} (circuit {})", self.url, breaker.state_name()),
+            None => format!("webhook: {}", self.url),
+        }
     }
 
     async fn json_struct(&self) -> Value {
         json!({
             "type": "webhook",
+            "kind": Self::KIND,
             "options": {
-                "url": self.url
-            }
+                "url": self.url,
+                "timeout_ms": self.timeout.as_millis() as u64,
+                "headers": self.headers.keys().map(|name| name.as_str()).collect::<Vec<_>>(),
+                "signed": self.signing_key.is_some()
+            },
+            "healthy": self.breaker.as_ref().is_none_or(|b| b.is_available()),
+            "circuit_breaker": self.breaker.as_ref().map(|b| b.json_struct())
         })
     }
 }
\ No newline at end of file
@@ -7,13 +7,28 @@ use serde_json::Value;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
 
-use axum_server::tls_rustls::RustlsConfig;
+use std::future::Future;
 use std::net::SocketAddr;
 
 use tokio::runtime::Builder;
 
-use crate::dynamic::handler::dynamic_handler;
+use crate::dynamic::handler::{dynamic_any_handler, dynamic_handler, dynamic_ingress_handler};
+use crate::dynamic::router::{mount, restore, snapshot};
+use crate::dispatch::Dispatcher;
+use crate::lb::split::set_shares;
+use crate::route::filter::edit_filter;
+use crate::dispatch::ordered::{OrderedDispatcher, OrderingKey};
+use crate::dispatch::flood::FloodControl;
+use crate::listener::{ListenAddr, Listener, ListenerComponent};
+use crate::health::{Health, LIVENESS_PATH, READINESS_PATH};
+use crate::update::manager::Updaters;
+use crate::dynamic::router::{mount_ingress, StaticPaths};
+use crate::api::message::UpdaterAction;
+use crate::utils::tls::TlsCerts;
+use crate::config::document::{ConfigDocument, ConfigEdit};
+use crate::config::registry::RouteConfig;
 
 
 pub struct Tgin {
@@ -26,8 +41,24 @@ pub struct Tgin {
     pub ssl_key: Option<String>,
 
     api: Option<Api>,
+
+    ordering: Option<(OrderingKey, usize, usize)>,
+
+    flood: Option<FloodControl>,
+
+    listeners: Vec<Listener>,
+
+    tls: Option<TlsCerts>,
+
+    queue_capacity: usize,
+
+    config: Option<ConfigDocument>,
+
+    paused_updates: Vec<usize>,
 }
 
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1000000;
+
 impl Tgin {
     pub fn new(
         updates: Vec<Box<dyn UpdaterComponent>>,
@@ -42,7 +73,14 @@ impl Tgin {
             server_port,
             ssl_cert: None,
             ssl_key: None,
-            api: None
+            api: None,
+            ordering: None,
+            flood: None,
+            listeners: Vec::new(),
+            tls: None,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            config: None,
+            paused_updates: Vec::new(),
         }
     }
 
@@ -50,11 +88,47 @@ impl Tgin {
         self.api = Some(api);
     }
 
+    pub fn set_ordering(&mut self, key: OrderingKey, lanes: usize, queue_limit: usize) {
+        self.ordering = Some((key, lanes, queue_limit));
+    }
+
+    pub fn set_flood_control(&mut self, flood: FloodControl) {
+        self.flood = Some(flood);
+    }
+
+    /// Extra listener next to the `server_port` one.
+    pub fn add_listener(&mut self, listener: Listener) {
+        self.listeners.push(listener);
+    }
+
     pub fn set_ssl(&mut self, ssl_cert: String, ssl_key: String) {
         self.ssl_cert = Some(ssl_cert);
         self.ssl_key = Some(ssl_key);
     }
 
+    /// TLS for the `server_port` listener with SNI certificates and reloading, replaces `set_ssl`.
+    pub fn set_tls(&mut self, tls: TlsCerts) {
+        self.tls = Some(tls);
+    }
+
+    /// Updates buffered between the providers and dispatch. A `WebhookUpdate`
+    /// finding it full acts on its `WhenFull` setting.
+    pub fn set_queue_capacity(&mut self, queue_capacity: usize) {
+        self.queue_capacity = queue_capacity.max(1);
+    }
+
+    /// The configuration this instance was built from. API changes are
+    /// applied to it too, `GET /api/config` returns it.
+    pub fn set_config(&mut self, config: ConfigDocument) {
+        self.config = Some(config);
+    }
+
+    /// Providers, by position in `updates`, that are registered paused
+    /// instead of started.
+    pub fn set_paused_updates(&mut self, paused_updates: Vec<usize>) {
+        self.paused_updates = paused_updates;
+    }
+
     pub fn run(self) {
         let runtime = Builder::new_multi_thread()
             .worker_threads(self.dark_threads)
@@ -74,6 +148,13 @@ impl Tgin {
 
             println!("{}", &self.route.print().await);
 
+            if let Some(port) = self.server_port {
+                println!("\nLISTEN ON 0.0.0.0:{}", port);
+            }
+            for listener in &self.listeners {
+                println!("\nLISTEN ON {} {:?}", listener.addr, listener.serve);
+            }
+
         });
 
         runtime.block_on(self.run_async());
@@ -82,74 +163,110 @@ impl Tgin {
 
 
     pub async fn run_async(self) {
-        let (tx, mut rx) = mpsc::channel::<Value>(1000000);
+        let (tx, mut rx) = mpsc::channel::<Value>(self.queue_capacity);
 
         let api = self.api;
 
+        let mut listeners = self.listeners;
         if let Some(port) = self.server_port {
-            let mut router: Router<Sender<Value>> = Router::new();
-
-            for provider in &self.updates {
-                router = provider.set_server(router).await;
+            let mut listener = Listener::new(ListenAddr::Tcp(SocketAddr::from(([0, 0, 0, 0], port))));
+            if let Some(tls) = self.tls.clone() {
+                listener.set_tls(tls);
+            } else if let (Some(cert), Some(key)) = (self.ssl_cert.clone(), self.ssl_key.clone()) {
+                listener.set_ssl(cert, key);
             }
+            listeners.insert(0, listener);
+        }
 
-            router = self.route.set_server(router).await;
+        let updates: Vec<Arc<dyn UpdaterComponent>> = self.updates.into_iter().map(Arc::from).collect();
+        let updaters = Arc::new(Updaters::new(&tx));
+        let health = Arc::new(Health::new(updaters.clone(), self.route.clone()));
 
-            
-            if let Some(ref api) = api {
-                router = api.set_server(router).await;
-            }
-            
-            let app = router.with_state(tx.clone());
+        // components added through the API may not reuse any of these
+        let mut static_paths = StaticPaths::default();
+        for provider in &updates {
+            static_paths.extend(provider.paths().await);
+        }
+        static_paths.extend(self.route.paths().await);
+        if let Some(quarantine) = self.flood.as_ref().and_then(|f| f.quarantine()) {
+            static_paths.extend(quarantine.paths().await);
+        }
+        if let Some(api) = &api {
+            static_paths.extend(api.paths().await);
+        }
+        static_paths.extend(vec![LIVENESS_PATH.to_string(), READINESS_PATH.to_string()]);
 
-            let app = if api.is_some() {
-                app.fallback(dynamic_handler)
-            } else {
-                app
-            };
+        if !listeners.is_empty() {
+            // every part is set up once and merged into the listeners that serve it
+            let mut ingress: Router<Sender<Value>> = Router::new();
+            for provider in &updates {
+                ingress = provider.set_server(ingress).await;
+            }
 
-            let addr = SocketAddr::from(([0, 0, 0, 0], port));
+            let mut routes: Router<Sender<Value>> = self.route.set_server(Router::new()).await;
+            if let Some(quarantine) = self.flood.as_ref().and_then(|f| f.quarantine()) {
+                routes = quarantine.set_server(routes).await;
+            }
 
-            match (self.ssl_cert.clone(), self.ssl_key.clone()) {
-                (Some(cert_path), Some(key_path)) => {
-                    let config = RustlsConfig::from_pem_file(cert_path, key_path)
-                        .await
-                        .expect("Failed to load SSL certificates");
+            let api_router = match api {
+                Some(ref api) => Some(api.set_server(Router::new()).await),
+                None => None,
+            };
 
-                    tokio::spawn(async move {
-                        axum_server::bind_rustls(addr, config)
-                            .serve(app.into_make_service())
-                            .await
-                            .unwrap();
-                    });
+            for listener in &listeners {
+                let mut router: Router<Sender<Value>> = Router::new();
+                if listener.serves(ListenerComponent::Ingress) {
+                    router = router.merge(ingress.clone());
+                }
+                if listener.serves(ListenerComponent::Routes) {
+                    router = router.merge(routes.clone());
+                }
+                if listener.serves(ListenerComponent::Health) {
+                    router = router.merge(health.router());
                 }
-                _ => {
-                    tokio::spawn(async move {
-                        let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-                        axum::serve(listener, app).await.unwrap();
-                    });
+                if let Some(api_router) = &api_router {
+                    if listener.serves(ListenerComponent::Api) {
+                        router = router.merge(api_router.clone());
+                    }
+                    // routes and updaters added through the API live in the dynamic registries
+                    match (listener.serves(ListenerComponent::Ingress), listener.serves(ListenerComponent::Routes)) {
+                        (true, true) => router = router.fallback(dynamic_any_handler),
+                        (true, false) => router = router.fallback(dynamic_ingress_handler),
+                        (false, true) => router = router.fallback(dynamic_handler),
+                        (false, false) => {}
+                    }
                 }
+                listener.spawn(router.with_state(tx.clone())).await;
             }
         }
+        health.set_listening();
 
-        for provider in self.updates {
-            let tx_clone = tx.clone();
-            tokio::spawn(async move {
-                provider.start(tx_clone).await;
-            });
+        for (index, provider) in updates.into_iter().enumerate() {
+            if self.paused_updates.contains(&index) {
+                updaters.add_paused(provider);
+            } else {
+                updaters.add(provider);
+            }
         }
 
         drop(tx);
 
+        let dispatcher = match self.ordering {
+            Some((key, lanes, queue_limit)) => Dispatcher::Ordered(Arc::new(
+                OrderedDispatcher::new(self.route.clone(), key, lanes, queue_limit)
+            )),
+            None => Dispatcher::Unordered(self.route.clone()),
+        };
+        let flood = self.flood;
+        let mut config = self.config;
 
 
         match api {
             None => {
                 while let Some(update) = rx.recv().await {
-                    let route_clone = self.route.clone();
-                    tokio::spawn(async move {
-                        route_clone.process(update).await;
-                    });
+                    if flood.as_ref().is_none_or(|f| f.admit(&update)) {
+                        dispatcher.dispatch(update).await;
+                    }
                 }
             },
 
@@ -164,21 +281,74 @@ impl Tgin {
                                     let _ = tx_response.send(self.route.json_struct().await);
                                 }
 
-                                ApiMessage::AddRoute{route, sublevel} => {
-                                    let self_route = self.route.clone();
-                                    match self_route.add_route(route).await {
-                                        Err(_) => {},
-                                        Ok(_) => {}
+                                ApiMessage::GetFlood(tx_response) => {
+                                    let _ = tx_response.send(flood.as_ref().map(|f| f.json_struct()));
+                                }
+
+                                ApiMessage::GetOrdering(tx_response) => {
+                                    let _ = tx_response.send(dispatcher.ordered().map(|o| o.json_struct()));
+                                }
+
+                                ApiMessage::GetConfig(tx_response) => {
+                                    let _ = tx_response.send(match &config {
+                                        Some(config) => config.to_ron(),
+                                        None => Err("the configuration is not available".to_string()),
+                                    });
+                                }
+
+                                ApiMessage::SetShares{name, shares, response} => {
+                                    let result = set_shares(&name, shares);
+                                    let edit = result.as_ref().ok().map(|shares| ConfigEdit::Shares { name, shares: shares.clone() });
+                                    answer_when(record_edit(&mut config, edit), response, result);
+                                }
+
+                                ApiMessage::EditFilter{name, edit, response} => {
+                                    let result = edit_filter(&name, edit);
+                                    let edit = result.as_ref().ok().map(|lists| ConfigEdit::Filter { name, lists: lists.clone() });
+                                    answer_when(record_edit(&mut config, edit), response, result);
+                                }
+
+                                ApiMessage::GetUpdates(tx_response) => {
+                                    let _ = tx_response.send(updaters.json_struct().await);
+                                }
+
+                                ApiMessage::AddUpdate{updater, config: update_config, response} => {
+                                    if let Err(e) = mount_ingress(updater.clone(), &static_paths).await {
+                                        let _ = response.send(Err(e));
+                                        continue;
+                                    }
+                                    let id = updaters.add(updater);
+                                    if let Some(config) = &mut config {
+                                        config.add_update(id, update_config);
+                                    }
+                                    answer_when(write_back(&mut config), response, Ok(id));
+                                }
+
+                                ApiMessage::ManageUpdate{id, action, response} => {
+                                    let (result, edit) = match action {
+                                        UpdaterAction::Pause => (updaters.pause(id), ConfigEdit::UpdatePaused { id, paused: true }),
+                                        UpdaterAction::Resume => (updaters.resume(id), ConfigEdit::UpdatePaused { id, paused: false }),
+                                        UpdaterAction::Remove => (updaters.remove(id), ConfigEdit::UpdateRemoved { id }),
+                                    };
+                                    let edit = result.is_ok().then_some(edit);
+                                    answer_when(record_edit(&mut config, edit), response, result);
+                                }
+
+                                ApiMessage::AddRoute{route, config: route_config, response} => {
+                                    match add_route(&self.route, &static_paths, &mut config, route, route_config).await {
+                                        Ok(()) => answer_when(write_back(&mut config), response, Ok(())),
+                                        Err(e) => {
+                                            let _ = response.send(Err(e));
+                                        }
                                     }
                                 }
                             }
                         },
 
                         Some(update) = rx.recv() => {
-                            let route_clone = self.route.clone();
-                            tokio::spawn(async move {
-                                route_clone.process(update).await;
-                            });
+                            if flood.as_ref().is_none_or(|f| f.admit(&update)) {
+                                dispatcher.dispatch(update).await;
+                            }
                         }
 
                     }
@@ -187,7 +357,78 @@ impl Tgin {
             }
         }
     }
+}
 
 
+/// Mounts `route` unless one of its paths is taken, adds it to the configuration and then to the root route.
+/// A failing step undoes the ones before it, so the live tree and the
+/// configuration never disagree.
+async fn add_route(
+    root: &Arc<dyn RouteableComponent>,
+    taken: &StaticPaths,
+    config: &mut Option<ConfigDocument>,
+    route: Arc<dyn RouteableComponent>,
+    route_config: RouteConfig,
+) -> Result<(), String> {
+    let mounted = snapshot();
+    mount(route.clone(), taken).await?;
+
+    let checkpoint = config.as_mut().map(|config| -> Result<String, String> {
+        let checkpoint = config.checkpoint()?;
+        config.add_route(route_config)?;
+        Ok(checkpoint)
+    }).transpose();
+    let checkpoint = match checkpoint {
+        Ok(checkpoint) => checkpoint,
+        Err(e) => {
+            restore(mounted);
+            return Err(e);
+        }
+    };
+
+    if root.add_route(route).await.is_err() {
+        restore(mounted);
+        if let (Some(config), Some(checkpoint)) = (config.as_mut(), checkpoint) {
+            if let Err(e) = config.rollback(&checkpoint) {
+                eprintln!("Failed to roll back the configuration: {}", e);
+            }
+        }
+        return Err("Root route does not accept new routes".to_string());
+    }
+    Ok(())
+}
+
+/// Applies `edit` to `config` and writes it back when it changed anything.
+fn record_edit(config: &mut Option<ConfigDocument>, edit: Option<ConfigEdit>) -> impl Future<Output = ()> + Send + 'static {
+    let changed = match (config.as_mut(), edit) {
+        (Some(config), Some(edit)) => config.edit(&edit),
+        _ => false,
+    };
+    let write = changed.then(|| write_back(config));
+    async move {
+        if let Some(write) = write {
+            write.await;
+        }
+    }
+}
+
+/// Writes `config` back off the dispatch loop, the returned future is done
+/// once it is on disk or failed to get there.
+fn write_back(config: &mut Option<ConfigDocument>) -> impl Future<Output = ()> + Send + 'static {
+    let write = config.as_mut().map(|config| (config.path().display().to_string(), config.write_back()));
+    async move {
+        if let Some((path, write)) = write {
+            if let Err(e) = write.await {
+                eprintln!("Failed to write {}: {}", path, e);
+            }
+        }
+    }
+}
 
+/// Sends `result` once `written` is done, so a change the API reports is on disk.
+fn answer_when<T: Send + 'static>(written: impl Future<Output = ()> + Send + 'static, response: oneshot::Sender<T>, result: T) {
+    tokio::spawn(async move {
+        written.await;
+        let _ = response.send(result);
+    });
 }
@@ -1,12 +1,46 @@
-
-
 use async_trait::async_trait;
+use serde::Serialize;
 use serde_json::Value;
 use tokio::sync::mpsc::Sender;
 
+use crate::utils::time::unix_millis;
+
 #[async_trait]
 pub trait Updater: Send + Sync {
     async fn start(&self, tx: Sender<Value>);
 
+    /// Whether the updater currently receives updates, reported by `/readyz`.
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    /// Reported by `GET /api/updates`.
+    fn status(&self) -> UpdaterStatus {
+        UpdaterStatus::default()
+    }
+
+    /// Called when the updater is paused or removed through the API, after its
+    /// `start` task was stopped. Updaters serving HTTP stop accepting updates.
+    fn set_paused(&self, _paused: bool) {}
+
+}
+
+/// What an updater last did.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct UpdaterStatus {
+    /// Unix millis of the last successful `getUpdates` call or received update.
+    pub last_success: Option<u64>,
+    /// Next `getUpdates` offset.
+    pub offset: Option<i64>,
+    pub last_error: Option<String>,
 }
 
+impl UpdaterStatus {
+    pub fn succeeded(&mut self) {
+        self.last_success = Some(unix_millis());
+    }
+
+    pub fn failed(&mut self, error: String) {
+        self.last_error = Some(error);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::base::{Serverable, Printable};
-use crate::update::base::Updater;
+use crate::update::base::{Updater, UpdaterStatus};
 use crate::utils::defaults::TELEGRAM_TOKEN_REGEX;
 
 use async_trait::async_trait;
@@ -10,23 +10,56 @@ use tokio::time::{sleep, Duration};
 
 use regex::Regex;
 
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
 
 pub struct LongPollUpdate {
     client: Client,
     url: String,
     default_timeout_sleep: u64,
     error_timeout_sleep: u64,
+    max_error_sleep: u64,
+    timeout: u64,
+    request_timeout: Option<Duration>,
+    limit: u32,
+    allowed_updates: Option<Vec<String>>,
+    delete_webhook_on_conflict: bool,
     token_regex: Regex,
+    /// The last `getUpdates` call succeeded.
+    ready: AtomicBool,
+    status: Mutex<UpdaterStatus>,
+}
+
+/// Why a `getUpdates` call did not return updates.
+enum PollError {
+    /// 401, the token is no longer valid.
+    Unauthorized(String),
+    /// 429, Telegram asks to wait this many seconds.
+    RetryAfter(u64),
+    /// 409, a webhook is set or another instance is polling the same bot.
+    Conflict(String),
+    Other(String),
 }
 
 impl LongPollUpdate {
+    pub const KIND: &'static str = "LongPollUpdate";
+
     pub fn new(token: String) -> Self {
         Self {
             client: Client::new(),
             url: format!("https://api.telegram.org/bot{}/getUpdates", token),
             default_timeout_sleep: 0,
             error_timeout_sleep: 100,
+            max_error_sleep: 30_000,
+            timeout: 30,
+            request_timeout: None,
+            limit: 100,
+            allowed_updates: None,
+            delete_webhook_on_conflict: false,
             token_regex: Regex::new(TELEGRAM_TOKEN_REGEX).unwrap(),
+            ready: AtomicBool::new(false),
+            status: Mutex::new(UpdaterStatus::default()),
         }
     }
 
@@ -38,54 +71,167 @@ impl LongPollUpdate {
         self.url = url;
     }
 
+    /// `error_timeout_sleep` is the first wait after a failed call, it doubles
+    /// with every failure in a row up to `set_max_error_sleep`.
     pub fn set_timeouts(&mut self, default_timeout_sleep: u64, error_timeout_sleep: u64) {
         self.default_timeout_sleep = default_timeout_sleep;
         self.error_timeout_sleep = error_timeout_sleep;
     }
 
+    pub fn set_max_error_sleep(&mut self, max_error_sleep: u64) {
+        self.max_error_sleep = max_error_sleep;
+    }
+
+    /// `timeout` (seconds) and `limit` sent with every `getUpdates`.
+    pub fn set_poll(&mut self, timeout: u64, limit: u32) {
+        self.timeout = timeout;
+        self.limit = limit.clamp(1, 100);
+    }
+
+    /// Time allowed for a `getUpdates` call on top of its long poll `timeout`.
+    pub fn set_request_timeout(&mut self, timeout: Duration) {
+        self.request_timeout = Some(timeout);
+    }
+
+    pub fn set_allowed_updates(&mut self, allowed_updates: Vec<String>) {
+        self.allowed_updates = Some(allowed_updates);
+    }
+
+    /// On 409 Conflict delete the webhook that blocks polling instead of only warning.
+    pub fn set_delete_webhook_on_conflict(&mut self, delete: bool) {
+        self.delete_webhook_on_conflict = delete;
+    }
+
     pub fn set_regex_token(&mut self, regex: Regex) {
         self.token_regex = regex;
     }
 
+    fn method_url(&self, method: &str) -> String {
+        match self.url.rsplit_once('/') {
+            Some((base, _)) => format!("{}/{}", base, method),
+            None => method.to_string(),
+        }
+    }
+
+    async fn poll(&self, offset: i64) -> Result<Vec<Value>, PollError> {
+        let mut params = vec![
+            ("offset", offset.to_string()),
+            ("timeout", self.timeout.to_string()),
+            ("limit", self.limit.to_string()),
+        ];
+        if let Some(allowed) = &self.allowed_updates {
+            params.push(("allowed_updates", serde_json::to_string(allowed).unwrap_or_default()));
+        }
+
+        let mut request = self.client.get(&self.url).query(&params);
+        if let Some(timeout) = self.request_timeout {
+            request = request.timeout(timeout + Duration::from_secs(self.timeout));
+        }
+        let res = request.send().await
+            .map_err(|err| PollError::Other(format!("network error: {}", err)))?;
+        let status = res.status().as_u16();
+        let json = res.json::<Value>().await
+            .map_err(|err| PollError::Other(format!("invalid response ({}): {}", status, err)))?;
+
+        if json.get("ok").and_then(|ok| ok.as_bool()) == Some(true) {
+            return Ok(json.get("result").and_then(|r| r.as_array()).cloned().unwrap_or_default());
+        }
+
+        let description = json.get("description").and_then(|d| d.as_str()).unwrap_or("").to_string();
+        let code = json.get("error_code").and_then(|c| c.as_u64()).unwrap_or(status as u64);
+        let retry_after = json.get("parameters")
+            .and_then(|p| p.get("retry_after"))
+            .and_then(|r| r.as_u64());
+
+        Err(match (code, retry_after) {
+            (401, _) => PollError::Unauthorized(description),
+            (429, Some(secs)) => PollError::RetryAfter(secs),
+            (409, _) => PollError::Conflict(description),
+            _ => PollError::Other(format!("{} {}", code, description)),
+        })
+    }
+
+    async fn delete_webhook(&self) {
+        match self.client.post(self.method_url("deleteWebhook")).send().await {
+            Ok(res) if res.status().is_success() => eprintln!("LongPollUpdate: deleted the webhook that blocked getUpdates"),
+            Ok(res) => eprintln!("LongPollUpdate: deleteWebhook failed with {}", res.status()),
+            Err(err) => eprintln!("LongPollUpdate: deleteWebhook failed: {}", err),
+        }
+    }
+
+    fn error_sleep(&self, failures: u32) -> Duration {
+        let factor = 1u64.checked_shl(failures.saturating_sub(1)).unwrap_or(u64::MAX);
+        Duration::from_millis(self.error_timeout_sleep.saturating_mul(factor).min(self.max_error_sleep))
+    }
 }
 
 #[async_trait]
 impl Updater for LongPollUpdate {
     async fn start(&self, tx: Sender<Value>) {
-        let mut offset = 0;
+        // kept across restarts, so a resumed poller confirms what it already forwarded
+        let mut offset = self.status.lock().unwrap().offset.unwrap_or(0);
+        let mut failures = 0;
 
         loop {
-            let params = [("offset", offset.to_string()), ("timeout", "30".to_string()), ("limit", "100".to_string())];
-            match self.client.get(&self.url).query(&params).send().await {
-                Ok(res) => {
-                    match res.json::<Value>().await {
-                        Ok(json) => {
-                            if let Some(result) = json.get("result").and_then(|r| r.as_array()) {
-                                for update in result {
-                                    if let Some(id) = update.get("update_id").and_then(|i| i.as_i64()) {
-                                        offset = id + 1;
-                                        if tx.send(update.clone()).await.is_err() {
-                                            return;
-                                        }
-                                    }
-                                }
+            match self.poll(offset).await {
+                Ok(updates) => {
+                    failures = 0;
+                    self.ready.store(true, Ordering::SeqCst);
+                    self.status.lock().unwrap().succeeded();
+                    for update in updates {
+                        if let Some(id) = update.get("update_id").and_then(|i| i.as_i64()) {
+                            if tx.send(update).await.is_err() {
+                                return;
                             }
-                            sleep(Duration::from_millis(self.default_timeout_sleep)).await;
-                        }
-                        Err(err) => {
-                            eprintln!("JSON parse error: {:?}", err);
-                            sleep(Duration::from_millis(self.error_timeout_sleep)).await;
+                            offset = id + 1;
+                            self.status.lock().unwrap().offset = Some(offset);
                         }
                     }
+                    sleep(Duration::from_millis(self.default_timeout_sleep)).await;
                 }
-                Err(err) => {
-                    eprintln!("Network error: {:?}", err);
-
-                    sleep(Duration::from_millis(self.error_timeout_sleep)).await;
+                Err(PollError::Unauthorized(description)) => {
+                    self.ready.store(false, Ordering::SeqCst);
+                    self.status.lock().unwrap().failed(format!("401 Unauthorized: {}", description));
+                    eprintln!("LongPollUpdate stopped, Telegram rejected the bot token: {}", description);
+                    return;
+                }
+                Err(PollError::RetryAfter(secs)) => {
+                    self.status.lock().unwrap().failed(format!("429 Too Many Requests: retry after {}s", secs));
+                    eprintln!("LongPollUpdate: too many requests, retrying in {}s", secs);
+                    sleep(Duration::from_secs(secs)).await;
+                }
+                Err(PollError::Conflict(description)) => {
+                    failures += 1;
+                    self.ready.store(false, Ordering::SeqCst);
+                    self.status.lock().unwrap().failed(format!("409 Conflict: {}", description));
+                    if self.delete_webhook_on_conflict {
+                        self.delete_webhook().await;
+                    } else {
+                        eprintln!(
+                            "WARNING: LongPollUpdate gets 409 Conflict ({}). A webhook is set for this bot or another instance polls it, updates are not received.",
+                            description
+                        );
+                    }
+                    sleep(self.error_sleep(failures)).await;
+                }
+                Err(PollError::Other(err)) => {
+                    failures += 1;
+                    self.ready.store(false, Ordering::SeqCst);
+                    self.status.lock().unwrap().failed(err.clone());
+                    eprintln!("LongPollUpdate: getUpdates failed: {}", err);
+                    sleep(self.error_sleep(failures)).await;
                 }
             }
         }
     }
+
+    fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    fn status(&self) -> UpdaterStatus {
+        self.status.lock().unwrap().clone()
+    }
 }
 
 impl Serverable for LongPollUpdate {}
@@ -0,0 +1,136 @@
+use crate::base::UpdaterComponent;
+
+use serde_json::{json, Value};
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc::{Sender, WeakSender};
+use tokio::task::JoinHandle;
+
+
+struct Entry {
+    updater: Arc<dyn UpdaterComponent>,
+    /// `None` while paused.
+    task: Option<JoinHandle<()>>,
+}
+
+impl Entry {
+    fn state(&self) -> &'static str {
+        match &self.task {
+            None => "paused",
+            Some(task) if task.is_finished() => "stopped",
+            Some(_) => "running",
+        }
+    }
+}
+
+/// Updaters of a running instance by id: the ones from the config in config
+/// order, then the ones added through the API. Pausing stops the `start`
+/// task of an updater, resuming starts it again.
+pub struct Updaters {
+    tx: WeakSender<Value>,
+    entries: Mutex<BTreeMap<usize, Entry>>,
+    next_id: Mutex<usize>,
+}
+
+impl Updaters {
+    /// Only a weak handle to `tx` is kept, the queue still closes once every
+    /// updater and listener is gone.
+    pub fn new(tx: &Sender<Value>) -> Self {
+        Self {
+            tx: tx.downgrade(),
+            entries: Mutex::new(BTreeMap::new()),
+            next_id: Mutex::new(0),
+        }
+    }
+
+    fn spawn(&self, updater: &Arc<dyn UpdaterComponent>) -> Option<JoinHandle<()>> {
+        let tx = self.tx.upgrade()?;
+        let updater = updater.clone();
+        Some(tokio::spawn(async move {
+            updater.start(tx).await;
+        }))
+    }
+
+    /// Starts the updater and returns its id.
+    pub fn add(&self, updater: Arc<dyn UpdaterComponent>) -> usize {
+        let task = self.spawn(&updater);
+        self.insert(Entry { updater, task })
+    }
+
+    /// Registers the updater without starting it, `resume` does.
+    pub fn add_paused(&self, updater: Arc<dyn UpdaterComponent>) -> usize {
+        updater.set_paused(true);
+        self.insert(Entry { updater, task: None })
+    }
+
+    fn insert(&self, entry: Entry) -> usize {
+        let id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            *next_id - 1
+        };
+        self.entries.lock().unwrap().insert(id, entry);
+        id
+    }
+
+    pub fn pause(&self, id: usize) -> Result<(), String> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(&id).ok_or_else(|| format!("no updater {}", id))?;
+        let task = entry.task.take().ok_or_else(|| format!("updater {} is already paused", id))?;
+        task.abort();
+        entry.updater.set_paused(true);
+        Ok(())
+    }
+
+    pub fn resume(&self, id: usize) -> Result<(), String> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(&id).ok_or_else(|| format!("no updater {}", id))?;
+        if entry.task.as_ref().is_some_and(|task| !task.is_finished()) {
+            return Err(format!("updater {} is already running", id));
+        }
+        entry.updater.set_paused(false);
+        entry.task = self.spawn(&entry.updater);
+        Ok(())
+    }
+
+    /// Stops the updater for good. Paths of a removed `WebhookUpdate` stay
+    /// mounted and answer 503 until restart.
+    pub fn remove(&self, id: usize) -> Result<(), String> {
+        let entry = self.entries.lock().unwrap().remove(&id).ok_or_else(|| format!("no updater {}", id))?;
+        if let Some(task) = entry.task {
+            task.abort();
+        }
+        entry.updater.set_paused(true);
+        Ok(())
+    }
+
+    /// Updaters that are not paused.
+    pub fn active(&self) -> Vec<Arc<dyn UpdaterComponent>> {
+        self.entries.lock().unwrap()
+            .values()
+            .filter(|entry| entry.task.is_some())
+            .map(|entry| entry.updater.clone())
+            .collect()
+    }
+
+    pub async fn json_struct(&self) -> Value {
+        let entries: Vec<_> = self.entries.lock().unwrap()
+            .iter()
+            .map(|(id, entry)| (*id, entry.state(), entry.updater.clone()))
+            .collect();
+
+        let mut list = Vec::new();
+        for (id, state, updater) in entries {
+            list.push(json!({
+                "id": id,
+                "name": updater.print().await.trim(),
+                "state": state,
+                "ready": updater.is_ready(),
+                "status": updater.status(),
+            }));
+        }
+        Value::Array(list)
+    }
+}
@@ -1,3 +1,5 @@
 pub mod base;
 pub mod webhook;
 pub mod longpull;
+pub mod replay;
+pub mod manager;
@@ -0,0 +1,174 @@
+use crate::base::{Serverable, Printable};
+use crate::update::base::Updater;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use tokio::sync::mpsc::{self, Sender};
+use tokio::time::{sleep, Duration};
+
+use flate2::read::GzDecoder;
+
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum ReplaySpeed {
+    /// Keep the gaps between updates as they were recorded.
+    #[default]
+    Original,
+    /// Divide the recorded gaps, `Scaled(2.0)` replays twice as fast.
+    Scaled(f64),
+    /// No gaps at all.
+    Max,
+}
+
+pub struct ReplayUpdate {
+    path: PathBuf,
+    speed: ReplaySpeed,
+}
+
+impl ReplayUpdate {
+    pub const KIND: &'static str = "ReplayUpdate";
+
+    pub fn new(path: String) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            speed: ReplaySpeed::Original,
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: ReplaySpeed) {
+        self.speed = speed;
+    }
+
+    fn delay(&self, gap_ms: u64) -> Duration {
+        match self.speed {
+            ReplaySpeed::Original => Duration::from_millis(gap_ms),
+            ReplaySpeed::Scaled(factor) if factor > 0.0 => Duration::from_secs_f64(gap_ms as f64 / 1000.0 / factor),
+            ReplaySpeed::Scaled(_) | ReplaySpeed::Max => Duration::ZERO,
+        }
+    }
+
+    /// Recorded files in replay order. File names written by `FileSinkRoute`
+    /// start with the creation time, so name order is chronological.
+    fn files(&self) -> std::io::Result<Vec<PathBuf>> {
+        if self.path.is_file() {
+            return Ok(vec![self.path.clone()]);
+        }
+
+        let mut files: Vec<PathBuf> = std::fs::read_dir(&self.path)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                let name = path.to_string_lossy();
+                name.ends_with(".jsonl") || name.ends_with(".jsonl.gz")
+            })
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// Lines of a recording waiting to be replayed. The reader stays this far ahead.
+const READ_AHEAD: usize = 1024;
+
+/// Reads `path` line by line into `lines`, so a recording never has to fit in
+/// memory. Stops at the first error or when the receiver is gone.
+fn read_lines(path: &Path, lines: mpsc::Sender<std::io::Result<String>>) {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) => {
+            let _ = lines.blocking_send(Err(err));
+            return;
+        }
+    };
+    let reader: Box<dyn BufRead> = if path.to_string_lossy().ends_with(".gz") {
+        Box::new(BufReader::new(GzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    };
+
+    for line in reader.lines() {
+        let failed = line.is_err();
+        if lines.blocking_send(line).is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// Accepts both `FileSinkRoute` records (`{"ts": .., "update": {..}}`) and bare updates.
+fn parse_record(line: &str) -> Option<(Option<u64>, Value)> {
+    let mut record: Value = serde_json::from_str(line).ok()?;
+    match record.get_mut("update").map(Value::take) {
+        Some(update) => Some((record.get("ts").and_then(|t| t.as_u64()), update)),
+        None => Some((None, record)),
+    }
+}
+
+
+#[async_trait]
+impl Updater for ReplayUpdate {
+    async fn start(&self, tx: Sender<Value>) {
+        let files = match self.files() {
+            Ok(files) => files,
+            Err(err) => {
+                eprintln!("Replay error in {}: {:?}", self.path.display(), err);
+                return;
+            }
+        };
+
+        let mut last_ts: Option<u64> = None;
+        let mut replayed = 0;
+
+        for path in files {
+            let read_path = path.clone();
+            let (lines_tx, mut lines) = mpsc::channel(READ_AHEAD);
+            tokio::task::spawn_blocking(move || read_lines(&read_path, lines_tx));
+
+            while let Some(line) = lines.recv().await {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(err) => {
+                        eprintln!("Replay error in {}: {:?}", path.display(), err);
+                        break;
+                    }
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let Some((ts, update)) = parse_record(&line) else {
+                    eprintln!("Replay skipped malformed line in {}", path.display());
+                    continue;
+                };
+
+                if let (Some(prev), Some(ts)) = (last_ts, ts) {
+                    let delay = self.delay(ts.saturating_sub(prev));
+                    if !delay.is_zero() {
+                        sleep(delay).await;
+                    }
+                }
+                if ts.is_some() {
+                    last_ts = ts;
+                }
+
+                if tx.send(update).await.is_err() {
+                    return;
+                }
+                replayed += 1;
+            }
+        }
+
+        println!("Replay finished: {} updates from {}", replayed, self.path.display());
+    }
+}
+
+impl Serverable for ReplayUpdate {}
+
+#[async_trait]
+impl Printable for ReplayUpdate {
+    async fn print(&self) -> String {
+        format!("replay: {} {:?}", self.path.display(), self.speed)
+    }
+}
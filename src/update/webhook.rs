@@ -1,15 +1,34 @@
 use crate::base::{Serverable, Printable};
-use crate::update::base::Updater;
+use crate::listener::ClientAddr;
+use crate::update::base::{Updater, UpdaterStatus};
 
 use crate::utils::defaults::TELEGRAM_TOKEN_REGEX;
+use crate::utils::source_ip::SourceFilter;
 
 use async_trait::async_trait;
-use axum::{extract::State, routing::post, Json, Router};
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, FromRequest, Request, State},
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::post,
+    Router,
+};
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
+use std::io::SeekFrom;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
 use reqwest::Client;
 
+use tokio::io::{AsyncBufReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::Mutex;
 
 use regex::Regex;
 
@@ -47,16 +66,89 @@ impl RegistrationWebhookConfig {
 }
 
 
+/// What the endpoint does with an update when the dispatch queue is full.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub enum WhenFull {
+    /// Wait for room, Telegram sees a slow response.
+    #[default]
+    Wait,
+    /// Answer with `status` (4xx or 5xx) so Telegram keeps the update and
+    /// delivers it again later, optionally with a `Retry-After` header.
+    Reject { status: u16, retry_after: Option<u64> },
+    /// Accept the update and append it to this JSONL file. Spilled updates
+    /// are fed back into the queue once it is at most half full, after the
+    /// ones that arrived in the meantime. A restart resumes after the last
+    /// update fed back, only an update queued right before a crash may come twice.
+    Spill(String),
+}
+
+pub const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// How often a spill file is checked for updates to feed back.
+const SPILL_DRAIN_EVERY: Duration = Duration::from_secs(1);
+
 pub struct WebhookUpdate {
     path: String,
     registration: Option<RegistrationWebhookConfig>, 
+    source_filter: Option<Arc<SourceFilter>>,
+    when_full: WhenFull,
+    max_body_bytes: usize,
+    request_timeout: Option<Duration>,
+    spill_lock: Arc<Mutex<()>>,
+    registered: AtomicBool,
+    paused: Arc<AtomicBool>,
+    status: Arc<std::sync::Mutex<UpdaterStatus>>,
 }
 
 
 
 impl WebhookUpdate {
+    pub const KIND: &'static str = "WebhookUpdate";
+
     pub fn new(path: String) -> Self {
-        Self { path, registration: None }
+        Self {
+            path,
+            registration: None,
+            source_filter: None,
+            when_full: WhenFull::Wait,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            request_timeout: None,
+            spill_lock: Arc::new(Mutex::new(())),
+            registered: AtomicBool::new(false),
+            paused: Arc::new(AtomicBool::new(false)),
+            status: Arc::new(std::sync::Mutex::new(UpdaterStatus::default())),
+        }
+    }
+
+    pub fn set_when_full(&mut self, when_full: WhenFull) -> Result<(), String> {
+        if let WhenFull::Reject { status, .. } = when_full {
+            if !(400..600).contains(&status) {
+                return Err(format!("status {} would not make Telegram retry the update", status));
+            }
+        }
+        self.when_full = when_full;
+        Ok(())
+    }
+
+    /// Bodies above the limit are answered with 413.
+    pub fn set_max_body_bytes(&mut self, max_body_bytes: usize) {
+        self.max_body_bytes = max_body_bytes;
+    }
+
+    /// Longest a request may take, reading the body and waiting for room in
+    /// the queue included. Slower requests are answered with 503.
+    pub fn set_request_timeout(&mut self, request_timeout: Duration) {
+        self.request_timeout = Some(request_timeout);
+    }
+
+    /// Accepts updates only from the networks of `filter`, requests from other
+    /// addresses are answered with 403.
+    pub fn set_source_filter(&mut self, filter: SourceFilter) {
+        self.source_filter = Some(Arc::new(filter));
+    }
+
+    pub fn set_registration(&mut self, registration: RegistrationWebhookConfig) {
+        self.registration = Some(registration);
     }
 
 
@@ -69,11 +161,16 @@ impl WebhookUpdate {
             Ok(resp) => {
                 if resp.status().is_success() {
                     println!("Webhook set successfully for path: {}", self.path);
+                    self.registered.store(true, Ordering::SeqCst);
                 } else {
                     eprintln!("Failed to set webhook. Status: {}", resp.status());
+                    self.status.lock().unwrap().failed(format!("setWebhook answered {}", resp.status()));
                 }
             }
-            Err(e) => eprintln!("Network error setting webhook: {}", e),
+            Err(e) => {
+                eprintln!("Network error setting webhook: {}", e);
+                self.status.lock().unwrap().failed(format!("setWebhook failed: {}", e));
+            }
         }
     }
 
@@ -82,12 +179,107 @@ impl WebhookUpdate {
 
 #[async_trait]
 impl Updater for WebhookUpdate {
-    async fn start(&self, _tx: Sender<Value>) {
+    async fn start(&self, tx: Sender<Value>) {
         if let Some(config) = &self.registration {
             self.register_webhook(config).await;
         } else {
             println!("Webhook started in passive mode (no auto-registration) for {}", self.path);
         }
+        if let WhenFull::Spill(path) = &self.when_full {
+            drain_spill(path, &self.spill_lock, tx).await;
+        }
+    }
+
+    /// Passive webhooks are ready right away, registering ones once `setWebhook` succeeded.
+    fn is_ready(&self) -> bool {
+        self.registration.is_none() || self.registered.load(Ordering::SeqCst)
+    }
+
+    fn status(&self) -> UpdaterStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// A paused endpoint answers 503, so Telegram keeps the updates.
+    fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+    }
+}
+
+
+/// Feeds spilled updates back into the queue. The spill file is renamed
+/// before reading, so the endpoint can keep appending to a new one; a
+/// `.draining` file left by a previous run is sent first, from where it
+/// stopped.
+async fn drain_spill(path: &str, lock: &Mutex<()>, tx: Sender<Value>) {
+    let draining = format!("{}.draining", path);
+    let offset = format!("{}.offset", draining);
+    loop {
+        if tokio::fs::metadata(&draining).await.is_err() {
+            tokio::time::sleep(SPILL_DRAIN_EVERY).await;
+            if tx.capacity() * 2 < tx.max_capacity() {
+                continue;
+            }
+            let _guard = lock.lock().await;
+            // left over when a run stopped between removing the two files
+            let _ = tokio::fs::remove_file(&offset).await;
+            if tokio::fs::rename(path, &draining).await.is_err() {
+                continue;
+            }
+        }
+
+        match send_spilled(&draining, &offset, &tx).await {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(err) => {
+                eprintln!("Webhook spill: failed to read {}: {}", draining, err);
+                tokio::time::sleep(SPILL_DRAIN_EVERY).await;
+                continue;
+            }
+        }
+        if let Err(err) = tokio::fs::remove_file(&draining).await {
+            eprintln!("Webhook spill: failed to remove {}: {}", draining, err);
+            return;
+        }
+        let _ = tokio::fs::remove_file(&offset).await;
+    }
+}
+
+/// Sends the updates in `draining` from the byte offset kept in `offset_path`
+/// on, moving the offset past every line once it is queued. Returns `false`
+/// when the queue is gone.
+async fn send_spilled(draining: &str, offset_path: &str, tx: &Sender<Value>) -> std::io::Result<bool> {
+    let mut offset: u64 = match tokio::fs::read_to_string(offset_path).await {
+        Ok(saved) => saved.trim().parse().unwrap_or(0),
+        Err(_) => 0,
+    };
+    let mut file = tokio::fs::File::open(draining).await?;
+    file.seek(SeekFrom::Start(offset)).await?;
+    let mut lines = BufReader::new(file);
+    let mut progress = tokio::fs::OpenOptions::new().create(true).write(true).truncate(false).open(offset_path).await?;
+
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = lines.read_line(&mut line).await?;
+        if read == 0 {
+            return Ok(true);
+        }
+        offset += read as u64;
+
+        if !line.trim().is_empty() {
+            match serde_json::from_str::<Value>(&line) {
+                Ok(update) => {
+                    if tx.send(update).await.is_err() {
+                        return Ok(false);
+                    }
+                }
+                Err(err) => eprintln!("Webhook spill: skipping malformed line in {}: {}", draining, err),
+            }
+        }
+        // fixed width, so the previous value is always overwritten completely
+        progress.seek(SeekFrom::Start(0)).await?;
+        progress.write_all(format!("{:020}", offset).as_bytes()).await?;
+        progress.flush().await?;
     }
 }
 
@@ -95,13 +287,133 @@ impl Updater for WebhookUpdate {
 #[async_trait]
 impl Serverable for WebhookUpdate {
     async fn set_server(&self, router: Router<Sender<Value>>) -> Router<Sender<Value>> {
-        router.route(&self.path, post(handler))
+        let intake = Arc::new(Intake {
+            when_full: self.when_full.clone(),
+            request_timeout: self.request_timeout,
+            spill_lock: self.spill_lock.clone(),
+            paused: self.paused.clone(),
+            status: self.status.clone(),
+        });
+        let handler = post(move |State(tx): State<Sender<Value>>, request: Request| {
+            let intake = intake.clone();
+            async move { intake.receive(tx, request).await }
+        })
+        .layer(DefaultBodyLimit::max(self.max_body_bytes));
+
+        let Some(filter) = self.source_filter.clone() else {
+            return router.route(&self.path, handler);
+        };
+        let path = self.path.clone();
+        let check = middleware::from_fn(move |request: Request, next: Next| {
+            let filter = filter.clone();
+            let path = path.clone();
+            async move { check_source(&filter, &path, request, next).await }
+        });
+        router.route(&self.path, handler.layer(check))
     }
+
+    async fn paths(&self) -> Vec<String> {
+        vec![self.path.clone()]
+    }
+}
+
+
+async fn check_source(filter: &SourceFilter, path: &str, request: Request, next: Next) -> Response {
+    let peer = request.extensions().get::<ClientAddr>().map(|addr| addr.0.ip());
+    if filter.allows(peer, request.headers()) {
+        return next.run(request).await;
+    }
+    let client = filter.client_ip(peer, request.headers());
+    eprintln!(
+        "Webhook {}: rejected update from {}",
+        path,
+        client.map(|ip| ip.to_string()).unwrap_or_else(|| "unknown address".to_string()),
+    );
+    StatusCode::FORBIDDEN.into_response()
 }
 
 
-async fn handler(State(tx): State<Sender<Value>>, Json(update): Json<Value>) {
-    let _ = tx.send(update).await;
+/// Request handling of the endpoint, shared by its requests.
+struct Intake {
+    when_full: WhenFull,
+    request_timeout: Option<Duration>,
+    spill_lock: Arc<Mutex<()>>,
+    paused: Arc<AtomicBool>,
+    status: Arc<std::sync::Mutex<UpdaterStatus>>,
+}
+
+impl Intake {
+    async fn receive(&self, tx: Sender<Value>, request: Request) -> Response {
+        if self.paused.load(Ordering::SeqCst) {
+            return StatusCode::SERVICE_UNAVAILABLE.into_response();
+        }
+        let response = self.receive_in_time(tx, request).await;
+        if response.status().is_success() {
+            self.status.lock().unwrap().succeeded();
+        }
+        response
+    }
+
+    async fn receive_in_time(&self, tx: Sender<Value>, request: Request) -> Response {
+        let Some(limit) = self.request_timeout else {
+            return self.accept(tx, request).await;
+        };
+        tokio::time::timeout(limit, self.accept(tx, request))
+            .await
+            .unwrap_or_else(|_| StatusCode::SERVICE_UNAVAILABLE.into_response())
+    }
+
+    async fn accept(&self, tx: Sender<Value>, request: Request) -> Response {
+        let body = match Bytes::from_request(request, &()).await {
+            Ok(body) => body,
+            Err(rejection) => return rejection.into_response(),
+        };
+        let update: Value = match serde_json::from_slice(&body) {
+            Ok(update) => update,
+            Err(err) => return (StatusCode::BAD_REQUEST, format!("Invalid update: {}", err)).into_response(),
+        };
+        if !update.get("update_id").is_some_and(Value::is_u64) {
+            return (StatusCode::BAD_REQUEST, "Invalid update: no update_id").into_response();
+        }
+
+        let update = match tx.try_send(update) {
+            Ok(()) => return StatusCode::OK.into_response(),
+            Err(TrySendError::Closed(_)) => return StatusCode::SERVICE_UNAVAILABLE.into_response(),
+            Err(TrySendError::Full(update)) => update,
+        };
+        match &self.when_full {
+            WhenFull::Wait => match tx.send(update).await {
+                Ok(()) => StatusCode::OK.into_response(),
+                Err(_) => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+            },
+            WhenFull::Reject { status, retry_after } => {
+                let status = StatusCode::from_u16(*status).unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+                let mut response = status.into_response();
+                if let Some(secs) = retry_after {
+                    response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(*secs));
+                }
+                response
+            }
+            WhenFull::Spill(path) => match self.spill(path, &update).await {
+                Ok(()) => StatusCode::OK.into_response(),
+                Err(err) => {
+                    eprintln!("Webhook spill: failed to write {}: {}", path, err);
+                    self.status.lock().unwrap().failed(format!("failed to write {}: {}", path, err));
+                    StatusCode::SERVICE_UNAVAILABLE.into_response()
+                }
+            },
+        }
+    }
+
+    async fn spill(&self, path: &str, update: &Value) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(update)?;
+        line.push(b'\n');
+
+        let _guard = self.spill_lock.lock().await;
+        let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+        file.write_all(&line).await?;
+        file.flush().await
+    }
 }
 
 
@@ -112,6 +424,13 @@ impl Printable for WebhookUpdate {
             Some(reg)  => format!("REGISTRATED ON {}", &reg.token_regex.replace_all(&reg.set_webhook_url, "#####")),
             None => "".to_string()
         };
-        format!("webhook: 0.0.0.0{} {}", self.path, reg_text)
+        let filter_text = match &self.source_filter {
+            Some(filter) => format!(
+                " FROM [{}]",
+                filter.allowed().iter().map(|net| net.to_string()).collect::<Vec<_>>().join(", "),
+            ),
+            None => "".to_string(),
+        };
+        format!("webhook: 0.0.0.0{}{} {}", self.path, filter_text, reg_text)
     }
 }
\ No newline at end of file
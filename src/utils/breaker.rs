@@ -0,0 +1,184 @@
+use serde_json::{json, Value};
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Closed,
+    Open { until: Instant },
+    HalfOpen { probes: usize, succeeded: usize },
+}
+
+impl State {
+    fn name(&self) -> &'static str {
+        match self {
+            State::Closed => "closed",
+            State::Open { .. } => "open",
+            State::HalfOpen { .. } => "half-open",
+        }
+    }
+}
+
+struct Inner {
+    state: State,
+    /// Outcomes of the most recent calls while closed, `true` for a failure.
+    window: VecDeque<bool>,
+    opened: u64,
+    rejected: u64,
+}
+
+/// Closed / open / half-open breaker fed with call outcomes. Failures are
+/// errors and calls slower than `slow_call`; once they reach `error_rate`
+/// percent of the last `window` calls the breaker opens and rejects calls for
+/// `open_for`, then lets `half_open_probes` calls through to decide whether to
+/// close again.
+pub struct CircuitBreaker {
+    inner: Mutex<Inner>,
+    window: usize,
+    min_requests: usize,
+    error_rate: f64,
+    slow_call: Option<Duration>,
+    open_for: Duration,
+    half_open_probes: usize,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                state: State::Closed,
+                window: VecDeque::new(),
+                opened: 0,
+                rejected: 0,
+            }),
+            window: 20,
+            min_requests: 10,
+            error_rate: 50.0,
+            slow_call: None,
+            open_for: Duration::from_secs(30),
+            half_open_probes: 1,
+        }
+    }
+
+    /// Opens once `error_rate` percent of the last `window` calls failed,
+    /// counting only after `min_requests` calls were seen.
+    pub fn set_error_rate(&mut self, error_rate: f64, window: usize, min_requests: usize) {
+        self.error_rate = error_rate.clamp(0.0, 100.0);
+        self.window = window.max(1);
+        self.min_requests = min_requests.clamp(1, self.window);
+    }
+
+    /// Calls that take longer than `slow_call` count as failures even if they succeeded.
+    pub fn set_slow_call(&mut self, slow_call: Duration) {
+        self.slow_call = Some(slow_call);
+    }
+
+    pub fn set_open_for(&mut self, open_for: Duration) {
+        self.open_for = open_for;
+    }
+
+    pub fn set_half_open_probes(&mut self, probes: usize) {
+        self.half_open_probes = probes.max(1);
+    }
+
+    /// Asks for permission to make a call. Every granted call must be reported with `record`.
+    pub fn acquire(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let granted = match inner.state {
+            State::Closed => true,
+            State::Open { until } if Instant::now() >= until => {
+                inner.state = State::HalfOpen { probes: 1, succeeded: 0 };
+                true
+            }
+            State::Open { .. } => false,
+            State::HalfOpen { probes, succeeded } if probes < self.half_open_probes => {
+                inner.state = State::HalfOpen { probes: probes + 1, succeeded };
+                true
+            }
+            State::HalfOpen { .. } => false,
+        };
+        if !granted {
+            inner.rejected += 1;
+        }
+        granted
+    }
+
+    pub fn record(&self, ok: bool, elapsed: Duration) {
+        let failed = !ok || self.slow_call.is_some_and(|slow| elapsed > slow);
+        let mut inner = self.inner.lock().unwrap();
+
+        match inner.state {
+            State::Closed => {
+                inner.window.push_back(failed);
+                while inner.window.len() > self.window {
+                    inner.window.pop_front();
+                }
+
+                let calls = inner.window.len();
+                let failures = inner.window.iter().filter(|f| **f).count();
+                if calls >= self.min_requests && failures > 0 && failures as f64 * 100.0 >= self.error_rate * calls as f64 {
+                    self.open(&mut inner);
+                }
+            }
+            State::HalfOpen { .. } if failed => self.open(&mut inner),
+            State::HalfOpen { probes, succeeded } => {
+                if succeeded + 1 >= self.half_open_probes {
+                    inner.state = State::Closed;
+                    inner.window.clear();
+                } else {
+                    inner.state = State::HalfOpen { probes, succeeded: succeeded + 1 };
+                }
+            }
+            // a call granted before the breaker opened, its outcome no longer matters
+            State::Open { .. } => {}
+        }
+    }
+
+    fn open(&self, inner: &mut Inner) {
+        inner.state = State::Open { until: Instant::now() + self.open_for };
+        inner.window.clear();
+        inner.opened += 1;
+    }
+
+    /// Whether a call made now would be let through.
+    pub fn is_available(&self) -> bool {
+        match self.inner.lock().unwrap().state {
+            State::Closed => true,
+            State::Open { until } => Instant::now() >= until,
+            State::HalfOpen { probes, .. } => probes < self.half_open_probes,
+        }
+    }
+
+    pub fn state_name(&self) -> &'static str {
+        self.inner.lock().unwrap().state.name()
+    }
+
+    pub fn json_struct(&self) -> Value {
+        let inner = self.inner.lock().unwrap();
+        let failures = inner.window.iter().filter(|f| **f).count();
+        json!({
+            "state": inner.state.name(),
+            "recent_calls": inner.window.len(),
+            "recent_failures": failures,
+            "opened": inner.opened,
+            "rejected": inner.rejected,
+            "options": {
+                "window": self.window,
+                "min_requests": self.min_requests,
+                "error_rate": self.error_rate,
+                "slow_call_ms": self.slow_call.map(|d| d.as_millis() as u64),
+                "open_for": self.open_for.as_secs(),
+                "half_open_probes": self.half_open_probes
+            }
+        })
+    }
+}
@@ -4,3 +4,6 @@
 
 
 pub const TELEGRAM_TOKEN_REGEX: &str = r"(\d{8,15}):([a-zA-Z0-9_-]{30,50})";
+
+/// Subnets Telegram sends webhook requests from, see https://core.telegram.org/bots/webhooks
+pub const TELEGRAM_SUBNETS: [&str; 2] = ["149.154.160.0/20", "91.108.4.0/22"];
@@ -1 +1,7 @@
-pub mod defaults;
\ No newline at end of file
+pub mod defaults;
+pub mod update;
+pub mod time;
+pub mod breaker;
+pub mod tls;
+pub mod proxy_protocol;
+pub mod source_ip;
@@ -0,0 +1,88 @@
+use crate::utils::defaults::TELEGRAM_SUBNETS;
+
+use axum::http::HeaderMap;
+use ipnet::IpNet;
+
+use std::net::IpAddr;
+
+
+/// Parses CIDR blocks and single addresses. `telegram` expands to the
+/// subnets Telegram delivers webhooks from.
+pub fn parse_networks(entries: &[String]) -> Result<Vec<IpNet>, String> {
+    let mut networks = Vec::new();
+    for entry in entries {
+        let entry = entry.trim();
+        if entry.eq_ignore_ascii_case("telegram") {
+            networks.extend(TELEGRAM_SUBNETS.iter().map(|net| net.parse::<IpNet>().unwrap()));
+        } else if let Ok(net) = entry.parse::<IpNet>() {
+            networks.push(net);
+        } else {
+            let ip: IpAddr = entry.parse().map_err(|_| format!("invalid network {}", entry))?;
+            networks.push(IpNet::from(ip));
+        }
+    }
+    Ok(networks)
+}
+
+fn contains(networks: &[IpNet], ip: IpAddr) -> bool {
+    networks.iter().any(|net| net.contains(&ip))
+}
+
+/// Decides which clients may deliver updates. The client is the peer of the
+/// connection, unless the peer is a trusted proxy: then `X-Forwarded-For` is
+/// read from the right, skipping trusted proxies. Connections over a Unix
+/// socket have no peer address and are treated as coming from a trusted proxy.
+#[derive(Debug, Clone, Default)]
+pub struct SourceFilter {
+    allowed: Vec<IpNet>,
+    trusted_proxies: Vec<IpNet>,
+}
+
+impl SourceFilter {
+    pub fn new(allowed: Vec<IpNet>) -> Self {
+        Self { allowed, trusted_proxies: Vec::new() }
+    }
+
+    pub fn set_trusted_proxies(&mut self, trusted_proxies: Vec<IpNet>) {
+        self.trusted_proxies = trusted_proxies;
+    }
+
+    pub fn allowed(&self) -> &[IpNet] {
+        &self.allowed
+    }
+
+    pub fn trusted_proxies(&self) -> &[IpNet] {
+        &self.trusted_proxies
+    }
+
+    /// Address of the client behind the trusted proxies, `None` if it can't be told.
+    pub fn client_ip(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        // IPv4 clients of a dual-stack listener show up as ::ffff:a.b.c.d
+        let peer = peer.map(|ip| ip.to_canonical());
+        if peer.is_some_and(|ip| !contains(&self.trusted_proxies, ip)) {
+            return peer;
+        }
+
+        let mut forwarded = Vec::new();
+        for value in headers.get_all("x-forwarded-for") {
+            let value = value.to_str().ok()?;
+            for hop in value.split(',') {
+                forwarded.push(hop.trim().parse::<IpAddr>().ok()?.to_canonical());
+            }
+        }
+        forwarded.iter()
+            .rev()
+            .find(|ip| !contains(&self.trusted_proxies, **ip))
+            .or(forwarded.first())
+            .copied()
+            .or(peer)
+    }
+
+    /// An empty allowlist lets every client through.
+    pub fn allows(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> bool {
+        if self.allowed.is_empty() {
+            return true;
+        }
+        self.client_ip(peer, headers).is_some_and(|ip| contains(&self.allowed, ip))
+    }
+}
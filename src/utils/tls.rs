@@ -0,0 +1,170 @@
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::crypto::CryptoProvider;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use tokio::signal::unix::{signal, Signal, SignalKind};
+
+
+#[derive(Debug, Clone)]
+struct CertPair {
+    cert: PathBuf,
+    key: PathBuf,
+}
+
+impl CertPair {
+    fn load(&self, provider: &CryptoProvider) -> Result<CertifiedKey, String> {
+        let certs = CertificateDer::pem_file_iter(&self.cert)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("failed to read certificate {}: {}", self.cert.display(), e))?;
+        if certs.is_empty() {
+            return Err(format!("no certificate in {}", self.cert.display()));
+        }
+        let key = PrivateKeyDer::from_pem_file(&self.key)
+            .map_err(|e| format!("failed to read key {}: {}", self.key.display(), e))?;
+
+        CertifiedKey::from_der(certs, key, provider)
+            .map_err(|e| format!("invalid certificate {}: {}", self.cert.display(), e))
+    }
+
+    fn modified(&self) -> [Option<SystemTime>; 2] {
+        let modified = |path: &PathBuf| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        [modified(&self.cert), modified(&self.key)]
+    }
+}
+
+/// Picks the certificate by the SNI name of the client, `*.example.com`
+/// entries match one label. Clients without a known name get the default.
+#[derive(Debug)]
+struct SniResolver {
+    names: HashMap<String, Arc<CertifiedKey>>,
+    default: Arc<CertifiedKey>,
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let Some(name) = client_hello.server_name().map(|n| n.to_ascii_lowercase()) else {
+            return Some(self.default.clone());
+        };
+        let wildcard = name.split_once('.').map(|(_, parent)| format!("*.{}", parent));
+        self.names.get(&name)
+            .or_else(|| wildcard.and_then(|w| self.names.get(&w)))
+            .or(Some(&self.default))
+            .cloned()
+    }
+}
+
+/// Certificates of a TLS listener: a default pair, optional pairs selected by
+/// SNI, and how they are reloaded. Files are read again on SIGHUP and, with
+/// `set_watch_every`, whenever one of them changes. A reload that fails keeps
+/// the certificates in use.
+#[derive(Debug, Clone)]
+pub struct TlsCerts {
+    default: CertPair,
+    sni: Vec<(Vec<String>, CertPair)>,
+    watch_every: Option<Duration>,
+}
+
+impl TlsCerts {
+    pub fn new(cert: String, key: String) -> Self {
+        Self {
+            default: CertPair { cert: cert.into(), key: key.into() },
+            sni: Vec::new(),
+            watch_every: None,
+        }
+    }
+
+    /// Serves this pair to clients asking for one of `domains`.
+    pub fn add_sni(&mut self, domains: Vec<String>, cert: String, key: String) {
+        let domains = domains.into_iter().map(|d| d.to_ascii_lowercase()).collect();
+        self.sni.push((domains, CertPair { cert: cert.into(), key: key.into() }));
+    }
+
+    pub fn set_watch_every(&mut self, watch_every: Duration) {
+        self.watch_every = Some(watch_every);
+    }
+
+    fn pairs(&self) -> impl Iterator<Item = &CertPair> {
+        std::iter::once(&self.default).chain(self.sni.iter().map(|(_, pair)| pair))
+    }
+
+    fn modified(&self) -> Vec<[Option<SystemTime>; 2]> {
+        self.pairs().map(|pair| pair.modified()).collect()
+    }
+
+    pub fn server_config(&self) -> Result<ServerConfig, String> {
+        let provider = CryptoProvider::get_default()
+            .cloned()
+            .unwrap_or_else(|| Arc::new(rustls::crypto::aws_lc_rs::default_provider()));
+
+        let mut names = HashMap::new();
+        for (domains, pair) in &self.sni {
+            let key = Arc::new(pair.load(&provider)?);
+            for domain in domains {
+                names.insert(domain.clone(), key.clone());
+            }
+        }
+        let resolver = SniResolver {
+            names,
+            default: Arc::new(self.default.load(&provider)?),
+        };
+
+        let mut config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(resolver));
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    }
+
+    /// Loads the certificates and keeps them up to date in the background.
+    pub fn rustls_config(&self) -> Result<RustlsConfig, String> {
+        let config = RustlsConfig::from_config(Arc::new(self.server_config()?));
+        // registered before returning, so a SIGHUP right after startup does not kill the process
+        let hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => Some(hangup),
+            Err(err) => {
+                eprintln!("TLS: SIGHUP reload is not available: {}", err);
+                None
+            }
+        };
+        tokio::spawn(self.clone().reload(config.clone(), hangup));
+        Ok(config)
+    }
+
+    async fn reload(self, config: RustlsConfig, mut hangup: Option<Signal>) {
+        let mut modified = self.modified();
+
+        loop {
+            let reason = tokio::select! {
+                Some(_) = async { hangup.as_mut()?.recv().await } => "SIGHUP",
+                _ = tokio::time::sleep(self.watch_every.unwrap_or(Duration::MAX)), if self.watch_every.is_some() => {
+                    let current = self.modified();
+                    if current == modified {
+                        continue;
+                    }
+                    "file change"
+                }
+                else => return,
+            };
+
+            modified = self.modified();
+            match self.server_config() {
+                Ok(server_config) => {
+                    config.reload_from_config(Arc::new(server_config));
+                    println!("TLS: reloaded certificates after {}", reason);
+                }
+                Err(err) => eprintln!("TLS: reload after {} failed, keeping the current certificates: {}", reason, err),
+            }
+        }
+    }
+}
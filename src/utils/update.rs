@@ -0,0 +1,75 @@
+use serde_json::Value;
+
+
+/// Update fields that carry the payload, in the order Telegram documents them.
+pub const UPDATE_TYPES: &[&str] = &[
+    "message",
+    "edited_message",
+    "channel_post",
+    "edited_channel_post",
+    "business_connection",
+    "business_message",
+    "edited_business_message",
+    "deleted_business_messages",
+    "message_reaction",
+    "message_reaction_count",
+    "inline_query",
+    "chosen_inline_result",
+    "callback_query",
+    "shipping_query",
+    "pre_checkout_query",
+    "purchased_paid_media",
+    "poll",
+    "poll_answer",
+    "my_chat_member",
+    "chat_member",
+    "chat_join_request",
+    "chat_boost",
+    "removed_chat_boost",
+];
+
+/// Returns the update type (`"message"`, `"callback_query"`, ...) and its payload.
+pub fn payload(update: &Value) -> Option<(&'static str, &Value)> {
+    UPDATE_TYPES.iter().find_map(|kind| update.get(*kind).map(|p| (*kind, p)))
+}
+
+pub fn update_type(update: &Value) -> Option<&'static str> {
+    payload(update).map(|(kind, _)| kind)
+}
+
+/// The chat an update belongs to. Callback queries use the chat of the message they are attached to.
+pub fn chat(update: &Value) -> Option<&Value> {
+    let (_, payload) = payload(update)?;
+    payload.get("chat")
+        .or_else(|| payload.get("message").and_then(|m| m.get("chat")))
+}
+
+pub fn chat_id(update: &Value) -> Option<i64> {
+    chat(update)?.get("id")?.as_i64()
+}
+
+pub fn chat_type(update: &Value) -> Option<&str> {
+    chat(update)?.get("type")?.as_str()
+}
+
+/// The user who caused the update.
+pub fn user_id(update: &Value) -> Option<i64> {
+    let (_, payload) = payload(update)?;
+    payload.get("from")
+        .or_else(|| payload.get("user"))
+        .or_else(|| payload.get("voter_chat"))
+        .and_then(|u| u.get("id"))
+        .and_then(|id| id.as_i64())
+}
+
+/// Unix time in seconds the message was sent, callback queries use the date of their message.
+pub fn date(update: &Value) -> Option<i64> {
+    let (_, payload) = payload(update)?;
+    payload.get("date")
+        .or_else(|| payload.get("message").and_then(|m| m.get("date")))
+        .and_then(|d| d.as_i64())
+}
+
+pub fn update_id(update: &Value) -> Option<i64> {
+    update.get("update_id")?.as_i64()
+}
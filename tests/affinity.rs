@@ -0,0 +1,109 @@
+mod common;
+
+use common::telegram::MockTelegram;
+use common::{collect_route, message_update, poll_route, push_update, spawn_tgin, update_ids, url};
+
+use serde_json::{json, Value};
+
+const TOKEN: &str = "123456789:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+
+
+fn callback(id: i64, chat: i64, message_id: i64) -> Value {
+    json!({
+        "update_id": id,
+        "callback_query": {
+            "id": id.to_string(),
+            "from": { "id": chat },
+            "data": "press",
+            "message": { "message_id": message_id, "chat": { "id": chat } }
+        }
+    })
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn callbacks_follow_the_replica_that_sent_the_message() {
+    let telegram = MockTelegram::start(TOKEN).await;
+
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        api: Some(ApiConfig(base_path: "/api")),
+        updates: [WebhookUpdate(path: "/affinity/in")],
+        route: RoundRobinLB(
+            routes: [
+                LongPollRoute(path: "/first/getUpdates"),
+                LongPollRoute(path: "/second/getUpdates"),
+            ],
+            affinity: Some(AffinityConfig(proxy_path: Some("/tg"), api_url: "{}")),
+        ),
+    )"#, telegram.base_url())).await;
+
+    // the second replica sends a keyboard through the proxy
+    let sent: Value = reqwest::Client::new()
+        .post(url(port, &format!("/tg/1/bot{}/sendMessage", TOKEN)))
+        .json(&json!({ "chat_id": 5, "text": "pick one" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(sent["ok"], true);
+    assert_eq!(sent["result"]["message_id"], 1);
+    assert_eq!(telegram.sent_messages().len(), 1);
+
+    // uploads larger than axum's default body limit go through
+    let document = vec![b'x'; 5 * 1024 * 1024];
+    let uploaded = reqwest::Client::new()
+        .post(url(port, &format!("/tg/1/bot{}/sendDocument", TOKEN)))
+        .header("content-type", "multipart/form-data; boundary=x")
+        .body(document)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(uploaded.status(), 200);
+    assert_eq!(telegram.uploads(), vec![5 * 1024 * 1024]);
+
+    for id in 1..=4 {
+        push_update(port, "/affinity/in", callback(id, 5, 1)).await;
+    }
+    assert_eq!(update_ids(&collect_route(port, "/second/getUpdates", 4).await), vec![1, 2, 3, 4]);
+    assert!(poll_route(port, "/first/getUpdates", 0).await.is_empty());
+
+    // callbacks on messages nobody pinned are balanced as usual
+    push_update(port, "/affinity/in", callback(5, 5, 99)).await;
+    push_update(port, "/affinity/in", callback(6, 5, 98)).await;
+    let first = poll_route(port, "/first/getUpdates", 0).await;
+    let second = poll_route(port, "/second/getUpdates", 0).await;
+    assert_eq!(first.len(), 1);
+    assert_eq!(second.len(), 1);
+
+    let routes: Value = reqwest::get(url(port, "/api/routes")).await.unwrap().json().await.unwrap();
+    assert_eq!(routes["affinity"]["hits"], 4);
+    assert_eq!(routes["affinity"]["misses"], 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn callbacks_follow_the_replica_that_got_the_message() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        updates: [WebhookUpdate(path: "/affinity/in")],
+        route: RoundRobinLB(
+            routes: [
+                LongPollRoute(path: "/first/getUpdates"),
+                LongPollRoute(path: "/second/getUpdates"),
+            ],
+            affinity: Some(AffinityConfig(ttl: 60)),
+        ),
+    )"#).await;
+
+    push_update(port, "/affinity/in", message_update(1, json!({ "message_id": 10, "chat": { "id": 7 }, "text": "a" }))).await;
+    push_update(port, "/affinity/in", message_update(2, json!({ "message_id": 11, "chat": { "id": 7 }, "text": "b" }))).await;
+    assert_eq!(update_ids(&poll_route(port, "/first/getUpdates", 0).await), vec![1]);
+    assert_eq!(update_ids(&poll_route(port, "/second/getUpdates", 0).await), vec![2]);
+
+    for id in 3..=5 {
+        push_update(port, "/affinity/in", callback(id, 7, 11)).await;
+    }
+    assert_eq!(update_ids(&collect_route(port, "/second/getUpdates", 3).await), vec![3, 4, 5]);
+    assert!(poll_route(port, "/first/getUpdates", 0).await.is_empty());
+}
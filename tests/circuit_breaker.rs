@@ -0,0 +1,81 @@
+mod common;
+
+use common::{collect_route, message_update, push_update, spawn_tgin, update_ids, url, WebhookSink};
+
+use serde_json::{json, Value};
+use std::time::Duration;
+
+
+async fn circuit_state(port: u16) -> Value {
+    let routes: Value = reqwest::get(url(port, "/api/routes")).await.unwrap().json().await.unwrap();
+    routes["routes"][0]["circuit_breaker"]["state"].clone()
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn open_circuit_fails_fast_and_closes_after_probe() {
+    let mut sink = WebhookSink::start().await;
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        api: Some(ApiConfig(base_path: "/api")),
+        updates: [WebhookUpdate(path: "/breaker/in")],
+        route: RoundRobinLB(routes: [
+            WebhookRoute(
+                url: "{}",
+                circuit_breaker: Some(CircuitBreakerConfig(min_requests: 2, window: 4, open_for: 1)),
+            ),
+            LongPollRoute(path: "/spare/getUpdates"),
+        ]),
+    )"#, sink.url())).await;
+
+    // the round robin alternates, two failed deliveries open the circuit
+    sink.set_failing(true);
+    for id in 1..=4 {
+        push_update(port, "/breaker/in", message_update(id, json!({}))).await;
+    }
+    assert_eq!(collect_route(port, "/spare/getUpdates", 2).await.len(), 2);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(circuit_state(port).await, "open");
+
+    // while open the balancer skips the webhook entirely
+    sink.set_failing(false);
+    for id in 5..=6 {
+        push_update(port, "/breaker/in", message_update(id, json!({}))).await;
+    }
+    assert_eq!(update_ids(&collect_route(port, "/spare/getUpdates", 2).await), vec![5, 6]);
+    sink.assert_idle().await;
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    push_update(port, "/breaker/in", message_update(7, json!({}))).await;
+    push_update(port, "/breaker/in", message_update(8, json!({}))).await;
+    let mut delivered = sink.collect(1).await;
+    delivered.extend(collect_route(port, "/spare/getUpdates", 1).await);
+    assert_eq!(update_ids(&delivered), vec![7, 8]);
+    assert_eq!(circuit_state(port).await, "closed");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn zero_error_rate_is_rejected() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        api: Some(ApiConfig(base_path: "/api")),
+        updates: [WebhookUpdate(path: "/zero-rate/in")],
+        route: RoundRobinLB(routes: [LongPollRoute(path: "/zero-rate/getUpdates")]),
+    )"#).await;
+
+    let response: Value = reqwest::Client::new()
+        .post(url(port, "/api/route"))
+        .json(&json!({
+            "type": "WebhookRoute",
+            "url": "http://127.0.0.1:9/hook",
+            "circuit_breaker": { "error_rate": 0.0 },
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(response["error_code"], 400, "{}", response);
+    assert!(response["description"].as_str().unwrap().contains("error_rate"), "{}", response);
+}
@@ -0,0 +1,177 @@
+#![allow(dead_code)]
+
+pub mod telegram;
+
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use serde_json::{json, Value};
+
+use std::net::{SocketAddr, TcpListener as StdTcpListener};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+use tgin::config::document::ConfigDocument;
+use tgin::config::setup::build_tgin;
+
+
+pub fn free_port() -> u16 {
+    StdTcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// Starts `Tgin::run_async` for a RON config. `{port}` in the config is
+/// replaced by a free port, which is returned once the server accepts connections.
+pub async fn spawn_tgin(config: &str) -> u16 {
+    let port = free_port();
+    let config = config.replace("{port}", &port.to_string());
+    // `http` defaults are process wide, only one test per binary may set them
+    let tgin = build_tgin(
+        ron::from_str(&config).expect("Failed to parse RON config"),
+        ConfigDocument::parse("tgin.ron", &config),
+    ).expect("Invalid config");
+
+    tokio::spawn(tgin.run_async());
+
+    for _ in 0..100 {
+        if TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+            return port;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("tgin did not start listening on {}", port);
+}
+
+pub fn url(port: u16, path: &str) -> String {
+    format!("http://127.0.0.1:{}{}", port, path)
+}
+
+/// Sends `update` to the `WebhookUpdate` at `path` the way Telegram would.
+pub async fn push_update(port: u16, path: &str, update: Value) {
+    reqwest::Client::new()
+        .post(url(port, path))
+        .json(&update)
+        .send()
+        .await
+        .unwrap();
+}
+
+/// A `message` update, `fields` replace the ones of a text message in private chat 1.
+pub fn message_update(update_id: i64, fields: Value) -> Value {
+    let mut message = json!({ "message_id": update_id, "chat": { "id": 1, "type": "private" }, "text": "hi" });
+    if let (Some(message), Value::Object(fields)) = (message.as_object_mut(), fields) {
+        message.extend(fields);
+    }
+    json!({ "update_id": update_id, "message": message })
+}
+
+/// Calls a `LongPollRoute` the way a bot framework would.
+pub async fn poll_route(port: u16, path: &str, timeout: u64) -> Vec<Value> {
+    let timeout = timeout.to_string();
+    let response: Value = reqwest::Client::new()
+        .post(url(port, path))
+        .form(&[("timeout", timeout.as_str())])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(response["ok"], true, "{}", response);
+    response["result"].as_array().cloned().unwrap_or_default()
+}
+
+/// Polls a `LongPollRoute` until `count` updates were collected.
+pub async fn collect_route(port: u16, path: &str, count: usize) -> Vec<Value> {
+    let mut collected = Vec::new();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while collected.len() < count {
+        assert!(tokio::time::Instant::now() < deadline, "got {:?} from {}", collected, path);
+        collected.extend(poll_route(port, path, 1).await);
+    }
+    collected
+}
+
+pub fn update_ids(updates: &[Value]) -> Vec<i64> {
+    let mut ids: Vec<i64> = updates.iter().filter_map(|u| u["update_id"].as_i64()).collect();
+    ids.sort();
+    ids
+}
+
+
+/// HTTP endpoint standing in for a downstream bot behind a `WebhookRoute`.
+pub struct WebhookSink {
+    pub addr: SocketAddr,
+    rx: mpsc::UnboundedReceiver<Value>,
+    failing: Arc<AtomicBool>,
+}
+
+#[derive(Clone)]
+struct SinkState {
+    tx: mpsc::UnboundedSender<Value>,
+    jitter: Duration,
+    failing: Arc<AtomicBool>,
+}
+
+impl WebhookSink {
+    pub async fn start() -> Self {
+        Self::start_with_jitter(Duration::ZERO).await
+    }
+
+    /// Records and answers each update after a random delay of up to `jitter`.
+    pub async fn start_with_jitter(jitter: Duration) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let failing = Arc::new(AtomicBool::new(false));
+        let app = Router::new()
+            .route("/bot", post(receive))
+            .with_state(SinkState { tx, jitter, failing: failing.clone() });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        Self { addr, rx, failing }
+    }
+
+    /// While failing, every update is answered with 500 and not recorded.
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}/bot", self.addr)
+    }
+
+    pub async fn collect(&mut self, count: usize) -> Vec<Value> {
+        let mut collected = Vec::new();
+        while collected.len() < count {
+            match tokio::time::timeout(Duration::from_secs(5), self.rx.recv()).await {
+                Ok(Some(update)) => collected.push(update),
+                _ => panic!("webhook sink got {:?}, expected {}", collected, count),
+            }
+        }
+        collected
+    }
+
+    pub async fn assert_idle(&mut self) {
+        if let Ok(Some(update)) = tokio::time::timeout(Duration::from_millis(300), self.rx.recv()).await {
+            panic!("webhook sink got unexpected {}", update);
+        }
+    }
+}
+
+async fn receive(State(state): State<SinkState>, Json(update): Json<Value>) -> StatusCode {
+    if state.failing.load(Ordering::SeqCst) {
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+    if !state.jitter.is_zero() {
+        let delay = rand::random::<u64>() % state.jitter.as_millis() as u64;
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+    }
+    let _ = state.tx.send(update);
+    StatusCode::OK
+}
@@ -0,0 +1,257 @@
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{any, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::net::TcpListener;
+use tokio::sync::Notify;
+
+
+/// A failure the mock returns instead of answering the next Bot API call.
+#[derive(Clone, Debug)]
+pub enum Fault {
+    /// Bot API style error: `{"ok": false, "error_code": .., "description": ..}`.
+    Error { code: u16, description: String, retry_after: Option<u64> },
+    /// A 200 response whose body is not JSON.
+    Garbage,
+    /// Answer normally, but only after the given delay.
+    Delay(Duration),
+}
+
+#[derive(Default)]
+struct MockState {
+    token: String,
+    updates: Mutex<Vec<Value>>,
+    faults: Mutex<VecDeque<Fault>>,
+    get_updates_calls: Mutex<Vec<GetUpdatesQuery>>,
+    sent_messages: Mutex<Vec<Value>>,
+    uploads: Mutex<Vec<usize>>,
+    webhook: Mutex<Option<Value>>,
+    notify: Notify,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct GetUpdatesQuery {
+    pub offset: Option<i64>,
+    pub limit: Option<usize>,
+    pub timeout: Option<u64>,
+    pub allowed_updates: Option<String>,
+}
+
+/// In-process stand-in for `api.telegram.org` serving one bot token.
+pub struct MockTelegram {
+    pub addr: SocketAddr,
+    state: Arc<MockState>,
+}
+
+impl MockTelegram {
+    pub async fn start(token: &str) -> Self {
+        let state = Arc::new(MockState {
+            token: token.to_string(),
+            ..Default::default()
+        });
+
+        let app = Router::new()
+            .route("/:bot/getUpdates", any(get_updates))
+            .route("/:bot/setWebhook", post(set_webhook))
+            .route("/:bot/deleteWebhook", any(delete_webhook))
+            .route("/:bot/sendMessage", post(send_message))
+            .route("/:bot/sendDocument", post(send_document).layer(DefaultBodyLimit::disable()))
+            .with_state(state.clone());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        Self { addr, state }
+    }
+
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn method_url(&self, method: &str) -> String {
+        format!("{}/bot{}/{}", self.base_url(), self.state.token, method)
+    }
+
+    /// Queues an update with the next `update_id` and returns it.
+    pub fn push_update(&self, mut update: Value) -> Value {
+        let mut updates = self.state.updates.lock().unwrap();
+        let update_id = updates.len() as i64 + 1;
+        update["update_id"] = json!(update_id);
+        updates.push(update.clone());
+        drop(updates);
+
+        self.state.notify.notify_waiters();
+        update
+    }
+
+    pub fn push_message(&self, chat_id: i64, text: &str) -> Value {
+        self.push_update(json!({
+            "message": {
+                "message_id": 1,
+                "date": 0,
+                "chat": { "id": chat_id, "type": "private" },
+                "from": { "id": chat_id, "is_bot": false, "first_name": "test" },
+                "text": text
+            }
+        }))
+    }
+
+    pub fn inject_fault(&self, fault: Fault) {
+        self.state.faults.lock().unwrap().push_back(fault);
+    }
+
+    pub fn get_updates_calls(&self) -> Vec<GetUpdatesQuery> {
+        self.state.get_updates_calls.lock().unwrap().clone()
+    }
+
+    pub fn sent_messages(&self) -> Vec<Value> {
+        self.state.sent_messages.lock().unwrap().clone()
+    }
+
+    /// Body sizes of the `sendDocument` calls.
+    pub fn uploads(&self) -> Vec<usize> {
+        self.state.uploads.lock().unwrap().clone()
+    }
+
+    pub fn webhook(&self) -> Option<Value> {
+        self.state.webhook.lock().unwrap().clone()
+    }
+}
+
+
+fn error_response(code: u16, description: &str, retry_after: Option<u64>) -> Response {
+    let mut body = json!({
+        "ok": false,
+        "error_code": code,
+        "description": description
+    });
+    if let Some(retry_after) = retry_after {
+        body["parameters"] = json!({ "retry_after": retry_after });
+    }
+    (StatusCode::from_u16(code).unwrap(), Json(body)).into_response()
+}
+
+async fn check_call(state: &MockState, bot: &str) -> Option<Response> {
+    if bot.strip_prefix("bot") != Some(state.token.as_str()) {
+        return Some(error_response(401, "Unauthorized", None));
+    }
+
+    let fault = state.faults.lock().unwrap().pop_front();
+    match fault {
+        Some(Fault::Error { code, description, retry_after }) => {
+            Some(error_response(code, &description, retry_after))
+        }
+        Some(Fault::Garbage) => Some((StatusCode::OK, "<html>bad gateway</html>").into_response()),
+        Some(Fault::Delay(delay)) => {
+            tokio::time::sleep(delay).await;
+            None
+        }
+        None => None,
+    }
+}
+
+async fn get_updates(
+    State(state): State<Arc<MockState>>,
+    Path(bot): Path<String>,
+    Query(query): Query<GetUpdatesQuery>,
+) -> Response {
+    if let Some(response) = check_call(&state, &bot).await {
+        return response;
+    }
+    state.get_updates_calls.lock().unwrap().push(query.clone());
+
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(100);
+    // Keep long polls short so a test never waits on the mock.
+    let timeout = Duration::from_secs(query.timeout.unwrap_or(0).min(1));
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    loop {
+        let notified = state.notify.notified();
+        let batch: Vec<Value> = state.updates.lock().unwrap()
+            .iter()
+            .filter(|u| u["update_id"].as_i64().unwrap_or(0) >= offset)
+            .take(limit)
+            .cloned()
+            .collect();
+
+        if !batch.is_empty() || tokio::time::Instant::now() >= deadline {
+            return Json(json!({ "ok": true, "result": batch })).into_response();
+        }
+        let _ = tokio::time::timeout_at(deadline, notified).await;
+    }
+}
+
+async fn set_webhook(
+    State(state): State<Arc<MockState>>,
+    Path(bot): Path<String>,
+    Json(params): Json<Value>,
+) -> Response {
+    if let Some(response) = check_call(&state, &bot).await {
+        return response;
+    }
+    *state.webhook.lock().unwrap() = Some(params);
+    Json(json!({ "ok": true, "result": true, "description": "Webhook was set" })).into_response()
+}
+
+async fn delete_webhook(State(state): State<Arc<MockState>>, Path(bot): Path<String>) -> Response {
+    if let Some(response) = check_call(&state, &bot).await {
+        return response;
+    }
+    *state.webhook.lock().unwrap() = None;
+    Json(json!({ "ok": true, "result": true, "description": "Webhook was deleted" })).into_response()
+}
+
+async fn send_message(
+    State(state): State<Arc<MockState>>,
+    Path(bot): Path<String>,
+    Json(params): Json<Value>,
+) -> Response {
+    if let Some(response) = check_call(&state, &bot).await {
+        return response;
+    }
+    let mut sent = state.sent_messages.lock().unwrap();
+    let message = json!({
+        "message_id": sent.len() + 1,
+        "date": 0,
+        "chat": { "id": params["chat_id"] },
+        "text": params["text"]
+    });
+    sent.push(params);
+    Json(json!({ "ok": true, "result": message })).into_response()
+}
+
+/// Takes the upload as raw bytes, the size is all tests look at.
+async fn send_document(
+    State(state): State<Arc<MockState>>,
+    Path(bot): Path<String>,
+    body: Bytes,
+) -> Response {
+    if let Some(response) = check_call(&state, &bot).await {
+        return response;
+    }
+    let mut uploads = state.uploads.lock().unwrap();
+    uploads.push(body.len());
+    let message = json!({
+        "message_id": 1000 + uploads.len(),
+        "date": 0,
+        "chat": { "id": 5 },
+        "document": { "file_size": body.len() }
+    });
+    Json(json!({ "ok": true, "result": message })).into_response()
+}
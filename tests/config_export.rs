@@ -0,0 +1,208 @@
+mod common;
+
+use common::{free_port, url};
+
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+
+const SECRET: &str = "hook-secret-4f1a9c";
+/// Short enough to turn up inside other values of the file.
+const STALE_AFTER: &str = "80";
+
+
+fn config_file(name: &str, port: u16, persist: bool) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tgin-config-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("tgin.ron");
+    std::fs::write(&path, format!(r#"(
+        server_port: Some({}),
+        api: Some(ApiConfig(base_path: "/api", persist: {})),
+        updates: [
+            WebhookUpdate(path: "/export/in"),
+            WebhookUpdate(path: "/allowed/in", allowed_ips: ["${{TGIN_ALLOWED_IP}}"]),
+        ],
+        route: FilterRoute(
+            name: Some("gate"),
+            route: RoundRobinLB(routes: [
+                WebhookRoute(url: "http://127.0.0.1:1/hook", secret_token: Some("${{TGIN_HOOK_SECRET}}")),
+                WebhookRoute(url: "http://127.0.0.1:80/hook", timeout_ms: Some(8080)),
+                LongPollRoute(path: "/stale/getUpdates", stale_after: Some(${{TGIN_STALE_AFTER}}), ttl: Some(80)),
+            ]),
+        ),
+    )"#, port, persist)).unwrap();
+    path
+}
+
+/// The binary running on a config file, stopped when dropped.
+struct Instance(Child);
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+async fn start(path: &Path, port: u16) -> Instance {
+    let instance = Instance(Command::new(env!("CARGO_BIN_EXE_tgin"))
+        .args(["-f", path.to_str().unwrap()])
+        .env("TGIN_HOOK_SECRET", SECRET)
+        .env("TGIN_STALE_AFTER", STALE_AFTER)
+        .env("TGIN_ALLOWED_IP", "127.0.0.1")
+        .spawn()
+        .unwrap());
+    for _ in 0..250 {
+        if TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+            return instance;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("tgin did not start listening on {}", port);
+}
+
+async fn export(port: u16) -> String {
+    let response = reqwest::get(url(port, "/api/config")).await.unwrap();
+    assert_eq!(response.status(), 200);
+    response.text().await.unwrap()
+}
+
+async fn add_route(port: u16, path: &str) {
+    let response = reqwest::Client::new()
+        .post(url(port, "/api/route"))
+        .json(&json!({ "type": "LongPollRoute", "path": path }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+async fn api_post(port: u16, path: &str, body: Value) -> Value {
+    let response = reqwest::Client::new().post(url(port, path)).json(&body).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    response.json().await.unwrap()
+}
+
+async fn updater_states(port: u16) -> Vec<(String, String)> {
+    let response: Value = reqwest::get(url(port, "/api/updates")).await.unwrap().json().await.unwrap();
+    response["result"].as_array().unwrap().iter()
+        .map(|updater| (updater["name"].as_str().unwrap().to_string(), updater["state"].as_str().unwrap().to_string()))
+        .collect()
+}
+
+async fn has_route(port: u16, path: &str) -> bool {
+    let routes: Value = reqwest::get(url(port, "/api/routes")).await.unwrap().json().await.unwrap();
+    routes.to_string().contains(path)
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn api_changes_are_persisted_with_placeholders() {
+    let port = free_port();
+    let path = config_file("persist", port, true);
+    let tgin = start(&path, port).await;
+
+    add_route(port, "/added/getUpdates").await;
+    let response = reqwest::Client::new()
+        .patch(url(port, "/api/filter"))
+        .json(&json!({ "name": "gate", "list": "deny", "add": { "users": [4242] } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    // secrets only come from the environment
+    let response = reqwest::Client::new()
+        .post(url(port, "/api/route"))
+        .json(&json!({ "type": "WebhookRoute", "url": "http://127.0.0.1:1/literal", "secret_token": SECRET }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    api_post(port, "/api/route", json!({ "type": "WebhookRoute", "url": "http://127.0.0.1:1/added", "secret_token": "${TGIN_HOOK_SECRET}" })).await;
+
+    let exported = export(port).await;
+    assert_eq!(exported.matches("${TGIN_HOOK_SECRET}").count(), 2, "{}", exported);
+    assert!(!exported.contains(SECRET), "{}", exported);
+    assert!(!exported.contains("/literal"), "{}", exported);
+    assert!(exported.contains("LongPollRoute("), "{}", exported);
+    assert!(exported.contains("/added/getUpdates"), "{}", exported);
+    assert!(exported.contains("4242"), "{}", exported);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), exported);
+    assert!(!path.with_extension("ron.tmp").exists());
+
+    // the rewritten file starts the same tree
+    drop(tgin);
+    let _tgin = start(&path, port).await;
+    assert!(has_route(port, "/added/getUpdates").await);
+    assert_eq!(export(port).await, exported);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn config_file_is_left_alone_without_persist() {
+    let port = free_port();
+    let path = config_file("export", port, false);
+    let original = std::fs::read_to_string(&path).unwrap();
+    let _tgin = start(&path, port).await;
+
+    add_route(port, "/exported/getUpdates").await;
+
+    let exported = export(port).await;
+    assert!(exported.contains("/exported/getUpdates"), "{}", exported);
+    assert!(exported.contains("${TGIN_HOOK_SECRET}"), "{}", exported);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), original);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn placeholders_are_restored_only_where_they_were() {
+    let port = free_port();
+    let path = config_file("whole", port, false);
+    let _tgin = start(&path, port).await;
+
+    let exported = export(port).await;
+    assert!(exported.contains("stale_after: Some(${TGIN_STALE_AFTER})"), "{}", exported);
+    assert!(exported.contains("ttl: Some(80)"), "{}", exported);
+    assert!(exported.contains("timeout_ms: Some(8080)"), "{}", exported);
+    assert!(exported.contains("\"http://127.0.0.1:80/hook\""), "{}", exported);
+    assert_eq!(exported.matches("${TGIN_STALE_AFTER}").count(), 1, "{}", exported);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn updater_changes_are_persisted() {
+    let port = free_port();
+    let path = config_file("updaters", port, true);
+    let tgin = start(&path, port).await;
+
+    let removed = api_post(port, "/api/update", json!({ "type": "WebhookUpdate", "path": "/removed/in" })).await;
+    let kept = api_post(port, "/api/update", json!({ "type": "WebhookUpdate", "path": "/kept/in" })).await;
+    let removed = removed["result"]["id"].as_u64().unwrap();
+    let kept = kept["result"]["id"].as_u64().unwrap();
+    api_post(port, &format!("/api/update/{}/pause", kept), json!({})).await;
+    for id in [removed, 0] {
+        let response = reqwest::Client::new().delete(url(port, &format!("/api/update/{}", id))).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+    }
+
+    let exported = export(port).await;
+    assert!(exported.contains("/kept/in"), "{}", exported);
+    assert!(!exported.contains("/removed/in"), "{}", exported);
+    assert!(!exported.contains("/export/in"), "{}", exported);
+    // moved up into the place of the removed provider along with its placeholder
+    assert!(exported.contains("\"${TGIN_ALLOWED_IP}\""), "{}", exported);
+    assert!(!exported.contains("\"127.0.0.1\""), "{}", exported);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), exported);
+
+    // the provider after the removed ones starts paused
+    drop(tgin);
+    let _tgin = start(&path, port).await;
+    let states = updater_states(port).await;
+    assert_eq!(states.len(), 2, "{:?}", states);
+    assert!(states[0].0.contains("/allowed/in"), "{:?}", states);
+    assert_ne!(states[0].1, "paused", "{:?}", states);
+    assert!(states[1].0.contains("/kept/in"), "{:?}", states);
+    assert_eq!(states[1].1, "paused", "{:?}", states);
+    assert_eq!(export(port).await, exported);
+}
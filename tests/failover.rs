@@ -0,0 +1,66 @@
+mod common;
+
+use common::{collect_route, message_update, poll_route, push_update, spawn_tgin, update_ids, WebhookSink};
+
+use serde_json::json;
+use std::time::Duration;
+
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failover_lb_falls_through_and_recovers_after_hold_off() {
+    let mut primary = WebhookSink::start().await;
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        updates: [WebhookUpdate(path: "/failover/in")],
+        route: FailoverLB(
+            recover_after: Some(1),
+            groups: [
+                [WebhookRoute(url: "{}")],
+                [LongPollRoute(path: "/standby/getUpdates")],
+            ],
+        ),
+    )"#, primary.url())).await;
+
+    push_update(port, "/failover/in", message_update(1, json!({}))).await;
+    assert_eq!(update_ids(&primary.collect(1).await), vec![1]);
+
+    primary.set_failing(true);
+    push_update(port, "/failover/in", message_update(2, json!({}))).await;
+    push_update(port, "/failover/in", message_update(3, json!({}))).await;
+    assert_eq!(update_ids(&collect_route(port, "/standby/getUpdates", 2).await), vec![2, 3]);
+
+    // primary is back, but has not been up for `recover_after` yet
+    primary.set_failing(false);
+    push_update(port, "/failover/in", message_update(4, json!({}))).await;
+    assert_eq!(update_ids(&collect_route(port, "/standby/getUpdates", 1).await), vec![4]);
+    primary.assert_idle().await;
+
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    push_update(port, "/failover/in", message_update(5, json!({}))).await;
+    assert_eq!(update_ids(&primary.collect(1).await), vec![5]);
+    assert!(poll_route(port, "/standby/getUpdates", 0).await.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failover_lb_skips_stale_long_poll_route() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        updates: [WebhookUpdate(path: "/failover/in")],
+        route: FailoverLB(
+            recover_after: Some(0),
+            groups: [
+                [LongPollRoute(path: "/main/getUpdates", stale_after: Some(1))],
+                [LongPollRoute(path: "/backup/getUpdates")],
+            ],
+        ),
+    )"#).await;
+
+    push_update(port, "/failover/in", message_update(1, json!({}))).await;
+    assert_eq!(update_ids(&collect_route(port, "/main/getUpdates", 1).await), vec![1]);
+
+    // nobody polls the main route for longer than `stale_after`
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    push_update(port, "/failover/in", message_update(2, json!({}))).await;
+    assert_eq!(update_ids(&collect_route(port, "/backup/getUpdates", 1).await), vec![2]);
+}
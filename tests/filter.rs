@@ -0,0 +1,99 @@
+mod common;
+
+use common::{collect_route, message_update, poll_route, push_update, spawn_tgin, update_ids, url};
+
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::time::Duration;
+
+
+fn temp_file(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tgin-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("filters.ron")
+}
+
+async fn push(port: u16, update_id: i64, user: i64, chat_type: &str) {
+    let update = message_update(update_id, json!({ "from": { "id": user }, "chat": { "id": -user, "type": chat_type } }));
+    push_update(port, "/filter/in", update).await;
+}
+
+async fn edit(port: u16, body: Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .patch(url(port, "/api/filter"))
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn filter_route_drops_denied_updates_and_takes_api_edits() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        api: Some(ApiConfig(base_path: "/api")),
+        updates: [WebhookUpdate(path: "/filter/in")],
+        route: FilterRoute(
+            name: Some("gate"),
+            allow: (chat_types: ["private", "group"]),
+            deny: (users: [666]),
+            route: LongPollRoute(path: "/bot/getUpdates"),
+        ),
+    )"#).await;
+
+    push(port, 1, 1, "private").await;
+    push(port, 2, 666, "private").await;
+    push(port, 3, 2, "channel").await;
+    push(port, 4, 3, "group").await;
+    assert_eq!(update_ids(&collect_route(port, "/bot/getUpdates", 2).await), vec![1, 4]);
+
+    let response = edit(port, json!({ "name": "gate", "list": "deny", "add": { "users": [1] } })).await;
+    assert_eq!(response.status(), 200);
+    push(port, 5, 1, "private").await;
+    assert!(poll_route(port, "/bot/getUpdates", 0).await.is_empty());
+
+    edit(port, json!({ "name": "gate", "list": "deny", "remove": { "users": [1, 666] } })).await;
+    push(port, 6, 1, "private").await;
+    push(port, 7, 666, "private").await;
+    assert_eq!(update_ids(&collect_route(port, "/bot/getUpdates", 2).await), vec![6, 7]);
+
+    let response = edit(port, json!({ "name": "missing", "list": "deny", "add": { "users": [1] } })).await;
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn filter_route_reloads_watched_file() {
+    let file = temp_file("filter");
+    std::fs::write(&file, "(deny: (users: [5]))").unwrap();
+
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        api: Some(ApiConfig(base_path: "/api")),
+        updates: [WebhookUpdate(path: "/filter/in")],
+        route: FilterRoute(
+            name: Some("watched"),
+            file: Some("{}"),
+            watch_every: 1,
+            route: LongPollRoute(path: "/bot/getUpdates"),
+        ),
+    )"#, file.display())).await;
+
+    push(port, 1, 5, "private").await;
+    push(port, 2, 6, "private").await;
+    assert_eq!(update_ids(&collect_route(port, "/bot/getUpdates", 1).await), vec![2]);
+
+    std::fs::write(&file, "(deny: (users: [6]))").unwrap();
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    push(port, 3, 5, "private").await;
+    push(port, 4, 6, "private").await;
+    assert_eq!(update_ids(&collect_route(port, "/bot/getUpdates", 1).await), vec![3]);
+    assert!(poll_route(port, "/bot/getUpdates", 0).await.is_empty());
+
+    // edits made through the API are written back to the file
+    edit(port, json!({ "name": "watched", "list": "deny", "add": { "users": [7] } })).await;
+    let content = std::fs::read_to_string(&file).unwrap();
+    assert!(content.contains('7') && content.contains('6'), "{}", content);
+
+    std::fs::remove_dir_all(file.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use common::{collect_route, message_update, poll_route, push_update, spawn_tgin, update_ids, url};
+
+use serde_json::{json, Value};
+
+
+fn message(update_id: i64, user: i64) -> Value {
+    message_update(update_id, json!({ "from": { "id": user }, "chat": { "id": user, "type": "private" }, "text": "spam" }))
+}
+
+fn callback(update_id: i64, user: i64) -> Value {
+    json!({
+        "update_id": update_id,
+        "callback_query": { "id": "cb", "from": { "id": user }, "data": "x" }
+    })
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn flood_control_quarantines_over_limit_updates() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        api: Some(ApiConfig(base_path: "/api")),
+        updates: [WebhookUpdate(path: "/flood/in")],
+        route: LongPollRoute(path: "/bot/getUpdates"),
+        flood_control: Some((
+            per_user: Some((rate: 0.001, burst: 2)),
+            per_type: {
+                "callback_query": (per_user: Some((rate: 0.001, burst: 1))),
+            },
+            allowlist: [42],
+            quarantine: Some(LongPollRoute(path: "/quarantine/getUpdates")),
+        )),
+    )"#).await;
+
+    for id in 1..=4 {
+        push_update(port, "/flood/in", message(id, 7)).await;
+    }
+    // callback queries have their own bucket, the spent message bucket does not apply
+    push_update(port, "/flood/in", callback(5, 7)).await;
+    push_update(port, "/flood/in", callback(6, 7)).await;
+    for id in 7..=9 {
+        push_update(port, "/flood/in", message(id, 42)).await;
+    }
+
+    assert_eq!(update_ids(&collect_route(port, "/bot/getUpdates", 6).await), vec![1, 2, 5, 7, 8, 9]);
+    assert_eq!(update_ids(&collect_route(port, "/quarantine/getUpdates", 3).await), vec![3, 4, 6]);
+    assert!(poll_route(port, "/bot/getUpdates", 0).await.is_empty());
+
+    let report: Value = reqwest::get(url(port, "/api/flood")).await.unwrap().json().await.unwrap();
+    assert_eq!(report["result"]["limited"], 3);
+    assert_eq!(report["result"]["offenders"], json!([
+        { "scope": "user", "id": 7, "dropped": 3, "last_drop": report["result"]["offenders"][0]["last_drop"] }
+    ]));
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use common::telegram::{Fault, MockTelegram};
+use common::{collect_route, poll_route, spawn_tgin, update_ids, url};
+
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+const TOKEN: &str = "123456789:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+
+
+async fn spawn_poller(telegram: &MockTelegram, options: &str) -> u16 {
+    spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        updates: [
+            LongPollUpdate(token: "{}", url: Some("{}"), default_timeout_sleep: 10, error_timeout_sleep: 10, {}),
+        ],
+        route: LongPollRoute(path: "/polled/getUpdates"),
+    )"#, TOKEN, telegram.method_url("getUpdates"), options)).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_updates_parameters_are_configurable() {
+    let telegram = MockTelegram::start(TOKEN).await;
+    let port = spawn_poller(&telegram, r#"timeout: Some(0), limit: Some(5), allowed_updates: Some(["message", "callback_query"])"#).await;
+
+    telegram.push_message(1, "hi");
+    assert_eq!(update_ids(&collect_route(port, "/polled/getUpdates", 1).await), vec![1]);
+
+    let call = telegram.get_updates_calls()[0].clone();
+    assert_eq!(call.timeout, Some(0));
+    assert_eq!(call.limit, Some(5));
+    assert_eq!(call.allowed_updates.as_deref(), Some(r#"["message","callback_query"]"#));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn conflict_deletes_the_webhook() {
+    let telegram = MockTelegram::start(TOKEN).await;
+    reqwest::Client::new()
+        .post(telegram.method_url("setWebhook"))
+        .json(&json!({ "url": "https://old.example.com/bot" }))
+        .send()
+        .await
+        .unwrap();
+    telegram.inject_fault(Fault::Error {
+        code: 409,
+        description: "Conflict: can't use getUpdates method while webhook is active".into(),
+        retry_after: None,
+    });
+
+    let port = spawn_poller(&telegram, "delete_webhook_on_conflict: true").await;
+    telegram.push_message(1, "hi");
+
+    assert_eq!(update_ids(&collect_route(port, "/polled/getUpdates", 1).await), vec![1]);
+    assert!(telegram.webhook().is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn too_many_requests_waits_for_retry_after() {
+    let telegram = MockTelegram::start(TOKEN).await;
+    telegram.inject_fault(Fault::Error { code: 429, description: "Too Many Requests: retry after 1".into(), retry_after: Some(1) });
+
+    let started = Instant::now();
+    let port = spawn_poller(&telegram, "timeout: Some(0)").await;
+    telegram.push_message(1, "hi");
+
+    assert_eq!(update_ids(&collect_route(port, "/polled/getUpdates", 1).await), vec![1]);
+    assert!(started.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unauthorized_stops_the_updater() {
+    let telegram = MockTelegram::start(TOKEN).await;
+    telegram.inject_fault(Fault::Error { code: 401, description: "Unauthorized".into(), retry_after: None });
+
+    let port = spawn_poller(&telegram, "timeout: Some(0)").await;
+    telegram.push_message(1, "hi");
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(poll_route(port, "/polled/getUpdates", 0).await.is_empty());
+    assert!(telegram.get_updates_calls().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn malformed_polls_are_rejected() {
+    let telegram = MockTelegram::start(TOKEN).await;
+    let port = spawn_poller(&telegram, "timeout: Some(0)").await;
+    telegram.push_message(1, "hi");
+
+    let client = reqwest::Client::new();
+    for body in ["timeout=soon".to_string(), format!("timeout=0&{}", "x".repeat(64 * 1024))] {
+        let response: Value = client
+            .post(url(port, "/polled/getUpdates"))
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(response["ok"], false, "{}", response);
+        assert_eq!(response["error_code"], 400, "{}", response);
+    }
+
+    assert_eq!(update_ids(&collect_route(port, "/polled/getUpdates", 1).await), vec![1]);
+}
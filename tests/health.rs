@@ -0,0 +1,100 @@
+mod common;
+
+use common::telegram::{Fault, MockTelegram};
+use common::{spawn_tgin, url};
+
+use serde_json::Value;
+use std::process::Command;
+use std::time::Duration;
+
+const TOKEN: &str = "123456789:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+
+
+async fn get(port: u16, path: &str) -> (u16, Value) {
+    let response = reqwest::get(url(port, path)).await.unwrap();
+    (response.status().as_u16(), response.json().await.unwrap_or(Value::Null))
+}
+
+async fn spawn_poller(telegram: &MockTelegram) -> u16 {
+    spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        updates: [
+            LongPollUpdate(token: "{}", url: Some("{}"), timeout: Some(0), default_timeout_sleep: 10, error_timeout_sleep: 10),
+            WebhookUpdate(path: "/probe/in"),
+        ],
+        route: LongPollRoute(path: "/probe/getUpdates"),
+    )"#, TOKEN, telegram.method_url("getUpdates"))).await
+}
+
+/// Runs `tgin health` against a server, returns whether it exited successfully.
+fn cli_health(port: u16, extra: &[&str]) -> bool {
+    Command::new(env!("CARGO_BIN_EXE_tgin"))
+        .args(["health", "--url", &url(port, "")])
+        .args(extra)
+        .output()
+        .unwrap()
+        .status
+        .success()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ready_once_updaters_poll() {
+    let telegram = MockTelegram::start(TOKEN).await;
+    let port = spawn_poller(&telegram).await;
+
+    let (status, live) = get(port, "/healthz").await;
+    assert_eq!(status, 200);
+    assert_eq!(live["status"], "ok");
+
+    let mut ready = Value::Null;
+    for _ in 0..50 {
+        let (status, body) = get(port, "/readyz").await;
+        if status == 200 {
+            ready = body;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(ready["status"], "ready", "{}", ready);
+    assert_eq!(ready["checks"]["listeners"], true);
+    assert_eq!(ready["checks"]["route"], true);
+    assert_eq!(ready["checks"]["updates"].as_array().unwrap().len(), 2);
+
+    let (cli_ok, cli_live) = tokio::task::spawn_blocking(move || (cli_health(port, &[]), cli_health(port, &["--live"]))).await.unwrap();
+    assert!(cli_ok);
+    assert!(cli_live);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejected_token_is_not_ready() {
+    let telegram = MockTelegram::start(TOKEN).await;
+    telegram.inject_fault(Fault::Error { code: 401, description: "Unauthorized".into(), retry_after: None });
+    let port = spawn_poller(&telegram).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let (status, ready) = get(port, "/readyz").await;
+    assert_eq!(status, 503);
+    assert_eq!(ready["status"], "not_ready");
+    let updates = ready["checks"]["updates"].as_array().unwrap();
+    assert_eq!(updates[0]["ready"], false);
+    assert_eq!(updates[1]["ready"], true);
+
+    // still alive
+    assert_eq!(get(port, "/healthz").await.0, 200);
+
+    let (cli_ok, cli_live) = tokio::task::spawn_blocking(move || (cli_health(port, &[]), cli_health(port, &["--live"]))).await.unwrap();
+    assert!(!cli_ok);
+    assert!(cli_live);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn probes_follow_listener_components() {
+    let port = spawn_tgin(r#"(
+        listeners: [ListenerConfig(address: "127.0.0.1:{port}", serve: [Ingress])],
+        updates: [WebhookUpdate(path: "/probe/in")],
+        route: LongPollRoute(path: "/probe/getUpdates"),
+    )"#).await;
+
+    assert_eq!(get(port, "/healthz").await.0, 404);
+    assert_eq!(get(port, "/readyz").await.0, 404);
+}
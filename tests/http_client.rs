@@ -0,0 +1,48 @@
+mod common;
+
+use common::telegram::MockTelegram;
+use common::{collect_route, spawn_tgin, update_ids, url, WebhookSink};
+
+use serde_json::json;
+
+const TOKEN: &str = "123456789:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+
+
+// The proxies here are plain servers: a forward proxy gets the absolute URL in
+// the request line, which they route by its path like any other request.
+
+#[tokio::test(flavor = "multi_thread")]
+async fn telegram_and_webhook_traffic_goes_through_the_proxy() {
+    let telegram = MockTelegram::start(TOKEN).await;
+    let mut sink = WebhookSink::start().await;
+
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        http: Some(HttpConfig(proxy: Some("{}"), connect_timeout_ms: Some(2000), user_agent: Some("tgin-test"))),
+        updates: [
+            LongPollUpdate(token: "{}", url: Some("http://telegram.invalid/bot{}/getUpdates"), default_timeout_sleep: 10),
+            WebhookUpdate(path: "/proxied/in"),
+        ],
+        route: AllLB(routes: [
+            LongPollRoute(path: "/proxied/getUpdates"),
+            WebhookRoute(
+                url: "http://downstream.invalid/bot",
+                http: Some(HttpConfig(proxy: Some("http://{}"))),
+            ),
+        ]),
+    )"#, telegram.base_url(), TOKEN, TOKEN, sink.addr)).await;
+
+    // getUpdates only reaches the mock through the global proxy
+    telegram.push_message(1, "hi");
+    assert_eq!(update_ids(&collect_route(port, "/proxied/getUpdates", 1).await), vec![1]);
+
+    // the route overrides the proxy and points at the sink
+    reqwest::Client::new()
+        .post(url(port, "/proxied/in"))
+        .json(&json!({ "update_id": 50, "message": { "text": "direct" } }))
+        .send()
+        .await
+        .unwrap();
+    let delivered = sink.collect(2).await;
+    assert_eq!(update_ids(&delivered), vec![1, 50]);
+}
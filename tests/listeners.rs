@@ -0,0 +1,89 @@
+mod common;
+
+use common::{collect_route, free_port, spawn_tgin, update_ids, url};
+
+use serde_json::{json, Value};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+
+async fn post_unix(socket: &std::path::Path, path: &str) -> String {
+    let mut stream = UnixStream::connect(socket).await.unwrap();
+    let request = format!("POST {} HTTP/1.1\r\nHost: tgin\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn listeners_serve_only_their_components() {
+    let internal = free_port();
+    let socket = std::env::temp_dir().join(format!("tgin-{}.sock", internal));
+
+    let ingress = spawn_tgin(&format!(r#"(
+        api: Some(ApiConfig(base_path: "/api")),
+        listeners: [
+            ListenerConfig(address: "127.0.0.1:{{port}}", serve: [Ingress]),
+            ListenerConfig(address: "[::1]:{}", serve: [Routes, Api]),
+            ListenerConfig(address: "unix:{}", serve: [Routes]),
+        ],
+        updates: [WebhookUpdate(path: "/public/in")],
+        route: LongPollRoute(path: "/internal/getUpdates"),
+    )"#, internal, socket.display())).await;
+    let internal_url = |path: &str| format!("http://[::1]:{}{}", internal, path);
+
+    let client = reqwest::Client::new();
+    for id in 1..=2 {
+        let status = client.post(url(ingress, "/public/in"))
+            .json(&json!({ "update_id": id, "message": { "text": "hi" } }))
+            .send()
+            .await
+            .unwrap()
+            .status();
+        assert!(status.is_success());
+    }
+
+    // consumers and the API are not reachable on the public listener
+    assert_eq!(client.get(url(ingress, "/internal/getUpdates")).send().await.unwrap().status(), 404);
+    assert_eq!(client.get(url(ingress, "/api/routes")).send().await.unwrap().status(), 404);
+    // and ingress is not on the internal one, unknown paths end in the dynamic registry there
+    let missing: Value = client.post(internal_url("/public/in")).json(&json!({})).send().await.unwrap().json().await.unwrap();
+    assert_eq!(missing["error_code"], 404);
+
+    let routes: Value = client.get(internal_url("/api/routes")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(routes["kind"], "LongPollRoute");
+
+    let polled: Value = client.post(internal_url("/internal/getUpdates"))
+        .form(&[("limit", "1")])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(update_ids(polled["result"].as_array().unwrap()), vec![1]);
+
+    let response = post_unix(&socket, "/internal/getUpdates").await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("\"update_id\":2"), "{}", response);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_port_still_serves_everything() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        updates: [WebhookUpdate(path: "/legacy/in")],
+        route: LongPollRoute(path: "/legacy/getUpdates"),
+    )"#).await;
+
+    reqwest::Client::new()
+        .post(url(port, "/legacy/in"))
+        .json(&json!({ "update_id": 1, "message": { "text": "hi" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(update_ids(&collect_route(port, "/legacy/getUpdates", 1).await), vec![1]);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::{message_update, poll_route, push_update, spawn_tgin, update_ids};
+
+use serde_json::{json, Value};
+use std::time::Duration;
+
+
+async fn push(port: u16, update_id: i64, media_group: Option<&str>) {
+    let mut update = message_update(update_id, json!({ "photo": [] }));
+    if let Some(group) = media_group {
+        update["message"]["media_group_id"] = json!(group);
+    }
+    push_update(port, "/album/in", update).await;
+}
+
+async fn drain(port: u16, path: &str) -> Vec<Value> {
+    let mut updates = Vec::new();
+    loop {
+        let batch = poll_route(port, path, 0).await;
+        if batch.is_empty() {
+            return updates;
+        }
+        updates.extend(batch);
+    }
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn album_parts_reach_one_replica() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        updates: [WebhookUpdate(path: "/album/in")],
+        route: MediaGroupRoute(
+            window_ms: Some(300),
+            route: RoundRobinLB(routes: [
+                LongPollRoute(path: "/a/getUpdates"),
+                LongPollRoute(path: "/b/getUpdates"),
+            ]),
+        ),
+    )"#).await;
+
+    for id in 1..=3 {
+        push(port, id, Some("album-1")).await;
+    }
+    push(port, 4, None).await;
+    for id in 5..=7 {
+        push(port, id, Some("album-2")).await;
+    }
+    tokio::time::sleep(Duration::from_millis(600)).await;
+
+    let a = update_ids(&drain(port, "/a/getUpdates").await);
+    let b = update_ids(&drain(port, "/b/getUpdates").await);
+    assert_eq!(a.len() + b.len(), 7, "a: {:?} b: {:?}", a, b);
+    for album in [[1, 2, 3], [5, 6, 7]] {
+        assert!(album.iter().all(|id| a.contains(id)) || album.iter().all(|id| b.contains(id)), "a: {:?} b: {:?}", a, b);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn album_can_be_delivered_as_one_envelope() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        updates: [WebhookUpdate(path: "/album/in")],
+        route: MediaGroupRoute(
+            window_ms: Some(300),
+            mode: Envelope,
+            route: LongPollRoute(path: "/bot/getUpdates"),
+        ),
+    )"#).await;
+
+    push(port, 2, Some("album")).await;
+    push(port, 1, Some("album")).await;
+    tokio::time::sleep(Duration::from_millis(600)).await;
+
+    let updates = drain(port, "/bot/getUpdates").await;
+    assert_eq!(updates.len(), 1);
+    assert_eq!(updates[0]["update_id"], 1);
+    assert_eq!(updates[0]["media_group_id"], "album");
+    assert_eq!(update_ids(updates[0]["updates"].as_array().unwrap()), vec![1, 2]);
+}
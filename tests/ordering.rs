@@ -0,0 +1,80 @@
+mod common;
+
+use common::{message_update, push_update, spawn_tgin, url, WebhookSink};
+
+use serde_json::{json, Value};
+use std::time::Duration;
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ordered_dispatch_keeps_per_chat_order() {
+    let mut sink = WebhookSink::start_with_jitter(Duration::from_millis(15)).await;
+
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        ordering: Some(OrderingConfig(key: Chat, lanes: 4)),
+        updates: [WebhookUpdate(path: "/ordered/in")],
+        route: WebhookRoute(url: "{}"),
+    )"#, sink.url())).await;
+
+    let mut update_id = 0;
+    for seq in 0..15 {
+        for chat in [10, 20, 30] {
+            update_id += 1;
+            let update = message_update(update_id, json!({ "chat": { "id": chat, "type": "private" }, "text": seq.to_string() }));
+            push_update(port, "/ordered/in", update).await;
+        }
+    }
+
+    let received = sink.collect(45).await;
+    for chat in [10, 20, 30] {
+        let sequence: Vec<i64> = received.iter()
+            .filter(|u| u["message"]["chat"]["id"] == chat)
+            .map(|u| u["message"]["text"].as_str().unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(sequence, (0..15).collect::<Vec<i64>>(), "chat {}", chat);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn full_key_queues_drop_and_count_updates() {
+    // accepts connections and never answers, the first update holds the only lane
+    let stalled = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        api: Some(ApiConfig(base_path: "/api")),
+        ordering: Some(OrderingConfig(key: Chat, lanes: 1, queue_limit: 1)),
+        updates: [WebhookUpdate(path: "/stalled/in")],
+        route: WebhookRoute(url: "http://{}/hook", timeout_ms: Some(5000)),
+    )"#, stalled.local_addr().unwrap())).await;
+
+    for update_id in 1..=4 {
+        push_update(port, "/stalled/in", message_update(update_id, json!({ "chat": { "id": 10, "type": "private" } }))).await;
+    }
+
+    let mut report = Value::Null;
+    for _ in 0..50 {
+        let response: Value = reqwest::get(url(port, "/api/ordering")).await.unwrap().json().await.unwrap();
+        report = response["result"].clone();
+        if report["dropped"] == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(report["dropped"], 2, "{}", report);
+    assert_eq!(report["busy_lanes"], 1, "{}", report);
+    assert_eq!(report["keys"], 1, "{}", report);
+    assert_eq!(report["queued"], 1, "{}", report);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ordering_report_needs_ordering() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        api: Some(ApiConfig(base_path: "/api")),
+        updates: [WebhookUpdate(path: "/unordered/in")],
+        route: LongPollRoute(path: "/unordered/getUpdates"),
+    )"#).await;
+
+    assert_eq!(reqwest::get(url(port, "/api/ordering")).await.unwrap().status(), 404);
+}
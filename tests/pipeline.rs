@@ -0,0 +1,241 @@
+mod common;
+
+use common::telegram::{Fault, MockTelegram};
+use common::{collect_route, poll_route, spawn_tgin, update_ids, url, WebhookSink};
+
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const TOKEN: &str = "123456789:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn longpoll_update_round_robin_to_longpoll_and_webhook() {
+    let telegram = MockTelegram::start(TOKEN).await;
+    let mut sink = WebhookSink::start().await;
+
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        updates: [
+            LongPollUpdate(token: "{}", url: Some("{}"), default_timeout_sleep: 10),
+        ],
+        route: RoundRobinLB(routes: [
+            LongPollRoute(path: "/bot1/getUpdates"),
+            WebhookRoute(url: "{}"),
+        ]),
+    )"#, TOKEN, telegram.method_url("getUpdates"), sink.url())).await;
+
+    for i in 0..4 {
+        telegram.push_message(100 + i, "hello");
+    }
+
+    let polled = collect_route(port, "/bot1/getUpdates", 2).await;
+    let pushed = sink.collect(2).await;
+
+    let mut all = polled.clone();
+    all.extend(pushed.clone());
+    assert_eq!(update_ids(&all), vec![1, 2, 3, 4]);
+    assert_eq!(polled.len(), 2);
+    assert_eq!(pushed.len(), 2);
+
+    // offsets are confirmed, nothing is delivered twice
+    assert!(poll_route(port, "/bot1/getUpdates", 0).await.is_empty());
+    sink.assert_idle().await;
+    assert!(telegram.get_updates_calls().iter().any(|q| q.offset == Some(5)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn webhook_update_all_lb_fans_out() {
+    let mut sink = WebhookSink::start().await;
+
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        updates: [WebhookUpdate(path: "/fanout/in")],
+        route: AllLB(routes: [
+            LongPollRoute(path: "/fanout-a/getUpdates"),
+            LongPollRoute(path: "/fanout-b/getUpdates"),
+            WebhookRoute(url: "{}"),
+        ]),
+    )"#, sink.url())).await;
+
+    let client = reqwest::Client::new();
+    for id in 1..=3 {
+        let response = client.post(url(port, "/fanout/in"))
+            .json(&json!({ "update_id": id }))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+    }
+
+    assert_eq!(update_ids(&collect_route(port, "/fanout-a/getUpdates", 3).await), vec![1, 2, 3]);
+    assert_eq!(update_ids(&collect_route(port, "/fanout-b/getUpdates", 3).await), vec![1, 2, 3]);
+    assert_eq!(update_ids(&sink.collect(3).await), vec![1, 2, 3]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dynamic_api_adds_routes() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        api: Some(ApiConfig(base_path: "/api")),
+        updates: [WebhookUpdate(path: "/dynamic/in")],
+        route: RoundRobinLB(routes: [
+            LongPollRoute(path: "/dynamic-static/getUpdates"),
+        ]),
+    )"#).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client.post(url(port, "/api/route"))
+        .json(&json!({ "type": "LongPollRoute", "path": "/dynamic-added/getUpdates" }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let response = client.post(url(port, "/api/route"))
+        .json(&json!({ "type": "NoSuchRoute" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    // options only checked when the route is built
+    std::env::set_var("TGIN_PIPELINE_HEADER", "x");
+    let response = client.post(url(port, "/api/route"))
+        .json(&json!({ "type": "WebhookRoute", "url": "http://127.0.0.1:1/bot", "headers": { "bad header": "${TGIN_PIPELINE_HEADER}" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    // the path is already served, dynamically or by the static tree in front
+    for path in ["/dynamic-added/getUpdates", "/dynamic-static/getUpdates", "/dynamic/in"] {
+        let response = client.post(url(port, "/api/route"))
+            .json(&json!({ "type": "LongPollRoute", "path": path }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+        let rejected: Value = response.json().await.unwrap();
+        assert!(rejected["description"].as_str().unwrap().contains("already taken"), "{}", rejected);
+    }
+
+    // answered once the route is in place
+    let routes: Value = client.get(url(port, "/api/routes")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(routes["routes"].as_array().unwrap().len(), 2);
+    assert_eq!(routes["kind"], "RoundRobinLB");
+    assert_eq!(routes["routes"][1]["kind"], "LongPollRoute");
+    assert_eq!(routes["routes"][1]["options"]["path"], "/dynamic-added/getUpdates");
+
+    for id in 1..=2 {
+        client.post(url(port, "/dynamic/in"))
+            .json(&json!({ "update_id": id }))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let mut all = collect_route(port, "/dynamic-static/getUpdates", 1).await;
+    all.extend(collect_route(port, "/dynamic-added/getUpdates", 1).await);
+    assert_eq!(update_ids(&all), vec![1, 2]);
+
+    let missing: Value = client.post(url(port, "/dynamic-missing/getUpdates"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(missing["error_code"], 404);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn longpoll_update_survives_telegram_faults() {
+    let telegram = MockTelegram::start(TOKEN).await;
+    telegram.inject_fault(Fault::Error { code: 502, description: "Bad Gateway".into(), retry_after: None });
+    telegram.inject_fault(Fault::Garbage);
+    telegram.inject_fault(Fault::Delay(Duration::from_millis(200)));
+
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        updates: [
+            LongPollUpdate(token: "{}", url: Some("{}"), default_timeout_sleep: 10, error_timeout_sleep: 10),
+        ],
+        route: LongPollRoute(path: "/faults/getUpdates"),
+    )"#, TOKEN, telegram.method_url("getUpdates"))).await;
+
+    telegram.push_message(1, "one");
+    telegram.push_message(2, "two");
+
+    let updates = collect_route(port, "/faults/getUpdates", 2).await;
+    assert_eq!(update_ids(&updates), vec![1, 2]);
+    let first = updates.iter().find(|u| u["update_id"] == 1).unwrap();
+    assert_eq!(first["message"]["text"], "one");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn webhook_update_registers_with_telegram() {
+    let telegram = MockTelegram::start(TOKEN).await;
+
+    spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        updates: [
+            WebhookUpdate(
+                path: "/registered/in",
+                registration: Some(RegistrationWebhookConfig(
+                    public_ip: "https://bots.example.com/",
+                    token: "{}",
+                    set_webhook_url: Some("{}"),
+                )),
+            ),
+        ],
+        route: LongPollRoute(path: "/registered/getUpdates"),
+    )"#, TOKEN, telegram.method_url("setWebhook"))).await;
+
+    for _ in 0..50 {
+        if telegram.webhook().is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(telegram.webhook().unwrap()["url"], "https://bots.example.com/registered/in");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn mirror_route_shadows_without_holding_primary() {
+    let mut shadow = WebhookSink::start_with_jitter(Duration::from_millis(2000)).await;
+
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        api: Some(ApiConfig(base_path: "/api")),
+        updates: [WebhookUpdate(path: "/mirror/in")],
+        route: MirrorRoute(
+            primary: LongPollRoute(path: "/mirror/getUpdates"),
+            shadows: [
+                WebhookRoute(url: "{}"),
+                WebhookRoute(url: "http://127.0.0.1:1/unreachable"),
+            ],
+        ),
+    )"#, shadow.url())).await;
+
+    let client = reqwest::Client::new();
+    for id in 1..=3 {
+        client.post(url(port, "/mirror/in"))
+            .json(&json!({ "update_id": id }))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let started = tokio::time::Instant::now();
+    assert_eq!(update_ids(&collect_route(port, "/mirror/getUpdates", 3).await), vec![1, 2, 3]);
+    assert!(started.elapsed() < Duration::from_millis(1000));
+
+    assert_eq!(update_ids(&shadow.collect(3).await), vec![1, 2, 3]);
+
+    let routes: Value = client.get(url(port, "/api/routes")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(routes["kind"], "MirrorRoute");
+    assert_eq!(routes["metrics"]["primary"], 3);
+    assert_eq!(routes["metrics"]["shadowed"], 6);
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use common::{collect_route, spawn_tgin, update_ids, url};
+
+use serde_json::json;
+use std::path::PathBuf;
+use std::time::Duration;
+
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tgin-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn recorded_files(dir: &PathBuf) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.file_name().to_string_lossy().into_owned()).collect())
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn file_sink_records_and_replay_update_feeds_them_back() {
+    let dir = temp_dir("record");
+
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        updates: [WebhookUpdate(path: "/record/in")],
+        route: AllLB(routes: [
+            FileSinkRoute(path: "{}", max_size: Some(50), gzip: true),
+            LongPollRoute(path: "/record/getUpdates"),
+        ]),
+    )"#, dir.display())).await;
+
+    let client = reqwest::Client::new();
+    for id in 1..=5 {
+        client.post(url(port, "/record/in"))
+            .json(&json!({ "update_id": id, "message": { "text": "recorded" } }))
+            .send()
+            .await
+            .unwrap();
+    }
+    collect_route(port, "/record/getUpdates", 5).await;
+
+    // every record is over 50 bytes, so all but the open file get rotated and compressed
+    let mut files = Vec::new();
+    for _ in 0..50 {
+        files = recorded_files(&dir);
+        if files.iter().filter(|f| f.ends_with(".jsonl.gz")).count() == 4 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(files.iter().filter(|f| f.ends_with(".jsonl.gz")).count(), 4, "{:?}", files);
+    assert_eq!(files.iter().filter(|f| f.ends_with(".jsonl")).count(), 1, "{:?}", files);
+
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        updates: [ReplayUpdate(path: "{}", speed: Max)],
+        route: LongPollRoute(path: "/replay/getUpdates"),
+    )"#, dir.display())).await;
+
+    let replayed = collect_route(port, "/replay/getUpdates", 5).await;
+    assert_eq!(update_ids(&replayed), vec![1, 2, 3, 4, 5]);
+    assert!(replayed.iter().all(|u| u["message"]["text"] == "recorded"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replay_update_keeps_scaled_gaps() {
+    let dir = temp_dir("scaled");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("updates-1-0000.jsonl"), concat!(
+        "{\"ts\":1000,\"update\":{\"update_id\":1}}\n",
+        "{\"ts\":1900,\"update\":{\"update_id\":2}}\n",
+    )).unwrap();
+
+    let started = tokio::time::Instant::now();
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        updates: [ReplayUpdate(path: "{}", speed: Scaled(3.0))],
+        route: LongPollRoute(path: "/scaled/getUpdates"),
+    )"#, dir.display())).await;
+
+    let replayed = collect_route(port, "/scaled/getUpdates", 2).await;
+    assert_eq!(update_ids(&replayed), vec![1, 2]);
+    assert!(started.elapsed() >= Duration::from_millis(300));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
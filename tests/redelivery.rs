@@ -0,0 +1,97 @@
+mod common;
+
+use common::{collect_route, poll_route, spawn_tgin, update_ids, url};
+
+use serde_json::{json, Value};
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unfetched_updates_move_to_a_polling_sibling() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        api: Some(ApiConfig(base_path: "/api")),
+        updates: [WebhookUpdate(path: "/redeliver/in")],
+        route: RoundRobinLB(routes: [
+            LongPollRoute(path: "/dead/getUpdates", redeliver_after: Some(1)),
+            LongPollRoute(path: "/live/getUpdates", redeliver_after: Some(1)),
+        ]),
+    )"#).await;
+
+    for id in 1..=4 {
+        reqwest::Client::new()
+            .post(url(port, "/redeliver/in"))
+            .json(&json!({ "update_id": id, "message": { "text": "hi" } }))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    // only the live replica polls, the dead one's share comes over after the timeout
+    assert_eq!(update_ids(&collect_route(port, "/live/getUpdates", 4).await), vec![1, 2, 3, 4]);
+    assert!(poll_route(port, "/dead/getUpdates", 0).await.is_empty());
+
+    let routes: Value = reqwest::get(url(port, "/api/routes")).await.unwrap().json().await.unwrap();
+    assert_eq!(routes["routes"][0]["redelivered"], 2);
+    assert_eq!(routes["routes"][1]["redelivered"], 0);
+}
+
+async fn redelivered(port: u16) -> Vec<u64> {
+    let routes: Value = reqwest::get(url(port, "/api/routes")).await.unwrap().json().await.unwrap();
+    routes["routes"].as_array().unwrap().iter().map(|r| r["redelivered"].as_u64().unwrap()).collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn updates_stay_put_without_a_healthy_sibling() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        api: Some(ApiConfig(base_path: "/api")),
+        updates: [WebhookUpdate(path: "/stale/in")],
+        route: RoundRobinLB(routes: [
+            LongPollRoute(path: "/stale-a/getUpdates", stale_after: Some(1), redeliver_after: Some(1)),
+            LongPollRoute(path: "/stale-b/getUpdates", stale_after: Some(1), redeliver_after: Some(1)),
+        ]),
+    )"#).await;
+
+    for id in 1..=2 {
+        reqwest::Client::new()
+            .post(url(port, "/stale/in"))
+            .json(&json!({ "update_id": id, "message": { "text": "hi" } }))
+            .send()
+            .await
+            .unwrap();
+    }
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    // polling would make a route healthy again, so the queues are looked at through the API
+    let routes: Value = reqwest::get(url(port, "/api/routes")).await.unwrap().json().await.unwrap();
+    assert_eq!(routes["routes"][0]["queued"], 1);
+    assert_eq!(routes["routes"][1]["queued"], 1);
+    assert_eq!(redelivered(port).await, vec![0, 0]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn redelivery_stops_after_the_cap() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        api: Some(ApiConfig(base_path: "/api")),
+        updates: [WebhookUpdate(path: "/capped/in")],
+        route: RoundRobinLB(routes: [
+            LongPollRoute(path: "/capped-a/getUpdates", redeliver_after: Some(1)),
+            LongPollRoute(path: "/capped-b/getUpdates", redeliver_after: Some(1)),
+        ]),
+    )"#).await;
+
+    reqwest::Client::new()
+        .post(url(port, "/capped/in"))
+        .json(&json!({ "update_id": 1, "message": { "text": "hi" } }))
+        .send()
+        .await
+        .unwrap();
+    // nobody polls and both look healthy, the update moves MAX_REDELIVERIES times and stays
+    tokio::time::sleep(std::time::Duration::from_secs(6)).await;
+
+    assert_eq!(redelivered(port).await.iter().sum::<u64>(), tgin::base::MAX_REDELIVERIES as u64);
+    let mut all = poll_route(port, "/capped-a/getUpdates", 0).await;
+    all.extend(poll_route(port, "/capped-b/getUpdates", 0).await);
+    assert_eq!(update_ids(&all), vec![1]);
+}
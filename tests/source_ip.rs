@@ -0,0 +1,130 @@
+mod common;
+
+use common::{collect_route, spawn_tgin, update_ids, url};
+
+use serde_json::json;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+
+async fn deliver(port: u16, path: &str, id: u64, forwarded_for: Option<&str>) -> u16 {
+    let mut request = reqwest::Client::new()
+        .post(url(port, path))
+        .json(&json!({ "update_id": id, "message": { "text": "hi" } }));
+    if let Some(forwarded_for) = forwarded_for {
+        request = request.header("X-Forwarded-For", forwarded_for);
+    }
+    request.send().await.unwrap().status().as_u16()
+}
+
+/// Sends `header` followed by a webhook request and returns the status line.
+async fn deliver_proxied(port: u16, header: &[u8], id: u64) -> String {
+    let body = json!({ "update_id": id, "message": { "text": "hi" } }).to_string();
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let mut request = header.to_vec();
+    request.extend_from_slice(format!(
+        "POST /proxied/in HTTP/1.1\r\nHost: tgin\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(), body,
+    ).as_bytes());
+    // a dropped connection may fail the write or end in a reset
+    let _ = stream.write_all(&request).await;
+
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response).await;
+    String::from_utf8_lossy(&response).lines().next().unwrap_or_default().to_string()
+}
+
+fn proxy_v2_ipv4(src: [u8; 4], dst: [u8; 4]) -> Vec<u8> {
+    let mut header = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+    header.extend_from_slice(&[0x21, 0x11, 0, 12]);
+    header.extend_from_slice(&src);
+    header.extend_from_slice(&dst);
+    header.extend_from_slice(&5555u16.to_be_bytes());
+    header.extend_from_slice(&443u16.to_be_bytes());
+    header
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn only_allowed_networks_deliver_updates() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        updates: [
+            WebhookUpdate(path: "/telegram/in", allowed_ips: ["telegram"]),
+            WebhookUpdate(path: "/local/in", allowed_ips: ["10.0.0.0/8", "127.0.0.1"]),
+        ],
+        route: LongPollRoute(path: "/allow/getUpdates"),
+    )"#).await;
+
+    assert_eq!(deliver(port, "/telegram/in", 1, None).await, 403);
+    // without trusted proxies the header is not looked at
+    assert_eq!(deliver(port, "/telegram/in", 2, Some("149.154.167.99")).await, 403);
+    assert_eq!(deliver(port, "/local/in", 3, None).await, 200);
+
+    assert_eq!(update_ids(&collect_route(port, "/allow/getUpdates", 1).await), vec![3]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn forwarded_for_is_read_behind_trusted_proxies() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        updates: [WebhookUpdate(
+            path: "/forwarded/in",
+            allowed_ips: ["telegram"],
+            trusted_proxies: ["127.0.0.0/8", "10.1.0.0/16"],
+        )],
+        route: LongPollRoute(path: "/forwarded/getUpdates"),
+    )"#).await;
+
+    assert_eq!(deliver(port, "/forwarded/in", 1, Some("149.154.167.99")).await, 200);
+    assert_eq!(deliver(port, "/forwarded/in", 2, Some("149.154.167.99, 10.1.2.3")).await, 200);
+    assert_eq!(deliver(port, "/forwarded/in", 3, Some("8.8.8.8")).await, 403);
+    // a client can prepend anything, only the hops added by trusted proxies count
+    assert_eq!(deliver(port, "/forwarded/in", 4, Some("149.154.167.99, 8.8.8.8")).await, 403);
+    assert_eq!(deliver(port, "/forwarded/in", 5, Some("not-an-ip")).await, 403);
+    assert_eq!(deliver(port, "/forwarded/in", 6, None).await, 403);
+
+    assert_eq!(update_ids(&collect_route(port, "/forwarded/getUpdates", 2).await), vec![1, 2]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_address_is_taken_from_proxy_protocol() {
+    let port = spawn_tgin(r#"(
+        listeners: [ListenerConfig(
+            address: "127.0.0.1:{port}",
+            serve: [Ingress],
+            proxy_protocol: true,
+            proxy_protocol_from: ["127.0.0.1"],
+        )],
+        updates: [WebhookUpdate(path: "/proxied/in", allowed_ips: ["telegram"])],
+        route: LongPollRoute(path: "/proxied/getUpdates"),
+    )"#).await;
+
+    let v1 = deliver_proxied(port, b"PROXY TCP4 149.154.167.99 10.0.0.1 5555 443\r\n", 1).await;
+    assert!(v1.starts_with("HTTP/1.1 200"), "{}", v1);
+    let v2 = deliver_proxied(port, &proxy_v2_ipv4([91, 108, 4, 10], [10, 0, 0, 1]), 2).await;
+    assert!(v2.starts_with("HTTP/1.1 200"), "{}", v2);
+
+    let outsider = deliver_proxied(port, b"PROXY TCP4 8.8.8.8 10.0.0.1 5555 443\r\n", 3).await;
+    assert!(outsider.starts_with("HTTP/1.1 403"), "{}", outsider);
+    // connections without the header are dropped
+    assert_eq!(deliver_proxied(port, b"", 4).await, "");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn proxy_protocol_is_only_read_from_listed_peers() {
+    let port = spawn_tgin(r#"(
+        listeners: [ListenerConfig(
+            address: "127.0.0.1:{port}",
+            serve: [Ingress],
+            proxy_protocol: true,
+            proxy_protocol_from: ["10.0.0.0/8"],
+        )],
+        updates: [WebhookUpdate(path: "/proxied/in", allowed_ips: ["telegram"])],
+        route: LongPollRoute(path: "/proxied/getUpdates"),
+    )"#).await;
+
+    // a well-formed header from a peer outside `proxy_protocol_from` is not believed
+    let spoofed = deliver_proxied(port, b"PROXY TCP4 149.154.167.99 10.0.0.1 5555 443\r\n", 1).await;
+    assert_eq!(spoofed, "");
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use common::{collect_route, message_update, poll_route, push_update, spawn_tgin, update_ids, url};
+
+use serde_json::{json, Value};
+use std::time::Duration;
+
+
+async fn push_from(port: u16, update_id: i64, user: i64) {
+    let update = message_update(update_id, json!({ "from": { "id": user }, "chat": { "id": user, "type": "private" } }));
+    push_update(port, "/split/in", update).await;
+}
+
+async fn set_shares(port: u16, name: &str, shares: Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .put(url(port, "/api/split"))
+        .json(&json!({ "name": name, "shares": shares }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn add_split(port: u16, name: &str, path: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(url(port, "/api/route"))
+        .json(&json!({
+            "type": "SplitLB",
+            "name": name,
+            "routes": [{ "share": 1.0, "route": { "LongPollRoute": { "path": path } } }],
+        }))
+        .send()
+        .await
+        .unwrap()
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn split_lb_shares_change_at_runtime_and_stay_sticky() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        api: Some(ApiConfig(base_path: "/api")),
+        updates: [WebhookUpdate(path: "/split/in")],
+        route: SplitLB(
+            name: Some("canary"),
+            assignment: User,
+            routes: [
+                (share: 100.0, route: LongPollRoute(path: "/split-stable/getUpdates")),
+                (share: 0.0, route: LongPollRoute(path: "/split-canary/getUpdates")),
+            ],
+        ),
+    )"#).await;
+
+    for user in 1..=5 {
+        push_from(port, user, user).await;
+    }
+    assert_eq!(update_ids(&collect_route(port, "/split-stable/getUpdates", 5).await), vec![1, 2, 3, 4, 5]);
+
+    assert_eq!(set_shares(port, "canary", json!([0.0, 100.0])).await.status(), 200);
+    for user in 1..=5 {
+        push_from(port, 10 + user, user).await;
+    }
+    assert_eq!(update_ids(&collect_route(port, "/split-canary/getUpdates", 5).await), vec![11, 12, 13, 14, 15]);
+    assert!(poll_route(port, "/split-stable/getUpdates", 0).await.is_empty());
+
+    assert_eq!(set_shares(port, "canary", json!([50.0])).await.status(), 400);
+    assert_eq!(set_shares(port, "canary", json!([50.0, 50.0])).await.status(), 200);
+
+    // one user keeps landing on one route
+    for update_id in 20..30 {
+        push_from(port, update_id, 42).await;
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let stable = poll_route(port, "/split-stable/getUpdates", 0).await.len();
+    let canary = poll_route(port, "/split-canary/getUpdates", 0).await.len();
+    assert!((stable, canary) == (10, 0) || (stable, canary) == (0, 10), "{} {}", stable, canary);
+
+    let routes: Value = reqwest::get(url(port, "/api/routes")).await.unwrap().json().await.unwrap();
+    assert_eq!(routes["options"]["shares"], json!([50.0, 50.0]));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn split_names_are_unique_and_freed_on_rollback() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        api: Some(ApiConfig(base_path: "/api")),
+        updates: [WebhookUpdate(path: "/unique/in")],
+        route: RoundRobinLB(routes: [
+            SplitLB(name: Some("taken"), routes: [(share: 1.0, route: LongPollRoute(path: "/unique-taken/getUpdates"))]),
+        ]),
+    )"#).await;
+
+    assert_eq!(add_split(port, "taken", "/unique-other/getUpdates").await.status(), 400);
+
+    // the path is served already, the split is dropped again
+    assert_eq!(add_split(port, "fresh", "/unique-taken/getUpdates").await.status(), 400);
+    assert_eq!(set_shares(port, "fresh", json!([1.0])).await.status(), 400);
+
+    assert_eq!(add_split(port, "fresh", "/unique-fresh/getUpdates").await.status(), 200);
+    assert_eq!(set_shares(port, "fresh", json!([2.0])).await.status(), 200);
+}
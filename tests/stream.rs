@@ -0,0 +1,122 @@
+mod common;
+
+use common::{push_update, spawn_tgin, url};
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+
+
+/// Reads `data:` payloads of SSE `update` events until `count` were received.
+async fn sse_updates(response: &mut reqwest::Response, count: usize) -> Vec<Value> {
+    let mut buffer = String::new();
+    let mut frames = Vec::new();
+
+    while frames.len() < count {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), response.chunk())
+            .await
+            .expect("no SSE event in time")
+            .unwrap()
+            .expect("SSE stream closed");
+        buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+        while let Some(end) = buffer.find("\n\n") {
+            let event: String = buffer.drain(..end + 2).collect();
+            if event.lines().any(|l| l == "event: update") {
+                let data = event.lines().find_map(|l| l.strip_prefix("data: ")).unwrap();
+                frames.push(serde_json::from_str(data).unwrap());
+            }
+        }
+    }
+    frames
+}
+
+async fn ws_frame(socket: &mut tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>) -> Value {
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("no websocket frame in time")
+            .unwrap()
+            .unwrap();
+        if let Message::Text(text) = message {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sse_redelivers_unacked_updates_on_reconnect() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        updates: [WebhookUpdate(path: "/sse/in")],
+        route: StreamRoute(path: "/sse-stream"),
+    )"#).await;
+    let client = reqwest::Client::new();
+    let sse_url = url(port, "/sse-stream/sse?consumer=bot-a");
+
+    let mut response = client.get(&sse_url).send().await.unwrap();
+    push_update(port, "/sse/in", json!({ "update_id": 1 })).await;
+    push_update(port, "/sse/in", json!({ "update_id": 2 })).await;
+
+    let frames = sse_updates(&mut response, 2).await;
+    let ids: Vec<i64> = frames.iter().map(|f| f["update"]["update_id"].as_i64().unwrap()).collect();
+    assert_eq!(ids, vec![1, 2]);
+
+    // ack only the first one, then drop the connection
+    let ack: Value = client.post(url(port, "/sse-stream/ack"))
+        .json(&json!({ "consumer": "bot-a", "ack": frames[0]["id"] }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(ack["ok"], true);
+    drop(response);
+
+    let mut response = client.get(&sse_url).send().await.unwrap();
+    let redelivered = sse_updates(&mut response, 1).await;
+    assert_eq!(redelivered[0]["id"], frames[1]["id"]);
+    assert_eq!(redelivered[0]["update"]["update_id"], 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn websocket_consumers_compete_for_updates() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        updates: [WebhookUpdate(path: "/ws/in")],
+        route: StreamRoute(path: "/ws-stream", prefetch: Some(1)),
+    )"#).await;
+
+    let ws_url = format!("ws://127.0.0.1:{}/ws-stream/ws", port);
+    let (mut first, _) = tokio_tungstenite::connect_async(format!("{}?consumer=first", ws_url)).await.unwrap();
+    let (mut second, _) = tokio_tungstenite::connect_async(format!("{}?consumer=second", ws_url)).await.unwrap();
+    assert_eq!(ws_frame(&mut first).await["consumer"], "first");
+    assert_eq!(ws_frame(&mut second).await["consumer"], "second");
+
+    for id in 1..=4 {
+        push_update(port, "/ws/in", json!({ "update_id": id })).await;
+    }
+
+    // with a prefetch of one, each consumer holds a single update until it acks
+    let mut held = Vec::new();
+    for socket in [&mut first, &mut second] {
+        held.push(ws_frame(socket).await);
+    }
+    for socket in [&mut first, &mut second] {
+        assert!(tokio::time::timeout(Duration::from_millis(300), socket.next()).await.is_err());
+    }
+
+    let mut seen: Vec<i64> = held.iter().map(|f| f["update"]["update_id"].as_i64().unwrap()).collect();
+    for (socket, frame) in [&mut first, &mut second].into_iter().zip(&held) {
+        socket.send(Message::Text(json!({ "ack": frame["id"] }).to_string())).await.unwrap();
+    }
+    for socket in [&mut first, &mut second] {
+        seen.push(ws_frame(socket).await["update"]["update_id"].as_i64().unwrap());
+    }
+
+    seen.sort();
+    assert_eq!(seen, vec![1, 2, 3, 4]);
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use common::spawn_tgin;
+
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::CertificateDer;
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls");
+
+
+fn fixture(name: &str) -> String {
+    format!("{}/{}", FIXTURES, name)
+}
+
+fn der(cert: &Path) -> Vec<u8> {
+    CertificateDer::from_pem_file(cert).unwrap().to_vec()
+}
+
+/// Leaf certificate the server presents for `host`.
+async fn served_cert(port: u16, host: &str) -> Vec<u8> {
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .tls_info(true)
+        .resolve(host, SocketAddr::from(([127, 0, 0, 1], port)))
+        .build()
+        .unwrap();
+    let response = client.get(format!("https://{}:{}/tls/getUpdates", host, port)).send().await.unwrap();
+    response.extensions()
+        .get::<reqwest::tls::TlsInfo>()
+        .and_then(|info| info.peer_certificate())
+        .unwrap()
+        .to_vec()
+}
+
+/// Copies the default pair into a fresh directory so the test can replace it.
+fn certs_dir(tag: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tgin-tls-{}-{}", tag, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::copy(fixture("default.crt"), dir.join("server.crt")).unwrap();
+    std::fs::copy(fixture("default.key"), dir.join("server.key")).unwrap();
+    dir
+}
+
+fn renew(dir: &Path) {
+    std::fs::copy(fixture("renewed.key"), dir.join("server.key")).unwrap();
+    std::fs::copy(fixture("renewed.crt"), dir.join("server.crt")).unwrap();
+}
+
+async fn wait_for_cert(port: u16, expected: &[u8]) {
+    for _ in 0..50 {
+        if served_cert(port, "default.test").await == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("certificate was not reloaded");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn certificate_is_picked_by_sni() {
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        ssl: Some(SslConfig(
+            cert: "{}",
+            key: "{}",
+            sni: [SniCertConfig(domains: ["alpha.test", "*.alpha.test"], cert: "{}", key: "{}")],
+        )),
+        updates: [],
+        route: LongPollRoute(path: "/tls/getUpdates"),
+    )"#, fixture("default.crt"), fixture("default.key"), fixture("alpha.crt"), fixture("alpha.key"))).await;
+
+    let default = der(Path::new(&fixture("default.crt")));
+    let alpha = der(Path::new(&fixture("alpha.crt")));
+
+    assert_eq!(served_cert(port, "alpha.test").await, alpha);
+    assert_eq!(served_cert(port, "bot.alpha.test").await, alpha);
+    assert_eq!(served_cert(port, "other.test").await, default);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn changed_files_are_reloaded() {
+    let dir = certs_dir("watch");
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        ssl: Some(SslConfig(cert: "{}", key: "{}", watch_every: Some(1))),
+        updates: [],
+        route: LongPollRoute(path: "/tls/getUpdates"),
+    )"#, dir.join("server.crt").display(), dir.join("server.key").display())).await;
+
+    assert_eq!(served_cert(port, "default.test").await, der(&dir.join("server.crt")));
+
+    // mtimes have a coarse resolution on some filesystems
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    renew(&dir);
+    wait_for_cert(port, &der(Path::new(&fixture("renewed.crt")))).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sighup_reloads_certificates() {
+    let dir = certs_dir("sighup");
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        ssl: Some(SslConfig(cert: "{}", key: "{}")),
+        updates: [],
+        route: LongPollRoute(path: "/tls/getUpdates"),
+    )"#, dir.join("server.crt").display(), dir.join("server.key").display())).await;
+
+    assert_eq!(served_cert(port, "default.test").await, der(&dir.join("server.crt")));
+
+    renew(&dir);
+    let status = std::process::Command::new("kill")
+        .args(["-HUP", &std::process::id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    wait_for_cert(port, &der(Path::new(&fixture("renewed.crt")))).await;
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::{collect_route, message_update, poll_route, push_update, spawn_tgin, update_ids};
+
+use serde_json::{json, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+fn message(update_id: i64, date: u64) -> Value {
+    message_update(update_id, json!({ "date": date, "text": "/start" }))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn expired_updates_are_rerouted_on_dequeue() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        updates: [WebhookUpdate(path: "/ttl/in")],
+        route: LongPollRoute(
+            path: "/bot/getUpdates",
+            ttl: Some(1),
+            expired_route: Some(LongPollRoute(path: "/expired/getUpdates")),
+        ),
+    )"#).await;
+
+    push_update(port, "/ttl/in", message(1, now())).await;
+    push_update(port, "/ttl/in", message(2, now())).await;
+    tokio::time::sleep(Duration::from_millis(1300)).await;
+    push_update(port, "/ttl/in", message(3, now())).await;
+
+    assert_eq!(update_ids(&collect_route(port, "/bot/getUpdates", 1).await), vec![3]);
+    assert_eq!(update_ids(&collect_route(port, "/expired/getUpdates", 2).await), vec![1, 2]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn message_date_ttl_drops_old_messages() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        updates: [WebhookUpdate(path: "/ttl/in")],
+        route: LongPollRoute(path: "/bot/getUpdates", ttl: Some(60), ttl_from: Date),
+    )"#).await;
+
+    push_update(port, "/ttl/in", message(1, now() - 3600)).await;
+    push_update(port, "/ttl/in", message(2, now())).await;
+    assert_eq!(update_ids(&collect_route(port, "/bot/getUpdates", 1).await), vec![2]);
+    assert!(poll_route(port, "/bot/getUpdates", 0).await.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sweeper_expires_updates_nobody_polls() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        updates: [WebhookUpdate(path: "/ttl/in")],
+        route: LongPollRoute(
+            path: "/bot/getUpdates",
+            ttl: Some(1),
+            sweep_every: Some(1),
+            expired_route: Some(LongPollRoute(path: "/expired/getUpdates")),
+        ),
+    )"#).await;
+
+    push_update(port, "/ttl/in", message(1, now())).await;
+    assert_eq!(update_ids(&collect_route(port, "/expired/getUpdates", 1).await), vec![1]);
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use common::telegram::MockTelegram;
+use common::{collect_route, poll_route, spawn_tgin, update_ids, url};
+
+use serde_json::{json, Value};
+
+const TOKEN: &str = "123456789:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+const NEW_TOKEN: &str = "987654321:BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBB";
+
+
+async fn updaters(port: u16) -> Vec<Value> {
+    let response: Value = reqwest::get(url(port, "/api/updates")).await.unwrap().json().await.unwrap();
+    response["result"].as_array().unwrap().clone()
+}
+
+async fn api_post(port: u16, path: &str, body: Value) -> (u16, Value) {
+    let response = reqwest::Client::new().post(url(port, path)).json(&body).send().await.unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+async fn deliver(port: u16, path: &str, id: u64) -> u16 {
+    reqwest::Client::new()
+        .post(url(port, path))
+        .json(&json!({ "update_id": id, "message": { "text": "hi" } }))
+        .send()
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+async fn spawn_with_poller(telegram: &MockTelegram) -> u16 {
+    spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        api: Some(ApiConfig(base_path: "/api")),
+        updates: [
+            LongPollUpdate(token: "{}", url: Some("{}"), timeout: Some(0), default_timeout_sleep: 10, error_timeout_sleep: 10),
+        ],
+        route: LongPollRoute(path: "/managed/getUpdates"),
+    )"#, TOKEN, telegram.method_url("getUpdates"))).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pollers_can_be_paused_and_resumed() {
+    let telegram = MockTelegram::start(TOKEN).await;
+    let port = spawn_with_poller(&telegram).await;
+
+    telegram.push_message(1, "before");
+    assert_eq!(update_ids(&collect_route(port, "/managed/getUpdates", 1).await), vec![1]);
+
+    let listed = updaters(port).await;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0]["id"], 0);
+    assert_eq!(listed[0]["state"], "running");
+    assert_eq!(listed[0]["status"]["offset"], 2);
+    assert!(listed[0]["status"]["last_success"].is_u64());
+    assert!(!listed[0]["name"].as_str().unwrap().contains(TOKEN));
+
+    assert_eq!(api_post(port, "/api/update/0/pause", json!({})).await.0, 200);
+    assert_eq!(updaters(port).await[0]["state"], "paused");
+    assert_eq!(api_post(port, "/api/update/0/pause", json!({})).await.0, 400);
+
+    telegram.push_message(1, "while paused");
+    assert!(poll_route(port, "/managed/getUpdates", 1).await.is_empty());
+
+    assert_eq!(api_post(port, "/api/update/0/resume", json!({})).await.0, 200);
+    assert_eq!(update_ids(&collect_route(port, "/managed/getUpdates", 1).await), vec![2]);
+    // the offset survived the pause
+    assert_eq!(telegram.get_updates_calls().last().unwrap().offset, Some(3));
+    assert_eq!(updaters(port).await[0]["state"], "running");
+
+    assert_eq!(api_post(port, "/api/update/7/resume", json!({})).await.0, 400);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn updaters_can_be_added_and_removed() {
+    let telegram = MockTelegram::start(TOKEN).await;
+    let new_bot = MockTelegram::start(NEW_TOKEN).await;
+    let port = spawn_with_poller(&telegram).await;
+
+    // a new bot token, only taken from the environment
+    let (status, rejected) = api_post(port, "/api/update", json!({ "type": "LongPollUpdate", "token": NEW_TOKEN })).await;
+    assert_eq!(status, 400);
+    assert!(rejected["description"].as_str().unwrap().contains("token"), "{}", rejected);
+    std::env::set_var("TGIN_NEW_BOT_TOKEN", NEW_TOKEN);
+    let (status, added) = api_post(port, "/api/update", json!({
+        "type": "LongPollUpdate",
+        "token": "${TGIN_NEW_BOT_TOKEN}",
+        "url": new_bot.method_url("getUpdates"),
+        "timeout": 0,
+        "default_timeout_sleep": 10,
+    })).await;
+    assert_eq!(status, 200, "{}", added);
+    assert_eq!(added["result"]["id"], 1);
+    new_bot.push_message(5, "from the new bot");
+    assert_eq!(update_ids(&collect_route(port, "/managed/getUpdates", 1).await), vec![1]);
+
+    // a new webhook path
+    let (status, added) = api_post(port, "/api/update", json!({ "type": "WebhookUpdate", "path": "/added/in" })).await;
+    assert_eq!(status, 200, "{}", added);
+    let webhook = added["result"]["id"].as_u64().unwrap();
+    assert_eq!(deliver(port, "/added/in", 100).await, 200);
+    assert_eq!(update_ids(&collect_route(port, "/managed/getUpdates", 1).await), vec![100]);
+
+    assert_eq!(api_post(port, &format!("/api/update/{}/pause", webhook), json!({})).await.0, 200);
+    assert_eq!(deliver(port, "/added/in", 101).await, 503);
+
+    let removed = reqwest::Client::new().delete(url(port, &format!("/api/update/{}", webhook))).send().await.unwrap();
+    assert_eq!(removed.status(), 200);
+    let ids: Vec<u64> = updaters(port).await.iter().map(|u| u["id"].as_u64().unwrap()).collect();
+    assert_eq!(ids, vec![0, 1]);
+
+    // the path is still taken, as are the ones of the config and the API
+    for path in ["/added/in", "/managed/getUpdates", "/api/updates"] {
+        let (status, rejected) = api_post(port, "/api/update", json!({ "type": "WebhookUpdate", "path": path })).await;
+        assert_eq!(status, 400);
+        assert!(rejected["description"].as_str().unwrap().contains("already taken"), "{}", rejected);
+    }
+    assert_eq!(api_post(port, "/api/update", json!({ "type": "WebhookUpdate", "path": "no-slash" })).await.0, 400);
+    assert_eq!(api_post(port, "/api/update", json!({ "type": "NoSuchUpdate" })).await.0, 400);
+    let (status, rejected) = api_post(port, "/api/update", json!({
+        "type": "WebhookUpdate",
+        "path": "/filtered/in",
+        "allowed_ips": ["not a network"],
+    })).await;
+    assert_eq!(status, 400);
+    assert!(rejected["description"].as_str().unwrap().contains("allowed_ips"), "{}", rejected);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::{spawn_tgin, url};
+
+use axum::{body::Bytes, extract::State, http::HeaderMap, routing::post, Router};
+use ring::hmac;
+use serde_json::{json, Value};
+
+use std::time::Duration;
+
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls");
+
+
+/// Records the headers and raw body of every delivery.
+async fn start_recorder() -> (String, mpsc::UnboundedReceiver<(HeaderMap, Bytes)>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let app = Router::new()
+        .route("/bot", post(|State(tx): State<mpsc::UnboundedSender<(HeaderMap, Bytes)>>, headers: HeaderMap, body: Bytes| async move {
+            let _ = tx.send((headers, body));
+        }))
+        .with_state(tx);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (format!("http://{}/bot", addr), rx)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn deliveries_carry_auth_headers_and_signature() {
+    let (sink, mut deliveries) = start_recorder().await;
+
+    let port = spawn_tgin(&format!(r#"(
+        server_port: Some({{port}}),
+        updates: [WebhookUpdate(path: "/signed/in")],
+        route: WebhookRoute(
+            url: "{}",
+            headers: {{"Authorization": "Bearer internal"}},
+            secret_token: Some("telegram-secret"),
+            signing_secret: Some("hmac-key"),
+            http: Some(HttpConfig(client_cert: Some("{}/alpha.crt"), client_key: Some("{}/alpha.key"))),
+        ),
+    )"#, sink, FIXTURES, FIXTURES)).await;
+
+    reqwest::Client::new()
+        .post(url(port, "/signed/in"))
+        .json(&json!({ "update_id": 7, "message": { "text": "hi" } }))
+        .send()
+        .await
+        .unwrap();
+
+    let (headers, body) = tokio::time::timeout(Duration::from_secs(5), deliveries.recv()).await.unwrap().unwrap();
+    let header = |name: &str| headers.get(name).unwrap().to_str().unwrap().to_string();
+
+    assert_eq!(header("authorization"), "Bearer internal");
+    assert_eq!(header("x-telegram-bot-api-secret-token"), "telegram-secret");
+    assert_eq!(header("content-type"), "application/json");
+    let update: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(update["update_id"], 7);
+
+    let timestamp = header("x-tgin-timestamp");
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    assert!(now.abs_diff(timestamp.parse().unwrap()) < 60);
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, b"hmac-key");
+    let mut signed = format!("{}.", timestamp).into_bytes();
+    signed.extend_from_slice(&body);
+    let expected: String = hmac::sign(&key, &signed).as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+    assert_eq!(header("x-tgin-signature"), format!("sha256={}", expected));
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use common::{collect_route, spawn_tgin, update_ids, url};
+
+use axum::Router;
+use serde_json::{json, Value};
+
+use std::time::Duration;
+
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+use tgin::base::Serverable;
+use tgin::update::base::Updater;
+use tgin::update::webhook::{WebhookUpdate, WhenFull};
+
+
+/// Serves `webhook` in front of a queue that holds a single update and is
+/// only read by the test.
+async fn serve(webhook: &WebhookUpdate) -> (u16, mpsc::Sender<Value>, mpsc::Receiver<Value>) {
+    let (tx, rx) = mpsc::channel(1);
+    let app = webhook.set_server(Router::new()).await.with_state(tx.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (port, tx, rx)
+}
+
+async fn deliver(port: u16, body: Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(url(port, "/in"))
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+fn update(id: u64) -> Value {
+    json!({ "update_id": id, "message": { "text": "hi" } })
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn full_queue_is_rejected_so_telegram_retries() {
+    let mut webhook = WebhookUpdate::new("/in".to_string());
+    webhook.set_when_full(WhenFull::Reject { status: 429, retry_after: Some(3) }).unwrap();
+    let (port, _tx, mut rx) = serve(&webhook).await;
+
+    assert_eq!(deliver(port, update(1)).await.status(), 200);
+    let rejected = deliver(port, update(2)).await;
+    assert_eq!(rejected.status(), 429);
+    assert_eq!(rejected.headers()["retry-after"], "3");
+
+    assert_eq!(rx.recv().await.unwrap()["update_id"], 1);
+    assert_eq!(deliver(port, update(2)).await.status(), 200);
+    assert_eq!(rx.recv().await.unwrap()["update_id"], 2);
+
+    assert!(webhook.set_when_full(WhenFull::Reject { status: 200, retry_after: None }).is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn waiting_requests_time_out() {
+    let mut webhook = WebhookUpdate::new("/in".to_string());
+    webhook.set_request_timeout(Duration::from_millis(200));
+    let (port, _tx, mut rx) = serve(&webhook).await;
+
+    assert_eq!(deliver(port, update(1)).await.status(), 200);
+    assert_eq!(deliver(port, update(2)).await.status(), 503);
+    assert_eq!(rx.recv().await.unwrap()["update_id"], 1);
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn spilled_updates_are_fed_back() {
+    let spill = std::env::temp_dir().join(format!("tgin-spill-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&spill);
+
+    let mut webhook = WebhookUpdate::new("/in".to_string());
+    webhook.set_when_full(WhenFull::Spill(spill.display().to_string())).unwrap();
+    let (port, tx, mut rx) = serve(&webhook).await;
+
+    for id in 1..=3 {
+        assert_eq!(deliver(port, update(id)).await.status(), 200);
+    }
+    assert_eq!(std::fs::read_to_string(&spill).unwrap().lines().count(), 2);
+
+    tokio::spawn(async move { webhook.start(tx).await });
+    let mut ids = Vec::new();
+    for _ in 0..3 {
+        let update = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+        ids.push(update["update_id"].as_u64().unwrap());
+    }
+    assert_eq!(ids, vec![1, 2, 3]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn interrupted_drain_resumes_where_it_stopped() {
+    let spill = std::env::temp_dir().join(format!("tgin-resume-{}.jsonl", std::process::id()));
+    let draining = format!("{}.draining", spill.display());
+    let lines: String = (1..=3).map(|id| format!("{}\n", update(id))).collect();
+    std::fs::write(&draining, &lines).unwrap();
+    // the previous run got as far as the first update
+    let first = lines.find('\n').unwrap() + 1;
+    std::fs::write(format!("{}.offset", draining), format!("{:020}", first)).unwrap();
+
+    let mut webhook = WebhookUpdate::new("/in".to_string());
+    webhook.set_when_full(WhenFull::Spill(spill.display().to_string())).unwrap();
+    let (_port, tx, mut rx) = serve(&webhook).await;
+
+    tokio::spawn(async move { webhook.start(tx).await });
+    let mut ids = Vec::new();
+    for _ in 0..2 {
+        let update = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+        ids.push(update["update_id"].as_u64().unwrap());
+    }
+    assert_eq!(ids, vec![2, 3]);
+    assert!(tokio::time::timeout(Duration::from_millis(300), rx.recv()).await.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn malformed_and_oversized_bodies_are_refused() {
+    let port = spawn_tgin(r#"(
+        server_port: Some({port}),
+        updates: [WebhookUpdate(path: "/checked/in", max_body_bytes: Some(256))],
+        route: LongPollRoute(path: "/checked/getUpdates"),
+    )"#).await;
+    let client = reqwest::Client::new();
+    let post = |body: String| client.post(url(port, "/checked/in")).header("Content-Type", "application/json").body(body).send();
+
+    assert_eq!(post("not json".to_string()).await.unwrap().status(), 400);
+    assert_eq!(post(json!({ "message": { "text": "hi" } }).to_string()).await.unwrap().status(), 400);
+    assert_eq!(post(json!({ "update_id": "7" }).to_string()).await.unwrap().status(), 400);
+    let large = json!({ "update_id": 8, "message": { "text": "x".repeat(512) } });
+    assert_eq!(post(large.to_string()).await.unwrap().status(), 413);
+    assert_eq!(post(update(9).to_string()).await.unwrap().status(), 200);
+
+    assert_eq!(update_ids(&collect_route(port, "/checked/getUpdates", 1).await), vec![9]);
+}